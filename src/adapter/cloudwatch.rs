@@ -0,0 +1,598 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use aws_sdk_cloudwatchlogs::types::{QueryStatus, ResultField};
+use aws_sdk_cloudwatchlogs::Client;
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+
+use super::AdapterError;
+use crate::pipeline::Observer;
+use crate::stats::{Group, GrpcFailures, Observation, StatusCategory};
+
+/// The field CloudWatch Logs Insights uses to report when an event occurred.
+const TIMESTAMP_FIELD: &str = "@timestamp";
+/// The field Logs Insights uses to identify each event, which it
+/// adds to every row whether or not the query projects it.
+const PTR_FIELD: &str = "@ptr";
+/// The format of the `@timestamp` field in Logs Insights query results.
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+/// The default name of the field holding the HTTP status code.
+const DEFAULT_STATUS_FIELD: &str = "status";
+/// The default name of the field identifying which deployment served the request.
+const DEFAULT_DEPLOYMENT_FIELD: &str = "deployment";
+/// The largest number of rows a single Logs Insights query may return.
+const MAX_QUERY_ROWS: i32 = 10_000;
+/// How long we wait between checks on a running query.
+const DEFAULT_RESULTS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How far behind the newest event we've seen each query starts by default,
+/// to pick up events that are ingested late or share its timestamp.
+const DEFAULT_LOOK_BACK: Duration = Duration::from_secs(120);
+
+/// A [CloudwatchLogsObserver] runs a CloudWatch Logs Insights query against
+/// a log group and converts each row into an [Observation]. Each call to
+/// [query](Observer::query) only returns events it hasn't returned before.
+///
+/// Events can arrive in the log group well after they occur, and many can
+/// share a timestamp, so each query looks back a little before the newest
+/// event seen so far. Events in that overlap are told apart by their `@ptr`,
+/// which we remember until they fall out of the look-back window.
+pub struct CloudwatchLogsObserver {
+    /// The AWS client for querying Cloudwatch Logs.
+    client: Client,
+    /// The name of the log group to query.
+    log_group: String,
+    /// The identifier the control deployment writes into its log lines.
+    control_id: String,
+    /// The identifier the canary deployment writes into its log lines.
+    experimental_id: String,
+    /// The name of the field holding the HTTP status code.
+    status_field: String,
+    /// The name of the field holding the deployment identifier.
+    deployment_field: String,
+    /// The name of the field holding the request latency in
+    /// milliseconds, if latency is being compared.
+    latency_field: Option<String>,
+    /// The name of the field holding the gRPC status code, if
+    /// the deployments serve gRPC.
+    grpc_status_field: Option<String>,
+    /// The gRPC codes that count as failures.
+    grpc_failures: GrpcFailures,
+    /// A user-provided Logs Insights query. When absent, we
+    /// generate one from the field names.
+    query: Option<String>,
+    /// How long we wait between checks on a running query.
+    poll_interval: Duration,
+    /// How far behind the newest event we've seen each query starts.
+    look_back: TimeDelta,
+    /// Only events that occur strictly after this moment are emitted.
+    start: DateTime<Utc>,
+    /// The timestamp of the newest event we've emitted so far.
+    last_seen: DateTime<Utc>,
+    /// The `@ptr` and timestamp of each event we've emitted
+    /// that's still inside the look-back window.
+    seen: HashMap<String, DateTime<Utc>>,
+    /// Called with the number of rows a query skipped because they
+    /// had no valid timestamp, whenever there are any.
+    on_skipped: Option<Box<dyn FnMut(usize) + Send + Sync>>,
+}
+
+/// The rows returned by one run of the query.
+#[derive(Debug, Default, PartialEq)]
+pub struct Fetched {
+    /// The events we hadn't seen before, as observations.
+    pub observations: Vec<Observation>,
+    /// The number of rows skipped because they had no valid timestamp.
+    pub skipped: usize,
+}
+
+impl CloudwatchLogsObserver {
+    /// Create a new observer for the given log group. Only events that
+    /// occur after the observer is created will be emitted.
+    pub fn new(
+        client: Client,
+        log_group: impl Into<String>,
+        control_id: impl Into<String>,
+        experimental_id: impl Into<String>,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            client,
+            log_group: log_group.into(),
+            control_id: control_id.into(),
+            experimental_id: experimental_id.into(),
+            status_field: DEFAULT_STATUS_FIELD.to_owned(),
+            deployment_field: DEFAULT_DEPLOYMENT_FIELD.to_owned(),
+            latency_field: None,
+            grpc_status_field: None,
+            grpc_failures: GrpcFailures::default(),
+            query: None,
+            poll_interval: DEFAULT_RESULTS_POLL_INTERVAL,
+            look_back: TimeDelta::from_std(DEFAULT_LOOK_BACK).expect("the look-back is small"),
+            start: now,
+            last_seen: now,
+            seen: HashMap::new(),
+            on_skipped: None,
+        }
+    }
+
+    /// Build a CloudWatch Logs client from the ambient AWS configuration.
+    /// If an endpoint URL is provided, requests are sent there instead
+    /// of to AWS, which is useful for testing against a local stub.
+    pub async fn client(endpoint_url: Option<&str>) -> Client {
+        let mut loader = aws_config::from_env();
+        if let Some(url) = endpoint_url {
+            loader = loader.endpoint_url(url);
+        }
+        Client::new(&loader.load().await)
+    }
+
+    /// Set the name of the field holding the HTTP status code.
+    pub fn with_status_field(mut self, field: impl Into<String>) -> Self {
+        self.status_field = field.into();
+        self
+    }
+
+    /// Set the name of the field holding the deployment identifier.
+    pub fn with_deployment_field(mut self, field: impl Into<String>) -> Self {
+        self.deployment_field = field.into();
+        self
+    }
+
+    /// Set the name of the field holding the request latency, in
+    /// milliseconds. Without one, observations carry no latency.
+    pub fn with_latency_field(mut self, field: impl Into<String>) -> Self {
+        self.latency_field = Some(field.into());
+        self
+    }
+
+    /// Set the name of the field holding the gRPC status code. Rows with a
+    /// gRPC status are classified by it instead of by their HTTP status,
+    /// which is almost always 200 for gRPC calls.
+    pub fn with_grpc_status_field(mut self, field: impl Into<String>) -> Self {
+        self.grpc_status_field = Some(field.into());
+        self
+    }
+
+    /// Set which gRPC codes count as failures.
+    pub fn with_grpc_failures(mut self, failures: GrpcFailures) -> Self {
+        self.grpc_failures = failures;
+        self
+    }
+
+    /// Replace the generated Logs Insights query with a custom one. The query
+    /// must project `@timestamp` along with the status and deployment fields,
+    /// and the latency and gRPC status fields if there are any.
+    pub fn with_query(mut self, query: impl Into<String>) -> Self {
+        self.query = Some(query.into());
+        self
+    }
+
+    /// Set how long we wait between checks on a running query.
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Set how far behind the newest event we've seen each query starts.
+    /// Events that arrive later than this after newer ones are missed.
+    pub fn with_look_back(mut self, look_back: Duration) -> Self {
+        self.look_back = TimeDelta::from_std(look_back).unwrap_or_else(|_| TimeDelta::max_value());
+        self
+    }
+
+    /// Call the given function with the number of rows skipped by a query
+    /// whenever some rows have no valid timestamp. Otherwise, they're
+    /// skipped silently.
+    pub fn with_skipped_rows(mut self, report: impl FnMut(usize) + Send + Sync + 'static) -> Self {
+        self.on_skipped = Some(Box::new(report));
+        self
+    }
+
+    /// Only emit events that occur strictly after the given moment.
+    pub fn starting_at(mut self, start: DateTime<Utc>) -> Self {
+        self.start = start;
+        self.last_seen = start;
+        self
+    }
+
+    /// Returns the Logs Insights query string to run.
+    fn query_string(&self) -> String {
+        match &self.query {
+            Some(query) => query.clone(),
+            None => {
+                let mut fields = format!("{}, {}", self.status_field, self.deployment_field);
+                for extra in [&self.latency_field, &self.grpc_status_field]
+                    .into_iter()
+                    .flatten()
+                {
+                    fields = format!("{fields}, {extra}");
+                }
+                format!(
+                    "fields {TIMESTAMP_FIELD}, {fields} | sort {TIMESTAMP_FIELD} asc | limit {MAX_QUERY_ROWS}"
+                )
+            }
+        }
+    }
+
+    /// Run the query over every event since a little before the newest one
+    /// we saw, and return the events we haven't seen as observations. Rows
+    /// without a valid timestamp are skipped and counted.
+    pub async fn fetch(&mut self) -> Result<Fetched, AdapterError> {
+        let end = Utc::now();
+        // • Logs Insights time ranges have a granularity of seconds, and
+        //   we look back before the newest event, so we'll see some events
+        //   a second time. These are filtered out below by their `@ptr`.
+        let horizon = self.horizon();
+        let query_id = self
+            .client
+            .start_query()
+            .log_group_name(&self.log_group)
+            .start_time(horizon.timestamp())
+            .end_time(end.timestamp())
+            .query_string(self.query_string())
+            .limit(MAX_QUERY_ROWS)
+            .send()
+            .await
+            .map_err(|err| AdapterError::StartQuery(Box::new(err)))?
+            .query_id
+            .ok_or(AdapterError::MissingQueryId)?;
+        let rows = self.await_results(query_id).await?;
+
+        let mut observations = Vec::with_capacity(rows.len());
+        let mut newest = self.last_seen;
+        let mut skipped = 0;
+        for row in rows {
+            let Ok(timestamp) = parse_timestamp(field(&row, TIMESTAMP_FIELD)) else {
+                skipped += 1;
+                continue;
+            };
+            if timestamp <= self.start || timestamp < horizon {
+                continue;
+            }
+            // • Without a `@ptr`, we can only tell the row is new by
+            //   its timestamp, as if there were no look-back.
+            let is_new = match field(&row, PTR_FIELD) {
+                Some(ptr) => self.seen.insert(ptr.to_owned(), timestamp).is_none(),
+                None => timestamp > self.last_seen,
+            };
+            if !is_new {
+                continue;
+            }
+            newest = newest.max(timestamp);
+            if let Some(observation) = self.parse_row(&row) {
+                observations.push(observation);
+            }
+        }
+        self.last_seen = newest;
+        let horizon = self.horizon();
+        self.seen.retain(|_, timestamp| *timestamp >= horizon);
+        Ok(Fetched {
+            observations,
+            skipped,
+        })
+    }
+
+    /// Returns the earliest moment an event we haven't seen may have.
+    fn horizon(&self) -> DateTime<Utc> {
+        self.last_seen
+            .checked_sub_signed(self.look_back)
+            .unwrap_or(DateTime::<Utc>::MIN_UTC)
+            .max(self.start)
+    }
+
+    /// Poll the query until it completes, then return its rows.
+    async fn await_results(&self, query_id: String) -> Result<Vec<Vec<ResultField>>, AdapterError> {
+        loop {
+            let output = self
+                .client
+                .get_query_results()
+                .query_id(&query_id)
+                .send()
+                .await
+                .map_err(|err| AdapterError::GetQueryResults {
+                    query_id: query_id.clone(),
+                    source: Box::new(err),
+                })?;
+            match output.status {
+                Some(QueryStatus::Complete) => return Ok(output.results.unwrap_or_default()),
+                Some(QueryStatus::Running | QueryStatus::Scheduled) | None => {
+                    tokio::time::sleep(self.poll_interval).await
+                }
+                Some(status) => {
+                    return Err(AdapterError::QueryIncomplete {
+                        query_id,
+                        status: status.as_str().to_owned(),
+                    })
+                }
+            }
+        }
+    }
+
+    /// Convert a row into an observation. Rows from other deployments, or
+    /// rows without a recognizable status code, are skipped. A row with a
+    /// recognizable gRPC status is classified by it; otherwise, we fall back
+    /// to the HTTP status. A missing or malformed latency leaves the
+    /// observation without one.
+    fn parse_row(&self, row: &[ResultField]) -> Option<Observation> {
+        let deployment = field(row, &self.deployment_field)?;
+        let group = if deployment == self.control_id {
+            Group::Control
+        } else if deployment == self.experimental_id {
+            Group::Experimental
+        } else {
+            return None;
+        };
+        let grpc_status = self
+            .grpc_status_field
+            .as_deref()
+            .and_then(|name| field(row, name))
+            .and_then(|value| value.parse().ok());
+        let observation = match grpc_status {
+            Some(code) => Observation::grpc(group, code, &self.grpc_failures),
+            None => {
+                let status = field(row, &self.status_field)?.trim().parse().ok()?;
+                let outcome = StatusCategory::try_from(status).ok()?;
+                Observation::new(group, outcome).with_status(status)
+            }
+        };
+        let latency = self
+            .latency_field
+            .as_deref()
+            .and_then(|name| field(row, name))
+            .and_then(|value| value.trim().parse::<f64>().ok())
+            .and_then(|millis| Duration::try_from_secs_f64(millis / 1000.0).ok());
+        Some(match latency {
+            Some(latency) => observation.with_latency(latency),
+            None => observation,
+        })
+    }
+}
+
+#[async_trait]
+impl Observer for CloudwatchLogsObserver {
+    type Item = Observation;
+    type Error = AdapterError;
+
+    async fn query(&mut self) -> Result<Vec<Self::Item>, Self::Error> {
+        let fetched = self.fetch().await?;
+        match &mut self.on_skipped {
+            Some(report) if fetched.skipped > 0 => report(fetched.skipped),
+            _ => {}
+        }
+        Ok(fetched.observations)
+    }
+}
+
+/// Returns the value of the named field in this row, if present.
+fn field<'a>(row: &'a [ResultField], name: &str) -> Option<&'a str> {
+    row.iter()
+        .find(|result| result.field() == Some(name))
+        .and_then(ResultField::value)
+}
+
+/// Parse a Logs Insights `@timestamp`, which is always in UTC.
+fn parse_timestamp(value: Option<&str>) -> Result<DateTime<Utc>, AdapterError> {
+    value
+        .and_then(|value| NaiveDateTime::parse_from_str(value, TIMESTAMP_FORMAT).ok())
+        .map(|timestamp| timestamp.and_utc())
+        .ok_or_else(|| AdapterError::Timestamp(value.map(str::to_owned)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use aws_sdk_cloudwatchlogs::config::{BehaviorVersion, Credentials, Region};
+    use aws_sdk_cloudwatchlogs::{Client, Config};
+    use chrono::{DateTime, TimeDelta, Utc};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use wiremock::matchers::{header, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::{CloudwatchLogsObserver, TIMESTAMP_FORMAT};
+    use crate::pipeline::Observer;
+    use crate::stats::{Group, GrpcCode, GrpcFailures, Observation, StatusCategory};
+
+    /// Build a client that sends its requests to the stub server.
+    fn stub_client(server: &MockServer) -> Client {
+        let config = Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::for_tests())
+            .endpoint_url(server.uri())
+            .build();
+        Client::from_conf(config)
+    }
+
+    /// Build a result row the way Logs Insights would return it,
+    /// with a `@ptr` that's unique to its values.
+    fn row(timestamp: DateTime<Utc>, status: &str, deployment: &str) -> serde_json::Value {
+        let timestamp = timestamp.format(TIMESTAMP_FORMAT).to_string();
+        json!([
+            { "field": "@timestamp", "value": timestamp },
+            { "field": "status", "value": status },
+            { "field": "deployment", "value": deployment },
+            { "field": "duration", "value": "12.5" },
+            { "field": "@ptr", "value": format!("{timestamp}/{status}/{deployment}") },
+        ])
+    }
+
+    /// Answer StartQuery and GetQueryResults requests on the stub server.
+    async fn mount_query(server: &MockServer, status: &str, rows: serde_json::Value) {
+        Mock::given(method("POST"))
+            .and(header("x-amz-target", "Logs_20140328.StartQuery"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "queryId": "abc" })))
+            .mount(server)
+            .await;
+        Mock::given(method("POST"))
+            .and(header("x-amz-target", "Logs_20140328.GetQueryResults"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "status": status, "results": rows })),
+            )
+            .mount(server)
+            .await;
+    }
+
+    /// Rows are parsed into observations, and rows we've already seen
+    /// are not emitted a second time.
+    #[tokio::test]
+    async fn emits_only_new_observations() {
+        let server = MockServer::start().await;
+        let start = Utc::now() - TimeDelta::minutes(5);
+        let rows = json!([
+            row(start + TimeDelta::seconds(1), "200", "v1"),
+            row(start + TimeDelta::seconds(2), "503", "v2"),
+            row(start + TimeDelta::seconds(3), "404", "v3"),
+            row(start + TimeDelta::seconds(4), "not-a-status", "v1"),
+        ]);
+        mount_query(&server, "Complete", rows).await;
+
+        let mut observer = CloudwatchLogsObserver::new(stub_client(&server), "api", "v1", "v2")
+            .starting_at(start)
+            .with_poll_interval(Duration::from_millis(1));
+        let observed = observer.fetch().await.unwrap().observations;
+        let expected = vec![
+            Observation::new(Group::Control, StatusCategory::_2XX).with_status(200),
+            Observation::new(Group::Experimental, StatusCategory::_5XX).with_status(503),
+        ];
+        assert_eq!(observed, expected);
+        // The stub returns the same rows again, but they're all stale now.
+        assert_eq!(observer.query().await.unwrap(), vec![]);
+    }
+
+    /// Events that share the newest timestamp we've seen, or that
+    /// arrive late, are still emitted exactly once.
+    #[tokio::test]
+    async fn emits_late_observations_once() {
+        let server = MockServer::start().await;
+        let start = Utc::now() - TimeDelta::minutes(5);
+        let first = row(start + TimeDelta::seconds(2), "200", "v1");
+        let tied = row(start + TimeDelta::seconds(2), "503", "v2");
+        let late = row(start + TimeDelta::seconds(1), "500", "v1");
+        Mock::given(method("POST"))
+            .and(header("x-amz-target", "Logs_20140328.StartQuery"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "queryId": "abc" })))
+            .mount(&server)
+            .await;
+        for rows in [json!([first]), json!([late, first, tied])] {
+            Mock::given(method("POST"))
+                .and(header("x-amz-target", "Logs_20140328.GetQueryResults"))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_json(json!({ "status": "Complete", "results": rows })),
+                )
+                .up_to_n_times(1)
+                .mount(&server)
+                .await;
+        }
+
+        let mut observer = CloudwatchLogsObserver::new(stub_client(&server), "api", "v1", "v2")
+            .starting_at(start)
+            .with_poll_interval(Duration::from_millis(1));
+        let observed = observer.fetch().await.unwrap().observations;
+        let expected =
+            vec![Observation::new(Group::Control, StatusCategory::_2XX).with_status(200)];
+        assert_eq!(observed, expected);
+        let observed = observer.fetch().await.unwrap().observations;
+        let expected = vec![
+            Observation::new(Group::Control, StatusCategory::_5XX).with_status(500),
+            Observation::new(Group::Experimental, StatusCategory::_5XX).with_status(503),
+        ];
+        assert_eq!(observed, expected);
+    }
+
+    /// A row without a valid timestamp is skipped rather
+    /// than failing the rest of the batch.
+    #[tokio::test]
+    async fn skips_malformed_rows() {
+        let server = MockServer::start().await;
+        let start = Utc::now() - TimeDelta::minutes(5);
+        let rows = json!([
+            [
+                { "field": "@timestamp", "value": "yesterday" },
+                { "field": "status", "value": "500" },
+                { "field": "deployment", "value": "v2" },
+            ],
+            row(start + TimeDelta::seconds(1), "200", "v2"),
+        ]);
+        mount_query(&server, "Complete", rows).await;
+
+        let mut observer = CloudwatchLogsObserver::new(stub_client(&server), "api", "v1", "v2")
+            .starting_at(start)
+            .with_poll_interval(Duration::from_millis(1));
+        let fetched = observer.fetch().await.unwrap();
+        let expected = Observation::new(Group::Experimental, StatusCategory::_2XX).with_status(200);
+        assert_eq!(fetched.observations, vec![expected]);
+        assert_eq!(fetched.skipped, 1);
+    }
+
+    /// When a latency field is configured, observations carry its value.
+    #[tokio::test]
+    async fn parses_latency_field() {
+        let server = MockServer::start().await;
+        let start = Utc::now() - TimeDelta::minutes(5);
+        let rows = json!([row(start + TimeDelta::seconds(1), "200", "v2")]);
+        mount_query(&server, "Complete", rows).await;
+
+        let mut observer = CloudwatchLogsObserver::new(stub_client(&server), "api", "v1", "v2")
+            .with_latency_field("duration")
+            .starting_at(start)
+            .with_poll_interval(Duration::from_millis(1));
+        let observed = observer.fetch().await.unwrap().observations;
+        let expected = Observation::new(Group::Experimental, StatusCategory::_2XX)
+            .with_status(200)
+            .with_latency(Duration::from_micros(12_500));
+        assert_eq!(observed, vec![expected]);
+    }
+
+    /// gRPC calls are classified by their gRPC status, not the HTTP 200
+    /// they're served with, unless the row doesn't have one.
+    #[tokio::test]
+    async fn parses_grpc_status_field() {
+        let server = MockServer::start().await;
+        let start = Utc::now() - TimeDelta::minutes(5);
+        let grpc = |seconds, code: &str| {
+            let mut row = row(start + TimeDelta::seconds(seconds), "200", "v2");
+            row.as_array_mut()
+                .unwrap()
+                .push(json!({ "field": "grpc_status", "value": code }));
+            row
+        };
+        let rows = json!([
+            grpc(1, "14"),
+            grpc(2, "5"),
+            grpc(3, "0"),
+            row(start + TimeDelta::seconds(4), "503", "v2"),
+        ]);
+        mount_query(&server, "Complete", rows).await;
+
+        let failures = GrpcFailures::new([GrpcCode::Unavailable]);
+        let mut observer = CloudwatchLogsObserver::new(stub_client(&server), "api", "v1", "v2")
+            .with_grpc_status_field("grpc_status")
+            .with_grpc_failures(failures.clone())
+            .starting_at(start)
+            .with_poll_interval(Duration::from_millis(1));
+        assert!(observer.query_string().contains("grpc_status"));
+        let observed = observer.fetch().await.unwrap().observations;
+        let expected = vec![
+            Observation::grpc(Group::Experimental, GrpcCode::Unavailable, &failures),
+            Observation::grpc(Group::Experimental, GrpcCode::NotFound, &failures),
+            Observation::grpc(Group::Experimental, GrpcCode::Ok, &failures),
+            Observation::new(Group::Experimental, StatusCategory::_5XX).with_status(503),
+        ];
+        assert_eq!(observed, expected);
+        assert_eq!(observed[1].outcome, StatusCategory::_4XX);
+    }
+
+    /// A query that ends without completing is reported as an error.
+    #[tokio::test]
+    async fn failed_query_is_an_error() {
+        let server = MockServer::start().await;
+        mount_query(&server, "Failed", json!([])).await;
+        let mut observer = CloudwatchLogsObserver::new(stub_client(&server), "api", "v1", "v2");
+        assert!(observer.fetch().await.is_err());
+    }
+}
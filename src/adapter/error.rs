@@ -0,0 +1,57 @@
+use aws_sdk_cloudwatchlogs::error::SdkError;
+use aws_sdk_cloudwatchlogs::operation::get_query_results::GetQueryResultsError;
+use aws_sdk_cloudwatchlogs::operation::start_query::StartQueryError;
+use miette::Diagnostic;
+use thiserror::Error;
+
+/// An [AdapterError] describes why an adapter failed to
+/// collect observations from the external system it watches.
+#[derive(Error, Diagnostic, Debug)]
+pub enum AdapterError {
+    /// CloudWatch Logs refused to start the Insights query.
+    #[error("failed to start the CloudWatch Logs Insights query")]
+    #[diagnostic(code(canary::adapter::start_query))]
+    StartQuery(#[source] Box<SdkError<StartQueryError>>),
+    /// CloudWatch Logs accepted the query, but we couldn't fetch its results.
+    #[error("failed to fetch results for CloudWatch Logs Insights query {query_id}")]
+    #[diagnostic(code(canary::adapter::get_query_results))]
+    GetQueryResults {
+        query_id: String,
+        #[source]
+        source: Box<SdkError<GetQueryResultsError>>,
+    },
+    /// CloudWatch Logs started the query, but did not tell us its identifier.
+    #[error("CloudWatch Logs did not return a query id")]
+    #[diagnostic(code(canary::adapter::missing_query_id))]
+    MissingQueryId,
+    /// The query stopped running before it completed, e.g. because
+    /// it was cancelled or timed out.
+    #[error("CloudWatch Logs Insights query {query_id} ended with status {status}")]
+    #[diagnostic(
+        code(canary::adapter::query_incomplete),
+        help("check the query string and the log group in the CloudWatch console")
+    )]
+    QueryIncomplete { query_id: String, status: String },
+    /// A row was missing its timestamp, or the timestamp could not be parsed.
+    #[error("unable to parse the timestamp {0:?} from a query result")]
+    #[diagnostic(
+        code(canary::adapter::timestamp),
+        help("make sure your query projects the @timestamp field")
+    )]
+    Timestamp(Option<String>),
+    /// We couldn't reach the Prometheus server, or it didn't answer
+    /// with a Prometheus HTTP API response.
+    #[error("failed to query Prometheus")]
+    #[diagnostic(
+        code(canary::adapter::prometheus_request),
+        help("check that the URL points at a Prometheus server, like http://localhost:9090")
+    )]
+    PrometheusRequest(#[source] reqwest::Error),
+    /// Prometheus refused to run the query.
+    #[error("Prometheus rejected the query ({error_type}): {error}")]
+    #[diagnostic(
+        code(canary::adapter::prometheus_query),
+        help("check the metric and label names")
+    )]
+    PrometheusQuery { error_type: String, error: String },
+}
@@ -1,30 +1,67 @@
-use aws_sdk_cloudwatchlogs as cloudwatchlogs;
+use async_trait::async_trait;
 use futures_core::stream::Stream;
 use tokio::sync::mpsc::Sender;
+use tokio::time::{interval, Duration, MissedTickBehavior};
 
+use crate::pipeline::Observer;
 use crate::stats::Observation;
 
+pub use cloudwatch::{CloudwatchLogsObserver, Fetched};
+pub use error::AdapterError;
+pub use prometheus::PrometheusObserver;
+
+/// An observer that runs CloudWatch Logs Insights queries.
+mod cloudwatch;
+/// The errors adapters report when they fail to collect observations.
+mod error;
+/// An observer that runs PromQL range queries against a request counter.
+mod prometheus;
+
+/// A [CloudwatchLogsAdapter] polls an [ObservationEmitter] on a background
+/// task and forwards what it emits over a channel.
+///
+/// Deploy uses [repeat_query](crate::pipeline::repeat_query) instead, which
+/// only queries when the consumer asks for more, and retries failed queries
+/// within an error budget. The adapter keeps polling on schedule however
+/// slowly its stream is consumed, buffering what it emits, and leaves
+/// deciding when to give up on errors to the consumer.
 pub struct CloudwatchLogsAdapter {
     /// The AWS client for querying Cloudwatch Logs.
     client: Box<dyn ObservationEmitter>,
-    outbox: Sender<Observation>,
+    outbox: Sender<Result<Observation, AdapterError>>,
+    /// How long to wait between calls to the emitter.
+    period: Duration,
 }
 
-// TODO: This must be a Boxed Async function since it needs
-//       to perform nonblocking network IO.
 /// An ObservationEmitter returns the next set of observations when queried.
 /// The list of Observations may be empty if no observations occurred in the window.
+#[async_trait]
 pub trait ObservationEmitter: Send + Sync {
-    fn emit_next(&mut self) -> Vec<Observation>;
+    async fn emit_next(&mut self) -> Result<Vec<Observation>, AdapterError>;
+}
+
+#[async_trait]
+impl ObservationEmitter for CloudwatchLogsObserver {
+    async fn emit_next(&mut self) -> Result<Vec<Observation>, AdapterError> {
+        self.query().await
+    }
 }
 
 impl CloudwatchLogsAdapter {
-    /// Create a new [CloudwatchLogsAdapter] using a provided AWS client.
-    pub fn new(client: impl ObservationEmitter + 'static) -> impl Stream<Item = Observation> {
+    /// Spawn a [CloudwatchLogsAdapter] polling the provided client, and
+    /// return the stream of what it emits. The client is polled once per
+    /// period until the returned stream is dropped. Errors are forwarded to
+    /// the stream, and polling continues after an error so the consumer can
+    /// decide whether to give up.
+    pub fn spawn(
+        client: impl ObservationEmitter + 'static,
+        period: Duration,
+    ) -> impl Stream<Item = Result<Observation, AdapterError>> {
         let (outbox, mut inbox) = tokio::sync::mpsc::channel(1024);
         let adapter = Self {
             client: Box::new(client),
             outbox,
+            period,
         };
         tokio::spawn(async move {
             adapter.run().await;
@@ -37,43 +74,121 @@ impl CloudwatchLogsAdapter {
     }
 
     async fn run(mut self) {
-        for item in self.client.emit_next() {
-            self.outbox.send(item).await.unwrap();
+        let mut timer = interval(self.period);
+        // • If a slow query makes us miss a tick, wait a full period
+        //   before the next one rather than firing in a burst.
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            // • Stop as soon as the receiver is dropped, even if we're
+            //   in the middle of waiting for the next tick.
+            tokio::select! {
+                _ = self.outbox.closed() => return,
+                _ = timer.tick() => (),
+            }
+            let items = match self.client.emit_next().await {
+                Ok(observations) => observations.into_iter().map(Ok).collect(),
+                Err(err) => vec![Err(err)],
+            };
+            for item in items {
+                // • Sending only fails once the receiver has been dropped,
+                //   so there's nobody left to poll for.
+                if self.outbox.send(item).await.is_err() {
+                    return;
+                }
+            }
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::adapter::Observation;
+    use std::sync::Arc;
+
+    use crate::adapter::{AdapterError, Observation};
     use crate::stats::{Group, StatusCategory};
 
     use super::{CloudwatchLogsAdapter, ObservationEmitter};
 
+    use async_trait::async_trait;
     use futures_util::pin_mut;
     use futures_util::StreamExt;
+    use tokio::time::Duration;
+
+    const PERIOD: Duration = Duration::from_millis(1);
 
     struct FakeObservationEmitter;
+    #[async_trait]
     impl ObservationEmitter for FakeObservationEmitter {
-        fn emit_next(&mut self) -> Vec<super::Observation> {
-            vec![Observation {
-                group: Group::Control,
-                outcome: StatusCategory::_2XX,
-            }]
+        async fn emit_next(&mut self) -> Result<Vec<super::Observation>, AdapterError> {
+            Ok(vec![Observation::new(Group::Control, StatusCategory::_2XX)])
+        }
+    }
+
+    /// This emitter fails on every other call.
+    #[derive(Default)]
+    struct FlakyObservationEmitter {
+        calls: usize,
+    }
+    #[async_trait]
+    impl ObservationEmitter for FlakyObservationEmitter {
+        async fn emit_next(&mut self) -> Result<Vec<super::Observation>, AdapterError> {
+            self.calls += 1;
+            if self.calls % 2 == 1 {
+                return Err(AdapterError::MissingQueryId);
+            }
+            FakeObservationEmitter.emit_next().await
+        }
+    }
+
+    /// This emitter holds a reference that's released when the adapter stops.
+    struct TrackedObservationEmitter(Arc<()>);
+    #[async_trait]
+    impl ObservationEmitter for TrackedObservationEmitter {
+        async fn emit_next(&mut self) -> Result<Vec<super::Observation>, AdapterError> {
+            FakeObservationEmitter.emit_next().await
         }
     }
 
     #[tokio::test]
     async fn smoke_adapter_works() {
-        let event_stream = CloudwatchLogsAdapter::new(FakeObservationEmitter);
+        let event_stream = CloudwatchLogsAdapter::spawn(FakeObservationEmitter, PERIOD);
         pin_mut!(event_stream);
         let mut count = 0;
-        while let Some(_) = event_stream.next().await {
-            println!("Yay!");
+        while let Some(item) = event_stream.next().await {
+            assert!(item.is_ok());
             count += 1;
             if count == 5 {
                 break;
             }
         }
+        assert_eq!(count, 5);
+    }
+
+    /// Errors are forwarded to the stream, and the adapter keeps polling.
+    #[tokio::test]
+    async fn errors_are_forwarded() {
+        let event_stream = CloudwatchLogsAdapter::spawn(FlakyObservationEmitter::default(), PERIOD);
+        pin_mut!(event_stream);
+        let items: Vec<_> = event_stream.take(4).collect().await;
+        assert!(matches!(items[0], Err(AdapterError::MissingQueryId)));
+        assert!(items[1].is_ok());
+        assert!(matches!(items[2], Err(AdapterError::MissingQueryId)));
+        assert!(items[3].is_ok());
+    }
+
+    /// Dropping the stream stops the background task, which drops the emitter.
+    #[tokio::test]
+    async fn stops_when_stream_is_dropped() {
+        let handle = Arc::new(());
+        let event_stream =
+            CloudwatchLogsAdapter::spawn(TrackedObservationEmitter(handle.clone()), PERIOD);
+        drop(event_stream);
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while Arc::strong_count(&handle) > 1 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("the adapter should stop once its stream is dropped");
     }
 }
@@ -0,0 +1,392 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::Deserialize;
+
+use super::AdapterError;
+use crate::pipeline::Observer;
+use crate::stats::{AggregatedObservation, Group, StatusCategory};
+
+/// The default counter of HTTP requests served.
+const DEFAULT_METRIC: &str = "http_requests_total";
+/// The default label identifying which deployment served the requests.
+const DEFAULT_VERSION_LABEL: &str = "version";
+/// The default label holding the HTTP status code.
+const DEFAULT_CODE_LABEL: &str = "code";
+/// By default, range queries return a sample every 15 seconds, which is
+/// also the most common scrape interval. Counter resets between two
+/// samples can't be told apart from a drop in traffic.
+const DEFAULT_STEP: Duration = Duration::from_secs(15);
+
+/// A [PrometheusObserver] runs PromQL range queries against the Prometheus
+/// HTTP API, and reports how much a request counter grew since the last
+/// query, per deployment and status code, as [AggregatedObservation]s.
+///
+/// Each series of the counter is tracked separately, e.g. per instance, so
+/// one instance restarting and resetting its counter to zero doesn't hide
+/// the requests the others served. Series that already exist on the first
+/// query only count requests served after it; series that appear later
+/// count every request they report.
+pub struct PrometheusObserver {
+    /// The client for the Prometheus HTTP API.
+    client: Client,
+    /// The base URL of the Prometheus server, e.g. `http://localhost:9090`.
+    url: String,
+    /// The identifier the control deployment exports as its version label.
+    control_id: String,
+    /// The identifier the canary deployment exports as its version label.
+    experimental_id: String,
+    /// The name of the request counter.
+    metric: String,
+    /// The label identifying the deployment.
+    version_label: String,
+    /// The label holding the HTTP status code.
+    code_label: String,
+    /// The resolution of the range queries.
+    step: Duration,
+    /// The end of the range we last queried. The next query starts here.
+    last_end: DateTime<Utc>,
+    /// The latest value of each series we've seen, by its labels.
+    counters: HashMap<BTreeMap<String, String>, f64>,
+    /// Whether a query has succeeded yet.
+    started: bool,
+}
+
+impl PrometheusObserver {
+    /// Create a new observer for the Prometheus server at the given URL.
+    /// Only requests served after the observer is created are counted.
+    pub fn new(
+        client: Client,
+        url: impl Into<String>,
+        control_id: impl Into<String>,
+        experimental_id: impl Into<String>,
+    ) -> Self {
+        Self {
+            client,
+            url: url.into(),
+            control_id: control_id.into(),
+            experimental_id: experimental_id.into(),
+            metric: DEFAULT_METRIC.to_owned(),
+            version_label: DEFAULT_VERSION_LABEL.to_owned(),
+            code_label: DEFAULT_CODE_LABEL.to_owned(),
+            step: DEFAULT_STEP,
+            last_end: Utc::now(),
+            counters: HashMap::new(),
+            started: false,
+        }
+    }
+
+    /// Set the name of the request counter.
+    pub fn with_metric(mut self, metric: impl Into<String>) -> Self {
+        self.metric = metric.into();
+        self
+    }
+
+    /// Set the label identifying which deployment served the requests.
+    pub fn with_version_label(mut self, label: impl Into<String>) -> Self {
+        self.version_label = label.into();
+        self
+    }
+
+    /// Set the label holding the HTTP status code.
+    pub fn with_code_label(mut self, label: impl Into<String>) -> Self {
+        self.code_label = label.into();
+        self
+    }
+
+    /// Set the resolution of the range queries. A finer step catches
+    /// counter resets that happen in quick succession.
+    pub fn with_step(mut self, step: Duration) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Only count requests served after the given moment.
+    pub fn starting_at(mut self, start: DateTime<Utc>) -> Self {
+        self.last_end = start;
+        self
+    }
+
+    /// Returns the PromQL query selecting both deployments' series.
+    fn query_string(&self) -> String {
+        let selector = |id: &str| {
+            let id = id.replace('\\', "\\\\").replace('"', "\\\"");
+            format!("{}{{{}=\"{id}\"}}", self.metric, self.version_label)
+        };
+        format!(
+            "{} or {}",
+            selector(&self.control_id),
+            selector(&self.experimental_id)
+        )
+    }
+
+    /// Query the counter over the range since the last query, and return
+    /// how many requests each group served with each status code.
+    pub async fn fetch(&mut self) -> Result<Vec<AggregatedObservation>, AdapterError> {
+        let end = Utc::now();
+        let timestamp =
+            |time: DateTime<Utc>| format!("{:.3}", time.timestamp_millis() as f64 / 1000.0);
+        let response: QueryResponse = self
+            .client
+            .get(format!(
+                "{}/api/v1/query_range",
+                self.url.trim_end_matches('/')
+            ))
+            .query(&[
+                ("query", self.query_string()),
+                ("start", timestamp(self.last_end)),
+                ("end", timestamp(end)),
+                ("step", format!("{}s", self.step.as_secs_f64())),
+            ])
+            .send()
+            .await
+            .map_err(AdapterError::PrometheusRequest)?
+            .json()
+            .await
+            .map_err(AdapterError::PrometheusRequest)?;
+        let series = match response {
+            QueryResponse {
+                data: Some(data), ..
+            } if response.status == "success" => data.result,
+            QueryResponse {
+                error_type, error, ..
+            } => {
+                return Err(AdapterError::PrometheusQuery {
+                    error_type: error_type.unwrap_or_default(),
+                    error: error.unwrap_or_default(),
+                })
+            }
+        };
+
+        let mut counts: BTreeMap<(Group, u16), f64> = BTreeMap::new();
+        for Series { metric, values } in series {
+            let Some(key) = self.classify(&metric) else {
+                continue;
+            };
+            // • A series we haven't seen before started from zero,
+            //   unless it was already counting before we started.
+            let previous = match self.counters.get(&metric) {
+                Some(value) => Some(*value),
+                None if self.started => Some(0.0),
+                None => None,
+            };
+            let values = values.iter().filter_map(|(_, value)| value.parse().ok());
+            let (increase, latest) = increase(previous, values);
+            if let Some(latest) = latest {
+                self.counters.insert(metric, latest);
+            }
+            *counts.entry(key).or_insert(0.0) += increase;
+        }
+        self.last_end = end;
+        self.started = true;
+        Ok(counts
+            .into_iter()
+            .filter_map(|((group, code), count)| {
+                let outcome = StatusCategory::try_from(code).ok()?;
+                let count = count.round() as u64;
+                (count > 0)
+                    .then(|| AggregatedObservation::new(group, outcome, count).with_status(code))
+            })
+            .collect())
+    }
+
+    /// Returns the group and status code of a series. Series from other
+    /// deployments, or without a recognizable status code, are skipped.
+    fn classify(&self, labels: &BTreeMap<String, String>) -> Option<(Group, u16)> {
+        let version = labels.get(&self.version_label)?;
+        let group = if *version == self.control_id {
+            Group::Control
+        } else if *version == self.experimental_id {
+            Group::Experimental
+        } else {
+            return None;
+        };
+        let code = labels.get(&self.code_label)?.trim().parse().ok()?;
+        StatusCategory::try_from(code).ok()?;
+        Some((group, code))
+    }
+}
+
+#[async_trait]
+impl Observer for PrometheusObserver {
+    type Item = AggregatedObservation;
+    type Error = AdapterError;
+
+    async fn query(&mut self) -> Result<Vec<Self::Item>, Self::Error> {
+        self.fetch().await
+    }
+}
+
+/// Returns how much a counter grew over its samples, starting from the
+/// previous value if there is one, along with its latest value. A counter
+/// only decreases when it's reset to zero, so after a drop, the new value
+/// is all growth.
+fn increase(previous: Option<f64>, values: impl Iterator<Item = f64>) -> (f64, Option<f64>) {
+    let mut total = 0.0;
+    let mut latest = previous;
+    for value in values {
+        if let Some(last) = latest {
+            total += if value < last { value } else { value - last };
+        }
+        latest = Some(value);
+    }
+    (total, latest)
+}
+
+/// The body of a Prometheus HTTP API response.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryResponse {
+    status: String,
+    data: Option<QueryData>,
+    error_type: Option<String>,
+    error: Option<String>,
+}
+
+/// The result of a range query, which is always a matrix.
+#[derive(Deserialize)]
+struct QueryData {
+    result: Vec<Series>,
+}
+
+/// One series of a range query: its labels, and its samples as
+/// (unix timestamp, value) pairs. Values are strings so that NaN
+/// and infinities survive JSON.
+#[derive(Deserialize)]
+struct Series {
+    metric: BTreeMap<String, String>,
+    values: Vec<(f64, String)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, Utc};
+    use pretty_assertions::assert_eq;
+    use reqwest::Client;
+    use serde_json::json;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::{increase, PrometheusObserver};
+    use crate::adapter::AdapterError;
+    use crate::pipeline::Observer;
+    use crate::stats::{AggregatedObservation, Group, StatusCategory};
+
+    /// Build a series of `http_requests_total` with the given samples.
+    fn series(instance: &str, version: &str, code: &str, values: &[u64]) -> serde_json::Value {
+        let values: Vec<_> = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| json!([1_700_000_000 + 15 * i, value.to_string()]))
+            .collect();
+        json!({
+            "metric": {
+                "__name__": "http_requests_total",
+                "instance": instance,
+                "version": version,
+                "code": code,
+            },
+            "values": values,
+        })
+    }
+
+    /// Answer the next range query on the stub server with these series.
+    async fn mount_result(server: &MockServer, result: Vec<serde_json::Value>) {
+        Mock::given(method("GET"))
+            .and(path("/api/v1/query_range"))
+            .and(query_param(
+                "query",
+                r#"http_requests_total{version="v1"} or http_requests_total{version="v2"}"#,
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "status": "success",
+                "data": { "resultType": "matrix", "result": result },
+            })))
+            .up_to_n_times(1)
+            .mount(server)
+            .await;
+    }
+
+    #[test]
+    fn counter_resets_are_growth() {
+        let values = [10.0, 15.0, 3.0, 5.0].into_iter();
+        assert_eq!(increase(None, values.clone()), (10.0, Some(5.0)));
+        assert_eq!(increase(Some(4.0), values), (16.0, Some(5.0)));
+        assert_eq!(increase(Some(4.0), std::iter::empty()), (0.0, Some(4.0)));
+    }
+
+    /// Counters are diffed between polls, per series, and summed into
+    /// one aggregate per group and status code.
+    #[tokio::test]
+    async fn diffs_counters_between_polls() {
+        let server = MockServer::start().await;
+        mount_result(
+            &server,
+            vec![
+                series("a", "v1", "200", &[100, 150]),
+                series("b", "v1", "200", &[40, 60]),
+                series("a", "v1", "500", &[10, 12]),
+                series("a", "v2", "200", &[50, 80]),
+                series("a", "v2", "503", &[5, 5]),
+                series("a", "v3", "500", &[0, 90]),
+                series("a", "v2", "teapot", &[0, 90]),
+            ],
+        )
+        .await;
+        // • Instance "b" restarts, and the canary reports its first 5XX.
+        mount_result(
+            &server,
+            vec![
+                series("a", "v1", "200", &[150, 170]),
+                series("b", "v1", "200", &[60, 8, 20]),
+                series("a", "v2", "200", &[80, 110]),
+                series("a", "v2", "500", &[3]),
+            ],
+        )
+        .await;
+
+        let start = Utc::now() - TimeDelta::minutes(1);
+        let mut observer =
+            PrometheusObserver::new(Client::new(), server.uri(), "v1", "v2").starting_at(start);
+        let expected = vec![
+            AggregatedObservation::new(Group::Control, StatusCategory::_2XX, 70).with_status(200),
+            AggregatedObservation::new(Group::Control, StatusCategory::_5XX, 2).with_status(500),
+            AggregatedObservation::new(Group::Experimental, StatusCategory::_2XX, 30)
+                .with_status(200),
+        ];
+        assert_eq!(observer.query().await.unwrap(), expected);
+        let expected = vec![
+            AggregatedObservation::new(Group::Control, StatusCategory::_2XX, 40).with_status(200),
+            AggregatedObservation::new(Group::Experimental, StatusCategory::_2XX, 30)
+                .with_status(200),
+            AggregatedObservation::new(Group::Experimental, StatusCategory::_5XX, 3)
+                .with_status(500),
+        ];
+        assert_eq!(observer.query().await.unwrap(), expected);
+    }
+
+    /// A query Prometheus rejects is reported as an error.
+    #[tokio::test]
+    async fn failed_query_is_an_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/query_range"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                "status": "error",
+                "errorType": "bad_data",
+                "error": "parse error",
+            })))
+            .mount(&server)
+            .await;
+        let mut observer = PrometheusObserver::new(Client::new(), server.uri(), "v1", "v2")
+            .with_metric("requests{");
+        let err = observer.fetch().await.unwrap_err();
+        assert!(
+            matches!(&err, AdapterError::PrometheusQuery { error_type, .. } if error_type == "bad_data"),
+            "{err:?}"
+        );
+    }
+}
@@ -1,10 +1,12 @@
+use std::process::ExitCode;
+
 use clap::{CommandFactory, Parser};
 use miette::Result;
 
 use canary::Flags;
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     // Parse the args provided to this process, including
     // commands and flags.
     let flags = Flags::parse();
@@ -14,7 +16,7 @@ async fn main() -> Result<()> {
 
 /// This function inspects the command that was provided and
 /// delegates to its entrypoint.
-async fn dispatch_command(flags: Flags) -> Result<()> {
+async fn dispatch_command(flags: Flags) -> Result<ExitCode> {
     match flags.cmd() {
         // No command was provided.
         None => empty_command(),
@@ -26,9 +28,9 @@ async fn dispatch_command(flags: Flags) -> Result<()> {
 
 /// When the CLI is run without any commands, we print
 /// the help text and exit successfully.
-fn empty_command() -> Result<()> {
+fn empty_command() -> Result<ExitCode> {
     Flags::command()
         .print_long_help()
         .expect("unable to print help message");
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
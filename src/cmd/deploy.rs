@@ -0,0 +1,913 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::builder::RangedU64ValueParser;
+use clap::{value_parser, Args};
+use miette::Result;
+use tokio::time::Duration;
+
+use crate::adapter::{CloudwatchLogsObserver, PrometheusObserver};
+use crate::config::{ConfigError, DeployConfig, ObserverConfig, ShifterConfig};
+use crate::pipeline::{batch_observations, repeat_query, RetryPolicy, DEFAULT_BATCH_SIZE};
+use crate::promotion::{
+    drive, DriveEvent, EngineFactory, Promotion, PromotionPlan, PromotionState,
+};
+use crate::shifter::{AlbRuleShifter, LambdaAliasShifter, ManualShifter, TrafficShifter};
+use crate::stats::{
+    Alpha, BayesianEngine, ChiSquareEngine, Correction, Direction, EngineKind, GrpcCode,
+    GrpcFailures, LatencyEngine, LatencyTest, ProportionEngine, SequentialEngine, StatusCategory,
+    StatusCodeSets, TestSelection, VerdictPolicy, DEFAULT_ALPHA_CUTOFF, DEFAULT_EQUIVALENCE_MARGIN,
+    DEFAULT_LOSS_THRESHOLD, DEFAULT_MARGIN, DEFAULT_MIN_EFFECT_SIZE, DEFAULT_MIN_GROUP_SIZE,
+    DEFAULT_ROLLBACK_THRESHOLD,
+};
+
+/// By default, we query for new observations once a minute.
+const DEFAULT_POLLING_INTERVAL: u64 = 60;
+/// By default, we give up on the canary after an hour.
+const DEFAULT_TIMEOUT: u64 = 3600;
+/// By default, each stage requires this many observations before it passes.
+const DEFAULT_MIN_SAMPLE_SIZE: usize = 1000;
+/// The default names of the fields holding the status code and deployment.
+const DEFAULT_STATUS_FIELD: &str = "status";
+const DEFAULT_DEPLOYMENT_FIELD: &str = "deployment";
+
+/// Observe a canary deployment and decide whether to promote it or roll it back.
+/// Every setting may be provided in the config file; flags take precedence.
+#[derive(Args, Clone)]
+pub struct Deploy {
+    /// The config file describing the deployment.
+    /// [default: canary.toml, if it exists]
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// The CloudWatch log group both deployments write their access logs to.
+    #[arg(long)]
+    log_group: Option<String>,
+
+    /// Observe a request counter in the Prometheus server at this URL,
+    /// e.g. http://localhost:9090, instead of CloudWatch Logs.
+    #[arg(long, conflicts_with = "log_group")]
+    prometheus_url: Option<String>,
+
+    /// The deployment identifier of the current, stable deployment.
+    #[arg(long)]
+    control: Option<String>,
+
+    /// The deployment identifier of the canary deployment.
+    #[arg(long)]
+    canary: Option<String>,
+
+    /// The log field holding the HTTP status code. [default: status]
+    #[arg(long)]
+    status_field: Option<String>,
+
+    /// The log field holding the deployment identifier. [default: deployment]
+    #[arg(long)]
+    deployment_field: Option<String>,
+
+    /// The log field holding the request latency in milliseconds.
+    /// When set, latency regressions fail a stage too.
+    #[arg(long)]
+    latency_field: Option<String>,
+
+    /// The log field holding the gRPC status code. When set, gRPC calls
+    /// are classified by their status rather than their HTTP status.
+    #[arg(long)]
+    grpc_status_field: Option<String>,
+
+    /// The gRPC codes that count as failures, separated by commas, e.g.
+    /// UNAVAILABLE,INTERNAL. [default: UNKNOWN,DEADLINE_EXCEEDED,
+    /// UNIMPLEMENTED,INTERNAL,UNAVAILABLE,DATA_LOSS]
+    #[arg(long, value_delimiter = ',')]
+    grpc_failure_codes: Option<Vec<GrpcCode>>,
+
+    /// The test used to compare latencies. [default: mann-whitney]
+    #[arg(long, value_enum)]
+    latency_test: Option<LatencyTest>,
+
+    /// The smallest latency regression, on the latency test's own
+    /// scale, that fails a stage. [default: 0.05]
+    #[arg(long, value_parser = fraction(0.0, 1.0))]
+    min_effect_size: Option<f64>,
+
+    /// Also fail a stage if the canary's p50, p90 or p99 latency is slower
+    /// than the control group's by more than this fraction, e.g. 0.2.
+    #[arg(long, value_parser = positive)]
+    percentile_tolerance: Option<f64>,
+
+    /// A custom Logs Insights query. It must project @timestamp
+    /// and the status, deployment and latency fields.
+    #[arg(long)]
+    query: Option<String>,
+
+    /// The decision engine comparing the groups' error rates. [default: chi-square]
+    #[arg(long, value_enum)]
+    engine: Option<EngineKind>,
+
+    /// The significance level of the statistical test. [default: 0.05]
+    #[arg(long)]
+    alpha: Option<Alpha>,
+
+    /// How the significance level is shared between the error rate and
+    /// latency tests, when latencies are compared. [default: holm]
+    #[arg(long, value_enum)]
+    correction: Option<Correction>,
+
+    /// The number of observations each group needs before
+    /// the error rates are compared. [default: 30]
+    #[arg(long, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    min_group_size: Option<usize>,
+
+    /// How the chi-square engine computes its p-value. By default, it falls
+    /// back to an exact test when expected counts are small. [default: auto]
+    #[arg(long, value_enum)]
+    test_method: Option<TestSelection>,
+
+    /// Exact status codes the chi-square engine tests apart from their
+    /// category, separated by commas. Repeat the flag for each set, e.g.
+    /// --status-code-set 429 --status-code-set 502,503,504. A canary that
+    /// serves a set of non-5XX codes significantly more often is rolled back.
+    #[arg(long = "status-code-set", value_parser = parse_code_set)]
+    status_code_sets: Option<Vec<Vec<u16>>>,
+
+    /// With the Bayesian engine, only count the canary as worse if its 5XX
+    /// rate exceeds the control group's by more than this. [default: 0]
+    #[arg(long, value_parser = fraction(0.0, 1.0))]
+    margin: Option<f64>,
+
+    /// With the Bayesian engine, roll back once the probability that
+    /// the canary is worse exceeds this. [default: 0.95]
+    #[arg(long, value_parser = fraction(0.5, 1.0))]
+    rollback_threshold: Option<f64>,
+
+    /// With the Bayesian engine, pass a stage early once the expected
+    /// loss of promoting the canary falls below this. [default: 0.001]
+    #[arg(long, value_parser = fraction(0.0, 1.0))]
+    loss_threshold: Option<f64>,
+
+    /// The largest number of items collected before we recompute
+    /// statistical significance. Each request counts as an item, except
+    /// with Prometheus, whose items are per-query counts standing for many
+    /// requests each. [default: 512]
+    #[arg(long, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    batch_size: Option<usize>,
+
+    /// How often to query for new observations, in seconds. [default: 60]
+    #[arg(long, value_parser = value_parser!(u64).range(1..))]
+    polling_interval: Option<u64>,
+
+    /// The number of observations each stage requires before
+    /// the canary can pass or fail it. [default: 1000]
+    #[arg(long)]
+    min_sample_size: Option<usize>,
+
+    /// How long to observe the canary before giving up, in seconds. [default: 3600]
+    #[arg(long, value_parser = value_parser!(u64).range(1..))]
+    timeout: Option<u64>,
+
+    /// Give up, reporting the deployment as inconclusive, after
+    /// this many consecutive queries fail. [default: 5]
+    #[arg(long, value_parser = value_parser!(u32).range(1..))]
+    max_query_failures: Option<u32>,
+
+    /// Test each stage with an always-valid sequential test after every
+    /// batch, rolling back a failing canary as soon as it's detected.
+    #[arg(long)]
+    sequential: bool,
+
+    /// With --sequential, pass a stage early once the canary's 5XX rate is
+    /// confidently less than this much higher than the control group's.
+    /// [default: 0.005]
+    #[arg(long, value_parser = fraction(0.0, 1.0))]
+    equivalence_margin: Option<f64>,
+
+    /// Send CloudWatch Logs requests to this URL instead of AWS.
+    #[arg(long)]
+    endpoint_url: Option<String>,
+}
+
+/// The [Settings] for a deployment, after merging the flags over the config file.
+#[derive(Debug, PartialEq, Clone)]
+struct Settings {
+    source: Source,
+    control: String,
+    canary: String,
+    status_field: String,
+    deployment_field: String,
+    latency_field: Option<String>,
+    grpc_status_field: Option<String>,
+    grpc_failures: GrpcFailures,
+    latency_test: LatencyTest,
+    min_effect_size: f64,
+    percentile_tolerance: Option<f64>,
+    query: Option<String>,
+    endpoint_url: Option<String>,
+    engine: EngineKind,
+    alpha: Alpha,
+    correction: Correction,
+    min_group_size: usize,
+    test_method: TestSelection,
+    status_code_sets: StatusCodeSets,
+    margin: f64,
+    rollback_threshold: f64,
+    loss_threshold: f64,
+    batch_size: usize,
+    polling_interval: Duration,
+    min_sample_size: usize,
+    timeout: Duration,
+    max_query_failures: u32,
+    sequential: bool,
+    equivalence_margin: f64,
+    promotion_steps: Option<Vec<u8>>,
+    shifter: Option<ShifterConfig>,
+}
+
+/// The [Source] of observations, after merging the flags over the config file.
+#[derive(Debug, PartialEq, Clone)]
+enum Source {
+    /// Run Logs Insights queries against a log group.
+    CloudwatchLogs { log_group: String },
+    /// Diff a request counter in Prometheus.
+    Prometheus {
+        url: String,
+        metric: Option<String>,
+        version_label: Option<String>,
+        code_label: Option<String>,
+    },
+}
+
+impl Deploy {
+    /// Merge the flags over the values in the config file, falling
+    /// back to defaults for any setting provided by neither.
+    fn settings(self, config: DeployConfig) -> Result<Settings, ConfigError> {
+        let (log_group, query, endpoint_url, prometheus) = match config.observer {
+            Some(ObserverConfig::CloudwatchLogs {
+                log_group,
+                query,
+                endpoint_url,
+            }) => (log_group, query, endpoint_url, None),
+            Some(ObserverConfig::Prometheus {
+                url,
+                metric,
+                version_label,
+                code_label,
+            }) => (
+                None,
+                None,
+                None,
+                Some((url, metric, version_label, code_label)),
+            ),
+            None => (None, None, None, None),
+        };
+        // • Either flag selects its observer, even if the config
+        //   file describes the other one.
+        let source = match (self.log_group, self.prometheus_url, prometheus) {
+            (Some(log_group), _, _) => Source::CloudwatchLogs { log_group },
+            (None, url, Some((config_url, metric, version_label, code_label))) => {
+                Source::Prometheus {
+                    url: url.or(config_url).ok_or(ConfigError::Missing {
+                        key: "observer.url",
+                        flag: "--prometheus-url",
+                    })?,
+                    metric,
+                    version_label,
+                    code_label,
+                }
+            }
+            (None, Some(url), None) => Source::Prometheus {
+                url,
+                metric: None,
+                version_label: None,
+                code_label: None,
+            },
+            (None, None, None) => Source::CloudwatchLogs {
+                log_group: log_group.ok_or(ConfigError::Missing {
+                    key: "observer.log-group",
+                    flag: "--log-group",
+                })?,
+            },
+        };
+        // • Only the chi-square engine has a sequential test, so
+        //   asking for one from another engine is a mistake.
+        let engine = self.engine.or(config.engine).unwrap_or_default();
+        let sequential = self.sequential || config.sequential.unwrap_or(false);
+        if sequential && engine != EngineKind::ChiSquare {
+            return Err(ConfigError::Sequential {
+                engine: engine.name(),
+            });
+        }
+        let mapping = config.mapping;
+        Ok(Settings {
+            source,
+            control: self
+                .control
+                .or(mapping.control)
+                .ok_or(ConfigError::Missing {
+                    key: "mapping.control",
+                    flag: "--control",
+                })?,
+            canary: self.canary.or(mapping.canary).ok_or(ConfigError::Missing {
+                key: "mapping.canary",
+                flag: "--canary",
+            })?,
+            status_field: self
+                .status_field
+                .or(mapping.status_field)
+                .unwrap_or_else(|| DEFAULT_STATUS_FIELD.to_owned()),
+            deployment_field: self
+                .deployment_field
+                .or(mapping.deployment_field)
+                .unwrap_or_else(|| DEFAULT_DEPLOYMENT_FIELD.to_owned()),
+            latency_field: self.latency_field.or(mapping.latency_field),
+            grpc_status_field: self.grpc_status_field.or(mapping.grpc_status_field),
+            grpc_failures: self
+                .grpc_failure_codes
+                .or(mapping.grpc_failure_codes)
+                .map_or_else(GrpcFailures::default, GrpcFailures::new),
+            latency_test: self
+                .latency_test
+                .or(config.latency_test)
+                .unwrap_or_default(),
+            min_effect_size: self
+                .min_effect_size
+                .or(config.min_effect_size)
+                .unwrap_or(DEFAULT_MIN_EFFECT_SIZE),
+            percentile_tolerance: self.percentile_tolerance.or(config.percentile_tolerance),
+            query: self.query.or(query),
+            endpoint_url: self.endpoint_url.or(endpoint_url),
+            engine,
+            alpha: self.alpha.or(config.alpha).unwrap_or(DEFAULT_ALPHA_CUTOFF),
+            correction: self
+                .correction
+                .or(config.correction)
+                .unwrap_or(Correction::Holm),
+            min_group_size: self
+                .min_group_size
+                .or(config.min_group_size)
+                .unwrap_or(DEFAULT_MIN_GROUP_SIZE),
+            test_method: self.test_method.or(config.test_method).unwrap_or_default(),
+            status_code_sets: match self.status_code_sets {
+                Some(sets) => StatusCodeSets::new(sets)?,
+                None => config.status_code_sets.unwrap_or_default(),
+            },
+            margin: self.margin.or(config.margin).unwrap_or(DEFAULT_MARGIN),
+            rollback_threshold: self
+                .rollback_threshold
+                .or(config.rollback_threshold)
+                .unwrap_or(DEFAULT_ROLLBACK_THRESHOLD),
+            loss_threshold: self
+                .loss_threshold
+                .or(config.loss_threshold)
+                .unwrap_or(DEFAULT_LOSS_THRESHOLD),
+            batch_size: self
+                .batch_size
+                .or(config.batch_size)
+                .unwrap_or(DEFAULT_BATCH_SIZE),
+            polling_interval: Duration::from_secs(
+                self.polling_interval
+                    .or(config.polling_interval)
+                    .unwrap_or(DEFAULT_POLLING_INTERVAL),
+            ),
+            min_sample_size: self
+                .min_sample_size
+                .or(config.min_sample_size)
+                .unwrap_or(DEFAULT_MIN_SAMPLE_SIZE),
+            timeout: Duration::from_secs(
+                self.timeout.or(config.timeout).unwrap_or(DEFAULT_TIMEOUT),
+            ),
+            max_query_failures: self
+                .max_query_failures
+                .or(config.max_query_failures)
+                .unwrap_or(RetryPolicy::default().max_consecutive_failures),
+            sequential,
+            equivalence_margin: self
+                .equivalence_margin
+                .or(config.equivalence_margin)
+                .unwrap_or(DEFAULT_EQUIVALENCE_MARGIN),
+            promotion_steps: config.promotion_steps,
+            shifter: config.shifter,
+        })
+    }
+}
+
+/// Parse one set of status codes, separated by commas.
+pub(super) fn parse_code_set(value: &str) -> Result<Vec<u16>, String> {
+    value
+        .split(',')
+        .map(|code| {
+            let code = code
+                .trim()
+                .parse::<u16>()
+                .map_err(|_| format!("{code:?} isn't a number"))?;
+            StatusCategory::try_from(code).map_err(|err| err.to_string())?;
+            Ok(code)
+        })
+        .collect()
+}
+
+/// Returns a parser for numbers at least `start` and less than `end`,
+/// so flags are held to the same bounds as the config file.
+fn fraction(start: f64, end: f64) -> impl Fn(&str) -> Result<f64, String> + Clone {
+    move |value| {
+        let number = parse_number(value)?;
+        if (start..end).contains(&number) {
+            Ok(number)
+        } else {
+            Err(format!("must be at least {start} and less than {end}"))
+        }
+    }
+}
+
+/// Parse a number greater than zero.
+fn positive(value: &str) -> Result<f64, String> {
+    let number = parse_number(value)?;
+    if number > 0.0 {
+        Ok(number)
+    } else {
+        Err("must be greater than zero".to_owned())
+    }
+}
+
+/// Parse a number, rejecting NaN, which no range contains.
+fn parse_number(value: &str) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|number| !number.is_nan())
+        .ok_or_else(|| format!("{value:?} isn't a number"))
+}
+
+/// The [Verdict] is the final decision about the canary.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Verdict {
+    /// The canary is no different from the control group and can replace it.
+    Promote,
+    /// The canary performs worse than the control group.
+    Rollback,
+    /// We couldn't collect enough observations to decide either way,
+    /// e.g. because the observer kept failing.
+    Inconclusive,
+}
+
+impl From<PromotionState> for Verdict {
+    fn from(state: PromotionState) -> Self {
+        match state {
+            PromotionState::Promoted => Self::Promote,
+            PromotionState::RolledBack => Self::Rollback,
+            PromotionState::Observing { .. } | PromotionState::Abandoned => Self::Inconclusive,
+        }
+    }
+}
+
+impl Verdict {
+    /// The process exit code used to report this verdict.
+    pub fn exit_code(self) -> ExitCode {
+        match self {
+            Self::Promote => ExitCode::SUCCESS,
+            Self::Rollback => ExitCode::from(2),
+            Self::Inconclusive => ExitCode::from(3),
+        }
+    }
+}
+
+impl Deploy {
+    /// Walk the canary through its promotion plan, observing each stage
+    /// until it passes or fails, or until we run out of time.
+    pub async fn dispatch(self) -> Result<ExitCode> {
+        let config = DeployConfig::load(self.config.as_deref())?;
+        let settings = self.settings(config)?;
+        let policy = RetryPolicy {
+            max_consecutive_failures: settings.max_query_failures,
+            ..RetryPolicy::default()
+        };
+        let (interval, batch_size) = (settings.polling_interval, settings.batch_size);
+        let plan = match &settings.promotion_steps {
+            Some(steps) => PromotionPlan::from_steps(steps, settings.min_sample_size)?,
+            None => PromotionPlan::single_stage(settings.min_sample_size),
+        };
+        let mut shifter: Box<dyn TrafficShifter> = match settings.shifter {
+            Some(ShifterConfig::LambdaAlias {
+                function_name,
+                alias,
+                baseline_version,
+                canary_version,
+                endpoint_url,
+            }) => Box::new(LambdaAliasShifter::new(
+                LambdaAliasShifter::client(endpoint_url.as_deref()).await,
+                function_name,
+                alias,
+                baseline_version,
+                canary_version,
+            )),
+            Some(ShifterConfig::AlbListenerRule {
+                rule_arn,
+                baseline_target_group,
+                canary_target_group,
+                endpoint_url,
+            }) => Box::new(AlbRuleShifter::new(
+                AlbRuleShifter::client(endpoint_url.as_deref()).await,
+                rule_arn,
+                baseline_target_group,
+                canary_target_group,
+            )),
+            None => Box::new(ManualShifter),
+        };
+        let (alpha, min_group_size) = (settings.alpha, settings.min_group_size);
+        let errors: EngineFactory = match settings.engine {
+            EngineKind::ChiSquare if settings.sequential => {
+                let engine = SequentialEngine::new()
+                    .with_alpha_cutoff(alpha)
+                    .with_min_group_size(min_group_size)
+                    .with_code_sets(settings.status_code_sets)
+                    .with_equivalence_margin(settings.equivalence_margin)?;
+                Box::new(move || Box::new(engine.clone()))
+            }
+            EngineKind::ChiSquare => {
+                let engine = ChiSquareEngine::new()
+                    .with_alpha_cutoff(alpha)
+                    .with_min_group_size(min_group_size)
+                    .with_test_selection(settings.test_method)
+                    .with_code_sets(settings.status_code_sets);
+                Box::new(move || Box::new(engine.clone()))
+            }
+            EngineKind::Bayesian => {
+                let engine = BayesianEngine::new()
+                    .with_alpha_cutoff(alpha)
+                    .with_margin(settings.margin)?
+                    .with_rollback_threshold(settings.rollback_threshold)?
+                    .with_loss_threshold(settings.loss_threshold)?;
+                Box::new(move || Box::new(engine.clone()))
+            }
+            EngineKind::ZTest => {
+                let engine = ProportionEngine::new()
+                    .with_alpha_cutoff(alpha)
+                    .with_min_group_size(min_group_size);
+                Box::new(move || Box::new(engine.clone()))
+            }
+        };
+        let prometheus = matches!(settings.source, Source::Prometheus { .. });
+        let latency = match &settings.latency_field {
+            Some(_) if prometheus => {
+                eprintln!(
+                    "warning: Prometheus counters don't record latencies, so they won't be compared"
+                );
+                None
+            }
+            Some(_) => {
+                let mut latency = LatencyEngine::new(settings.latency_test)
+                    .with_alpha_cutoff(alpha)
+                    .with_min_effect_size(settings.min_effect_size);
+                if let Some(tolerance) = settings.percentile_tolerance {
+                    latency = latency.with_percentile_tolerance(tolerance);
+                }
+                Some(latency)
+            }
+            None => None,
+        };
+        if settings.sequential && latency.is_some() {
+            eprintln!(
+                "warning: latencies are only compared once a stage is complete, so neither test will stop a stage early"
+            );
+        }
+        // • Both gates are required, and share the alpha cutoff
+        //   between them, so comparing latencies doesn't make a
+        //   healthy canary more likely to be rolled back.
+        let correction = settings.correction;
+        let mut promotion = Promotion::new(plan, alpha).with_engine(Box::new(move || {
+            let policy = VerdictPolicy::new()
+                .with_correction(correction, alpha)
+                .require("errors", errors());
+            Box::new(match latency.clone() {
+                Some(latency) => policy.require("latency", latency),
+                None => policy,
+            })
+        }));
+        let shifter = shifter.as_mut();
+        let state = match settings.source {
+            Source::CloudwatchLogs { log_group } => {
+                let client = CloudwatchLogsObserver::client(settings.endpoint_url.as_deref()).await;
+                let mut observer = CloudwatchLogsObserver::new(
+                    client,
+                    log_group,
+                    settings.control,
+                    settings.canary,
+                )
+                .with_status_field(settings.status_field)
+                .with_deployment_field(settings.deployment_field)
+                .with_skipped_rows(|skipped| {
+                    eprintln!("warning: skipped {skipped} CloudWatch Logs Insights rows without a valid @timestamp");
+                });
+                if let Some(field) = &settings.latency_field {
+                    observer = observer.with_latency_field(field);
+                }
+                if let Some(field) = settings.grpc_status_field {
+                    observer = observer
+                        .with_grpc_status_field(field)
+                        .with_grpc_failures(settings.grpc_failures);
+                }
+                if let Some(query) = settings.query {
+                    observer = observer.with_query(query);
+                }
+                let observations = repeat_query(observer, interval, policy);
+                let batches = batch_observations(observations, batch_size, interval);
+                drive(&mut promotion, batches, shifter, settings.timeout, report).await?
+            }
+            Source::Prometheus {
+                url,
+                metric,
+                version_label,
+                code_label,
+            } => {
+                let mut observer = PrometheusObserver::new(
+                    reqwest::Client::new(),
+                    url,
+                    settings.control,
+                    settings.canary,
+                );
+                if let Some(metric) = metric {
+                    observer = observer.with_metric(metric);
+                }
+                if let Some(label) = version_label {
+                    observer = observer.with_version_label(label);
+                }
+                if let Some(label) = code_label {
+                    observer = observer.with_code_label(label);
+                }
+                let observations = repeat_query(observer, interval, policy);
+                let batches = batch_observations(observations, batch_size, interval);
+                drive(&mut promotion, batches, shifter, settings.timeout, report).await?
+            }
+        };
+        let verdict = Verdict::from(state);
+        // TODO: Reincorporate the "Terminal" abstraction to
+        //       mediate writing to stdout from one spot.
+        let observations = promotion.total_observations();
+        let diagnostics = promotion.last_diagnostics();
+        let direction = diagnostics.and_then(|d| d.direction);
+        let better = direction == Some(Direction::Better);
+        let worse = direction == Some(Direction::Worse);
+        let summary = diagnostics.map_or(String::from("untested"), ToString::to_string);
+        match verdict {
+            Verdict::Promote if better => println!(
+                "promote: the canary is significantly better after {observations} observations ({summary})"
+            ),
+            Verdict::Promote => println!(
+                "promote: no significant difference after {observations} observations ({summary})"
+            ),
+            Verdict::Rollback if worse => println!(
+                "rollback: the canary's error rate is significantly higher than the control group's after {observations} observations ({summary})"
+            ),
+            Verdict::Rollback => println!(
+                "rollback: the canary performs significantly worse than the control group after {observations} observations ({summary})"
+            ),
+            Verdict::Inconclusive => println!(
+                "inconclusive: stopped observing after {observations} observations ({summary})"
+            ),
+        }
+        if let Some(rates) = promotion.last_error_rates() {
+            println!("errors: {rates}");
+        }
+        if let Some(result) = promotion.last_latency_result() {
+            println!(
+                "latency: effect size {:.3} (p = {:.4})",
+                result.effect_size, result.p_value
+            );
+            for delta in result.percentiles.iter().flat_map(|p| p.deltas) {
+                println!(
+                    "latency: p{:.0} {:.1}ms vs {:.1}ms ({:+.1}%)",
+                    delta.quantile * 100.0,
+                    delta.experimental,
+                    delta.control,
+                    delta.relative_delta() * 100.0
+                );
+            }
+        }
+        Ok(verdict.exit_code())
+    }
+}
+
+/// Tell the user what happened while the promotion was being driven.
+fn report<E: Display>(event: DriveEvent<E>) {
+    match event {
+        DriveEvent::QueryFailed(source) => {
+            eprintln!("warning: query failed, retrying: {source}");
+        }
+        DriveEvent::GaveUp { failures, source } => {
+            eprintln!("error: giving up after {failures} consecutive query failures: {source}");
+        }
+        DriveEvent::Advanced { weight } => {
+            eprintln!("stage passed, advancing the canary to {weight}% of traffic");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use tokio::time::Duration;
+
+    use super::{Deploy, Settings, Source};
+    use crate::config::{ConfigError, DeployConfig};
+    use crate::stats::{
+        Alpha, Correction, EngineKind, GrpcCode, GrpcFailures, LatencyTest, StatusCodeSets,
+        TestSelection,
+    };
+
+    /// Parse the deploy subcommand's flags.
+    fn flags(args: &[&str]) -> Deploy {
+        try_flags(args).unwrap()
+    }
+
+    /// Parse the deploy subcommand's flags, which may be invalid.
+    fn try_flags(args: &[&str]) -> Result<Deploy, clap::Error> {
+        use clap::Parser;
+        #[derive(Parser)]
+        struct Cli {
+            #[command(flatten)]
+            deploy: Deploy,
+        }
+        Cli::try_parse_from(std::iter::once("deploy").chain(args.iter().copied()))
+            .map(|cli| cli.deploy)
+    }
+
+    /// Flags take precedence over the config file, which takes
+    /// precedence over the defaults.
+    #[test]
+    fn flags_override_config_file() {
+        let config = DeployConfig::parse(
+            "canary.toml",
+            r#"
+                alpha = 0.01
+                timeout = 600
+                [observer]
+                type = "cloudwatch-logs"
+                log-group = "api"
+                [mapping]
+                control = "v1"
+                canary = "v2"
+            "#
+            .to_owned(),
+        )
+        .unwrap();
+        let settings = flags(&[
+            "--canary",
+            "v3",
+            "--timeout",
+            "60",
+            "--test-method",
+            "exact",
+            "--grpc-failure-codes",
+            "unavailable",
+            "--status-code-set",
+            "429",
+            "--status-code-set",
+            "502,503",
+        ])
+        .settings(config)
+        .unwrap();
+        assert_eq!(
+            settings,
+            Settings {
+                source: Source::CloudwatchLogs {
+                    log_group: "api".to_owned(),
+                },
+                control: "v1".to_owned(),
+                canary: "v3".to_owned(),
+                status_field: "status".to_owned(),
+                deployment_field: "deployment".to_owned(),
+                latency_field: None,
+                grpc_status_field: None,
+                grpc_failures: GrpcFailures::new([GrpcCode::Unavailable]),
+                latency_test: LatencyTest::MannWhitney,
+                min_effect_size: 0.05,
+                percentile_tolerance: None,
+                query: None,
+                endpoint_url: None,
+                engine: EngineKind::ChiSquare,
+                alpha: Alpha::new(0.01).unwrap(),
+                correction: Correction::Holm,
+                min_group_size: 30,
+                test_method: TestSelection::Exact,
+                status_code_sets: StatusCodeSets::new([vec![429], vec![502, 503]]).unwrap(),
+                margin: 0.0,
+                rollback_threshold: 0.95,
+                loss_threshold: 0.001,
+                batch_size: 512,
+                polling_interval: Duration::from_secs(60),
+                min_sample_size: 1000,
+                timeout: Duration::from_secs(60),
+                max_query_failures: 5,
+                sequential: false,
+                equivalence_margin: 0.005,
+                promotion_steps: None,
+                shifter: None,
+            }
+        );
+    }
+
+    #[test]
+    fn missing_settings_are_reported() {
+        let err = flags(&["--log-group", "api", "--control", "v1"])
+            .settings(DeployConfig::default())
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::Missing {
+                flag: "--canary",
+                ..
+            }
+        ));
+    }
+
+    /// The Prometheus URL flag selects the Prometheus observer over the
+    /// config file's, keeping the rest of its settings.
+    #[test]
+    fn prometheus_url_selects_prometheus() {
+        let config = DeployConfig::parse(
+            "canary.toml",
+            r#"
+                [observer]
+                type = "prometheus"
+                url = "http://prometheus:9090"
+                metric = "requests_total"
+                [mapping]
+                control = "v1"
+                canary = "v2"
+            "#
+            .to_owned(),
+        )
+        .unwrap();
+        let settings = flags(&["--prometheus-url", "http://localhost:9090"])
+            .settings(config.clone())
+            .unwrap();
+        assert_eq!(
+            settings.source,
+            Source::Prometheus {
+                url: "http://localhost:9090".to_owned(),
+                metric: Some("requests_total".to_owned()),
+                version_label: None,
+                code_label: None,
+            }
+        );
+        let settings = flags(&["--log-group", "api"]).settings(config).unwrap();
+        assert_eq!(
+            settings.source,
+            Source::CloudwatchLogs {
+                log_group: "api".to_owned()
+            }
+        );
+    }
+
+    /// Flags are held to the same bounds as the config file, so a zero
+    /// batch size or polling interval can't reach the pipeline.
+    #[test]
+    fn out_of_range_flags_are_rejected() {
+        for (flag, value) in [
+            ("--batch-size", "0"),
+            ("--polling-interval", "0"),
+            ("--min-group-size", "0"),
+            ("--timeout", "0"),
+            ("--max-query-failures", "0"),
+            ("--min-effect-size", "1"),
+            ("--percentile-tolerance", "0"),
+            ("--percentile-tolerance", "NaN"),
+            ("--margin", "-0.1"),
+            ("--rollback-threshold", "1.5"),
+            ("--rollback-threshold", "0"),
+            ("--loss-threshold", "-0.001"),
+            ("--equivalence-margin", "1"),
+        ] {
+            let err = try_flags(&[&format!("{flag}={value}")]).err();
+            assert_eq!(
+                err.map(|err| err.kind()),
+                Some(clap::error::ErrorKind::ValueValidation),
+                "{flag} {value}"
+            );
+        }
+        assert!(try_flags(&["--batch-size", "1", "--polling-interval", "1"]).is_ok());
+    }
+
+    /// Only the chi-square engine can test sequentially, so asking
+    /// another engine to is an error rather than silently ignored.
+    #[test]
+    fn sequential_requires_chi_square() {
+        let err = flags(&[
+            "--log-group",
+            "api",
+            "--control",
+            "v1",
+            "--canary",
+            "v2",
+            "--sequential",
+            "--engine",
+            "bayesian",
+        ])
+        .settings(DeployConfig::default())
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::Sequential { engine: "bayesian" }
+        ));
+    }
+}
@@ -1,4 +1,10 @@
+/// A subcommand to observe a canary and promote or roll it back.
+pub use deploy::Deploy;
+/// A subcommand to estimate how long a canary must be observed.
+pub use plan::Plan;
 /// A subcommand to print the version of this executable.
 pub use version::Version;
 
+mod deploy;
+mod plan;
 mod version;
@@ -0,0 +1,342 @@
+use clap::Args;
+use miette::{Diagnostic, Result};
+use thiserror::Error;
+use tokio::time::Duration;
+
+use super::deploy::parse_code_set;
+use crate::stats::{
+    sample_size, Alpha, Correction, StatsError, StatusCodeSets, DEFAULT_ALPHA_CUTOFF,
+};
+
+/// By default, we want an 80% chance of catching a regression.
+const DEFAULT_POWER: f64 = 0.8;
+/// By default, the canary gets half of the traffic.
+const DEFAULT_CANARY_WEIGHT: u8 = 50;
+
+/// Estimate how many requests, and how long, a canary must be observed
+/// to reliably detect a regression in its error rate.
+///
+/// Deploy rolls back on a one-sided test of the 5XX rate, run at the
+/// significance level left once it's split between the 5XX rate, each
+/// status code set, and the latency test. So the plan takes the same
+/// flags, and assumes the error rate test must reject on its own.
+#[derive(Args, Clone)]
+pub struct Plan {
+    /// The control group's error rate, e.g. 0.01 for 1% of requests.
+    #[arg(long, value_parser = rate)]
+    baseline_error_rate: f64,
+
+    /// The smallest increase in the error rate worth catching,
+    /// e.g. 0.005 to catch the canary failing 1.5% of requests.
+    #[arg(long, value_parser = rate)]
+    min_detectable_effect: f64,
+
+    /// The significance level of the statistical test.
+    #[arg(long, default_value_t = DEFAULT_ALPHA_CUTOFF)]
+    alpha: Alpha,
+
+    /// Exact status codes the chi-square engine tests apart from their
+    /// category, as passed to deploy. Each set of non-5XX codes is tested
+    /// too, which leaves a smaller significance level for the 5XX rate.
+    #[arg(long = "status-code-set", value_parser = parse_code_set)]
+    status_code_sets: Vec<Vec<u16>>,
+
+    /// Plan for latencies being compared too, as deploy does
+    /// when it's given a latency field.
+    #[arg(long)]
+    latency: bool,
+
+    /// How the significance level is shared between the error rate
+    /// and latency tests, when latencies are compared.
+    #[arg(long, value_enum, default_value_t = Correction::Holm)]
+    correction: Correction,
+
+    /// The probability of catching a regression at least
+    /// as large as the minimum detectable effect.
+    #[arg(long, value_parser = rate, default_value_t = DEFAULT_POWER)]
+    power: f64,
+
+    /// The service's traffic, in requests per second. When set, also
+    /// estimate how long the canary must be observed.
+    #[arg(long)]
+    traffic: Option<f64>,
+
+    /// The percentage of traffic routed to the canary.
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..100), default_value_t = DEFAULT_CANARY_WEIGHT)]
+    canary_weight: u8,
+
+    /// The number of stages the canary is promoted through, e.g. 4 for
+    /// promotion steps of [1, 5, 25, 50, 100]. Each stage is observed
+    /// separately, so the suggested timeout covers all of them.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), default_value_t = 1)]
+    stages: u32,
+}
+
+/// A [PlanError] explains why no estimate could be made.
+#[derive(Error, Diagnostic, Debug)]
+pub enum PlanError {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Stats(#[from] StatsError),
+
+    #[error("the traffic must be a positive number of requests per second")]
+    #[diagnostic(code(canary::plan::traffic))]
+    Traffic,
+}
+
+/// An [Estimate] is how much traffic the canary must be observed for.
+#[derive(Debug, PartialEq)]
+struct Estimate {
+    /// The significance level the 5XX rate is tested at.
+    alpha: Alpha,
+    /// The number of requests each group must serve.
+    group_size: usize,
+    /// The number of requests both groups must serve together, given
+    /// that the smaller group must still reach the group size.
+    total: usize,
+    /// How long serving that many requests takes, if the traffic is known.
+    duration: Option<Duration>,
+    /// How long every stage takes together, if the traffic is known.
+    timeout: Option<Duration>,
+}
+
+impl Plan {
+    /// Print the estimate.
+    pub fn dispatch(self) -> Result<()> {
+        let estimate = self.estimate()?;
+        // TODO: Reincorporate the "Terminal" abstraction to
+        //       mediate writing to stdout from one spot.
+        println!(
+            "each group must serve {} requests to detect an error rate of {:.2}% instead of {:.2}% (one-sided alpha = {}, power = {})",
+            estimate.group_size,
+            (self.baseline_error_rate + self.min_detectable_effect) * 100.0,
+            self.baseline_error_rate * 100.0,
+            estimate.alpha,
+            self.power,
+        );
+        println!(
+            "with {}% of traffic on the canary, that's {} requests in total",
+            self.canary_weight, estimate.total
+        );
+        if let (Some(duration), Some(timeout)) = (estimate.duration, estimate.timeout) {
+            println!(
+                "at the current traffic, that takes about {}",
+                format_duration(duration)
+            );
+            if self.stages > 1 {
+                println!(
+                    "with {} stages, the whole deployment takes about {}, or longer if earlier stages route less than {}% of traffic to the canary",
+                    self.stages,
+                    format_duration(timeout),
+                    self.canary_weight
+                );
+            }
+            println!(
+                "suggested flags: --min-group-size {} --min-sample-size {} --timeout {}",
+                estimate.group_size,
+                estimate.total,
+                timeout.as_secs()
+            );
+        }
+        Ok(())
+    }
+
+    /// Compute the sample size, and how long it takes to collect.
+    fn estimate(&self) -> Result<Estimate, PlanError> {
+        if self
+            .traffic
+            .is_some_and(|traffic| traffic.is_nan() || traffic <= 0.0)
+        {
+            return Err(PlanError::Traffic);
+        }
+        let alpha = self.error_rate_alpha()?;
+        let group_size = sample_size(
+            self.baseline_error_rate,
+            self.min_detectable_effect,
+            alpha,
+            self.power,
+        )?;
+        // • The smaller group is the bottleneck: at 5% of traffic, the
+        //   canary sees one request in twenty.
+        let share = f64::from(self.canary_weight.min(100 - self.canary_weight)) / 100.0;
+        let total = (group_size as f64 / share).ceil() as usize;
+        let duration = self
+            .traffic
+            .map(|traffic| Duration::from_secs((total as f64 / traffic).ceil() as u64));
+        Ok(Estimate {
+            alpha,
+            group_size,
+            total,
+            duration,
+            timeout: duration.map(|duration| duration * self.stages),
+        })
+    }
+
+    /// Returns the significance level deploy tests the 5XX rate at.
+    fn error_rate_alpha(&self) -> Result<Alpha, PlanError> {
+        // • The chi-square engine splits the alpha cutoff between
+        //   the 5XX rate and every set of non-5XX codes.
+        let sets = StatusCodeSets::new(self.status_code_sets.clone())?;
+        let tested = sets.classes().filter(|class| !class.is_server_error());
+        let alpha = self.alpha.split(1 + tested.count());
+        // • Holm only lets the 5XX rate reject at the full alpha once the
+        //   latency test has rejected, so we plan for it rejecting first.
+        Ok(match self.correction {
+            Correction::Bonferroni | Correction::Holm if self.latency => alpha.split(2),
+            _ => alpha,
+        })
+    }
+}
+
+/// Format a duration like "1h 2m 3s", omitting leading zero units.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    match (hours, minutes) {
+        (0, 0) => format!("{seconds}s"),
+        (0, _) => format!("{minutes}m {seconds}s"),
+        _ => format!("{hours}h {minutes}m {seconds}s"),
+    }
+}
+
+/// Parse a probability, which must lie strictly between 0 and 1.
+fn rate(value: &str) -> Result<f64, String> {
+    let rate: f64 = value.parse().map_err(|err| format!("{err}"))?;
+    if 0.0 < rate && rate < 1.0 {
+        Ok(rate)
+    } else {
+        Err(String::from("must be between 0 and 1"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use tokio::time::Duration;
+
+    use super::{format_duration, Estimate, Plan, PlanError};
+    use crate::stats::{Alpha, StatsError, DEFAULT_ALPHA_CUTOFF};
+
+    /// Parse the plan subcommand's flags.
+    fn flags(args: &[&str]) -> Result<Plan, clap::Error> {
+        use clap::Parser;
+        #[derive(Parser)]
+        struct Cli {
+            #[command(flatten)]
+            plan: Plan,
+        }
+        Cli::try_parse_from(std::iter::once("plan").chain(args.iter().copied())).map(|cli| cli.plan)
+    }
+
+    /// A canary on 5% of 10 requests per second must run
+    /// twenty times longer than its own share of traffic suggests.
+    #[test]
+    fn estimates_sample_size_and_duration() {
+        let plan = flags(&[
+            "--baseline-error-rate=0.1",
+            "--min-detectable-effect=0.05",
+            "--traffic=10",
+            "--canary-weight=5",
+        ])
+        .unwrap();
+        assert_eq!(
+            plan.estimate().unwrap(),
+            Estimate {
+                alpha: DEFAULT_ALPHA_CUTOFF,
+                group_size: 540,
+                total: 10_800,
+                duration: Some(Duration::from_secs(1080)),
+                timeout: Some(Duration::from_secs(1080)),
+            }
+        );
+        assert_eq!(format_duration(Duration::from_secs(1080)), "18m 0s");
+    }
+
+    /// Every stage is observed from scratch, so the
+    /// timeout grows with the number of stages.
+    #[test]
+    fn timeout_covers_every_stage() {
+        let plan = flags(&[
+            "--baseline-error-rate=0.1",
+            "--min-detectable-effect=0.05",
+            "--traffic=10",
+            "--canary-weight=5",
+            "--stages=4",
+        ])
+        .unwrap();
+        let estimate = plan.estimate().unwrap();
+        assert_eq!(estimate.duration, Some(Duration::from_secs(1080)));
+        assert_eq!(estimate.timeout, Some(Duration::from_secs(4 * 1080)));
+        assert!(flags(&[
+            "--baseline-error-rate=0.1",
+            "--min-detectable-effect=0.05",
+            "--stages=0",
+        ])
+        .is_err());
+    }
+
+    /// The 5XX rate shares the significance level with every set of
+    /// non-5XX codes, and with the latency test unless it's unadjusted,
+    /// so each of them makes a regression harder to detect.
+    #[test]
+    fn alpha_is_shared_like_deploy() {
+        let alpha = |args: &[&str]| {
+            let base = ["--baseline-error-rate=0.1", "--min-detectable-effect=0.05"];
+            let plan = flags(&[&base[..], args].concat()).unwrap();
+            plan.estimate().unwrap().alpha
+        };
+        let sets = ["--status-code-set=429", "--status-code-set=502,503"];
+        assert_eq!(alpha(&sets), Alpha::new(0.025).unwrap());
+        assert_eq!(alpha(&["--latency"]), Alpha::new(0.025).unwrap());
+        assert_eq!(
+            alpha(&[&sets[..], &["--latency"]].concat()),
+            Alpha::new(0.0125).unwrap()
+        );
+        assert_eq!(
+            alpha(&["--latency", "--correction=unadjusted"]),
+            DEFAULT_ALPHA_CUTOFF
+        );
+        let plan = flags(&[
+            "--baseline-error-rate=0.1",
+            "--min-detectable-effect=0.05",
+            "--latency",
+        ])
+        .unwrap();
+        assert!(plan.estimate().unwrap().group_size > 540);
+    }
+
+    #[test]
+    fn invalid_inputs_are_rejected() {
+        assert!(flags(&["--baseline-error-rate=1.5", "--min-detectable-effect=0.1"]).is_err());
+        assert!(flags(&[
+            "--baseline-error-rate=0.1",
+            "--min-detectable-effect=0.1",
+            "--canary-weight=100"
+        ])
+        .is_err());
+        assert!(flags(&[
+            "--baseline-error-rate=0.1",
+            "--min-detectable-effect=0.1",
+            "--alpha=0.95"
+        ])
+        .is_ok());
+        assert!(flags(&[
+            "--baseline-error-rate=0.1",
+            "--min-detectable-effect=0.1",
+            "--alpha=5"
+        ])
+        .is_err());
+        let plan = flags(&["--baseline-error-rate=0.6", "--min-detectable-effect=0.5"]).unwrap();
+        assert!(matches!(
+            plan.estimate(),
+            Err(PlanError::Stats(StatsError::Rates { .. }))
+        ));
+        let plan = flags(&[
+            "--baseline-error-rate=0.1",
+            "--min-detectable-effect=0.1",
+            "--traffic=0",
+        ])
+        .unwrap();
+        assert!(matches!(plan.estimate(), Err(PlanError::Traffic)));
+    }
+}
@@ -1,7 +1,9 @@
+use std::process::ExitCode;
+
 use clap::Subcommand;
 use miette::Result;
 
-use crate::cmd::Version;
+use crate::cmd::{Deploy, Plan, Version};
 
 /// one of the top-level commands accepted by
 /// the canary CLI.
@@ -9,13 +11,27 @@ use crate::cmd::Version;
 pub enum CanaryCommand {
     /// Print the CLI version and exit
     Version,
+    /// Observe a canary deployment, then promote it or roll it back
+    ///
+    /// Exits with status 0 when the canary should be promoted,
+    /// with status 2 when it should be rolled back, and with
+    /// status 3 when the result is inconclusive.
+    Deploy(Box<Deploy>),
+    /// Estimate how many requests, and how long, a canary must be observed
+    ///
+    /// Given the baseline error rate and the smallest regression worth
+    /// catching, computes the sample size each group needs, and how long
+    /// collecting it takes at the given traffic.
+    Plan(Plan),
 }
 
 impl CanaryCommand {
     /// dispatch the user-provided arguments to the command handler.
-    pub async fn dispatch(&self) -> Result<()> {
+    pub async fn dispatch(&self) -> Result<ExitCode> {
         match self.clone() {
-            Self::Version => Version::new().dispatch(),
+            Self::Version => Version::new().dispatch().map(|()| ExitCode::SUCCESS),
+            Self::Deploy(deploy) => deploy.dispatch().await,
+            Self::Plan(plan) => plan.dispatch().map(|()| ExitCode::SUCCESS),
         }
     }
 }
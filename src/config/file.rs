@@ -0,0 +1,709 @@
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use serde::Deserialize;
+use thiserror::Error;
+use toml::Spanned;
+
+use crate::stats::{
+    Alpha, Correction, EngineKind, GrpcCode, LatencyTest, StatsError, StatusCodeSets, TestSelection,
+};
+
+/// The name of the config file we look for in the working directory
+/// when no path is provided.
+pub const DEFAULT_CONFIG_PATH: &str = "canary.toml";
+
+/// A [DeployConfig] describes a canary deployment. It's read from
+/// a TOML file, usually `canary.toml`. Every setting is optional here
+/// because any of them may be provided (or overridden) by CLI flags.
+///
+/// ```toml
+/// engine = "chi-square"
+/// alpha = 0.05
+/// correction = "holm"
+/// min-group-size = 30
+/// test-method = "auto"
+/// status-code-sets = [[429], [502, 503, 504]]
+/// batch-size = 512
+/// polling-interval = 60
+/// min-sample-size = 1000
+/// timeout = 1800
+/// sequential = true
+/// equivalence-margin = 0.005
+///
+/// [observer]
+/// type = "cloudwatch-logs"
+/// log-group = "my-service"
+///
+/// [shifter]
+/// type = "lambda-alias"
+/// function-name = "my-service"
+/// alias = "live"
+/// baseline-version = "41"
+/// canary-version = "42"
+///
+/// [mapping]
+/// status-field = "status"
+/// deployment-field = "deployment"
+/// latency-field = "duration"
+/// grpc-status-field = "grpc_status"
+/// grpc-failure-codes = ["UNAVAILABLE", "DEADLINE_EXCEEDED", "INTERNAL"]
+/// control = "v41"
+/// canary = "v42"
+///
+/// [promotion]
+/// steps = [1, 5, 25, 50, 100]
+///
+/// [latency]
+/// test = "mann-whitney"
+/// min-effect-size = 0.05
+/// percentile-tolerance = 0.2
+///
+/// [bayesian]
+/// margin = 0.001
+/// rollback-threshold = 0.95
+/// loss-threshold = 0.001
+/// ```
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct DeployConfig {
+    /// Where observations come from.
+    pub observer: Option<ObserverConfig>,
+    /// What moves traffic between the deployments.
+    pub shifter: Option<ShifterConfig>,
+    /// How raw events map onto groups and outcomes.
+    pub mapping: MappingConfig,
+    /// The decision engine comparing the groups' error rates.
+    pub engine: Option<EngineKind>,
+    /// The significance level of the statistical test.
+    pub alpha: Option<Alpha>,
+    /// How the significance level is shared between the error
+    /// rate and latency tests.
+    pub correction: Option<Correction>,
+    /// The number of observations each group needs before
+    /// the error rates are compared.
+    pub min_group_size: Option<usize>,
+    /// How the chi-square engine computes its p-value.
+    pub test_method: Option<TestSelection>,
+    /// The exact status codes the chi-square engine tests apart
+    /// from their category.
+    pub status_code_sets: Option<StatusCodeSets>,
+    /// The largest number of items collected before we recompute
+    /// statistical significance: requests, or with an observer that
+    /// reports counts, the counts.
+    pub batch_size: Option<usize>,
+    /// How often to query for new observations, in seconds.
+    pub polling_interval: Option<u64>,
+    /// The number of observations required before we decide anything.
+    pub min_sample_size: Option<usize>,
+    /// How long to observe the canary before giving up, in seconds.
+    pub timeout: Option<u64>,
+    /// How many consecutive queries may fail before we give up.
+    pub max_query_failures: Option<u32>,
+    /// Whether to use the always-valid sequential test.
+    pub sequential: Option<bool>,
+    /// How much higher the canary's 5XX rate may be for the
+    /// sequential test to pass a stage early.
+    pub equivalence_margin: Option<f64>,
+    /// The traffic percentages the canary is promoted through.
+    pub promotion_steps: Option<Vec<u8>>,
+    /// The test used to compare latencies.
+    pub latency_test: Option<LatencyTest>,
+    /// The smallest latency regression that fails a stage.
+    pub min_effect_size: Option<f64>,
+    /// How much slower the canary's p50, p90 and p99 may be.
+    pub percentile_tolerance: Option<f64>,
+    /// How much higher the canary's 5XX rate may be before the
+    /// Bayesian engine counts it as worse.
+    pub margin: Option<f64>,
+    /// How sure the Bayesian engine must be that the canary is worse.
+    pub rollback_threshold: Option<f64>,
+    /// The expected loss below which the Bayesian engine passes a stage.
+    pub loss_threshold: Option<f64>,
+}
+
+/// The [ObserverConfig] selects the external system we query for observations.
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum ObserverConfig {
+    /// Run Logs Insights queries against a CloudWatch log group.
+    #[serde(rename_all = "kebab-case")]
+    CloudwatchLogs {
+        /// The log group both deployments write their access logs to.
+        log_group: Option<String>,
+        /// A custom Logs Insights query.
+        query: Option<String>,
+        /// Send requests to this URL instead of AWS.
+        endpoint_url: Option<String>,
+    },
+    /// Diff a request counter, labelled by deployment and status
+    /// code, between range queries against a Prometheus server.
+    #[serde(rename_all = "kebab-case")]
+    Prometheus {
+        /// The base URL of the Prometheus server.
+        url: Option<String>,
+        /// The name of the request counter.
+        metric: Option<String>,
+        /// The label identifying the deployment.
+        version_label: Option<String>,
+        /// The label holding the HTTP status code.
+        code_label: Option<String>,
+    },
+}
+
+/// The [ShifterConfig] selects what moves traffic between the control
+/// and canary deployments. Without one, traffic is left as it is and
+/// the exit code reports whether the canary should be promoted.
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum ShifterConfig {
+    /// Adjust the routing configuration of a Lambda alias.
+    #[serde(rename_all = "kebab-case")]
+    LambdaAlias {
+        /// The name or ARN of the Lambda function.
+        function_name: String,
+        /// The alias callers invoke.
+        alias: String,
+        /// The version currently serving production traffic.
+        baseline_version: String,
+        /// The version being canaried.
+        canary_version: String,
+        /// Send requests to this URL instead of AWS.
+        endpoint_url: Option<String>,
+    },
+    /// Adjust the forward action weights of an ALB listener rule.
+    #[serde(rename_all = "kebab-case")]
+    AlbListenerRule {
+        /// The ARN of the listener rule.
+        rule_arn: String,
+        /// The ARN of the target group serving production traffic.
+        baseline_target_group: String,
+        /// The ARN of the target group being canaried.
+        canary_target_group: String,
+        /// Send requests to this URL instead of AWS.
+        endpoint_url: Option<String>,
+    },
+}
+
+/// The [MappingConfig] describes which fields of an event hold the
+/// status code and deployment, and which deployment is which.
+#[derive(Deserialize, Debug, Default, PartialEq, Clone)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct MappingConfig {
+    /// The field holding the HTTP status code.
+    pub status_field: Option<String>,
+    /// The field holding the deployment identifier.
+    pub deployment_field: Option<String>,
+    /// The field holding the request latency, in milliseconds.
+    pub latency_field: Option<String>,
+    /// The field holding the gRPC status code, for gRPC services.
+    pub grpc_status_field: Option<String>,
+    /// The gRPC codes that count as failures.
+    pub grpc_failure_codes: Option<Vec<GrpcCode>>,
+    /// The deployment identifier of the control group.
+    pub control: Option<String>,
+    /// The deployment identifier of the canary.
+    pub canary: Option<String>,
+}
+
+/// This is the config file exactly as written, with the locations
+/// of the values we validate so errors can point at them.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RawConfig {
+    observer: Option<ObserverConfig>,
+    shifter: Option<ShifterConfig>,
+    #[serde(default)]
+    mapping: MappingConfig,
+    engine: Option<EngineKind>,
+    alpha: Option<Spanned<f64>>,
+    correction: Option<Correction>,
+    min_group_size: Option<Spanned<usize>>,
+    test_method: Option<TestSelection>,
+    status_code_sets: Option<Spanned<Vec<Vec<u16>>>>,
+    batch_size: Option<Spanned<usize>>,
+    polling_interval: Option<Spanned<u64>>,
+    min_sample_size: Option<Spanned<usize>>,
+    timeout: Option<Spanned<u64>>,
+    max_query_failures: Option<Spanned<u32>>,
+    sequential: Option<bool>,
+    equivalence_margin: Option<Spanned<f64>>,
+    #[serde(default)]
+    promotion: RawPromotion,
+    #[serde(default)]
+    latency: RawLatency,
+    #[serde(default)]
+    bayesian: RawBayesian,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RawPromotion {
+    steps: Option<Spanned<Vec<u8>>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RawLatency {
+    test: Option<LatencyTest>,
+    min_effect_size: Option<Spanned<f64>>,
+    percentile_tolerance: Option<Spanned<f64>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RawBayesian {
+    margin: Option<Spanned<f64>>,
+    rollback_threshold: Option<Spanned<f64>>,
+    loss_threshold: Option<Spanned<f64>>,
+}
+
+/// A [ConfigError] explains why the config file couldn't be used.
+#[derive(Error, Diagnostic, Debug)]
+pub enum ConfigError {
+    /// The file couldn't be read from disk.
+    #[error("unable to read the config file {path}")]
+    #[diagnostic(code(canary::config::read))]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The file isn't valid TOML, or has a value that doesn't make sense.
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Invalid(Box<InvalidConfig>),
+    /// A required setting wasn't provided in the config file or by a flag.
+    #[error("missing required setting `{key}`")]
+    #[diagnostic(
+        code(canary::config::missing),
+        help("pass {flag}, or set `{key}` in {DEFAULT_CONFIG_PATH}")
+    )]
+    Missing {
+        key: &'static str,
+        flag: &'static str,
+    },
+    /// Only the chi-square engine has a sequential test.
+    #[error("the {engine} engine can't test sequentially")]
+    #[diagnostic(
+        code(canary::config::sequential),
+        help("use the chi-square engine, or turn off sequential testing")
+    )]
+    Sequential { engine: &'static str },
+    /// The promotion steps don't describe a sequence of stages.
+    #[error(
+        "the promotion steps {steps:?} must be increasing percentages, with at least one below 100"
+    )]
+    #[diagnostic(code(canary::config::steps), help("for example, [1, 5, 25, 50, 100]"))]
+    Steps { steps: Vec<u8> },
+    /// A flag has a value that doesn't make sense.
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Flag(#[from] StatsError),
+}
+
+/// An [InvalidConfig] points at the part of the config file we couldn't use.
+#[derive(Error, Diagnostic, Debug)]
+#[error("invalid config file: {message}")]
+#[diagnostic(code(canary::config::invalid))]
+pub struct InvalidConfig {
+    message: String,
+    #[source_code]
+    src: NamedSource<String>,
+    #[label("{message}")]
+    span: Option<SourceSpan>,
+    #[help]
+    help: Option<String>,
+}
+
+impl DeployConfig {
+    /// Load the config file at the given path. If no path is provided, we
+    /// look for `canary.toml` in the working directory, and fall back to an
+    /// empty config when it doesn't exist.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let (path, required) = match path {
+            Some(path) => (path, true),
+            None => (Path::new(DEFAULT_CONFIG_PATH), false),
+        };
+        match std::fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&path.display().to_string(), contents),
+            Err(err) if !required && err.kind() == std::io::ErrorKind::NotFound => {
+                Ok(Self::default())
+            }
+            Err(source) => Err(ConfigError::Read {
+                path: path.to_owned(),
+                source,
+            }),
+        }
+    }
+
+    /// Parse and validate the contents of a config file. The name is
+    /// only used to label diagnostics.
+    pub fn parse(name: &str, contents: String) -> Result<Self, ConfigError> {
+        let raw: RawConfig = match toml::from_str(&contents) {
+            Ok(raw) => raw,
+            Err(err) => {
+                let message = err.message().to_owned();
+                let span = err.span();
+                return Err(invalid(name, contents, message, span, None));
+            }
+        };
+        let error = |message: &str, span: Range<usize>, help: &str| {
+            invalid(
+                name,
+                contents.clone(),
+                message.to_owned(),
+                Some(span),
+                Some(help.to_owned()),
+            )
+        };
+        // • Each of these checks points back at the offending value.
+        let alpha = match &raw.alpha {
+            Some(alpha) => match Alpha::new(*alpha.get_ref()) {
+                Ok(valid) => Some(valid),
+                Err(err) => {
+                    return Err(error(
+                        &err.to_string(),
+                        alpha.span(),
+                        "a typical significance level is 0.05",
+                    ))
+                }
+            },
+            None => None,
+        };
+        let status_code_sets = match &raw.status_code_sets {
+            Some(sets) => match StatusCodeSets::new(sets.get_ref().clone()) {
+                Ok(valid) => Some(valid),
+                Err(err) => {
+                    return Err(error(
+                        &err.to_string(),
+                        sets.span(),
+                        "keep each set within one category, like [502, 503, 504], and list each code once",
+                    ))
+                }
+            },
+            None => None,
+        };
+        if let Some(effect) = &raw.latency.min_effect_size {
+            if !(0.0..1.0).contains(effect.get_ref()) {
+                return Err(error(
+                    "min-effect-size must be at least 0 and less than 1",
+                    effect.span(),
+                    "a typical minimum effect size is 0.05",
+                ));
+            }
+        }
+        if let Some(tolerance) = &raw.latency.percentile_tolerance {
+            if tolerance.get_ref().is_nan() || *tolerance.get_ref() <= 0.0 {
+                return Err(error(
+                    "percentile-tolerance must be greater than zero",
+                    tolerance.span(),
+                    "0.2 allows the canary's percentiles to be 20% slower",
+                ));
+            }
+        }
+        for (value, key, valid) in [
+            (&raw.bayesian.margin, "margin", 0.0..1.0),
+            (
+                &raw.bayesian.rollback_threshold,
+                "rollback-threshold",
+                0.5..1.0,
+            ),
+            (&raw.bayesian.loss_threshold, "loss-threshold", 0.0..1.0),
+            (&raw.equivalence_margin, "equivalence-margin", 0.0..1.0),
+        ] {
+            if let Some(value) = value.as_ref().filter(|v| !valid.contains(v.get_ref())) {
+                return Err(error(
+                    &format!(
+                        "{key} must be at least {} and less than {}",
+                        valid.start, valid.end
+                    ),
+                    value.span(),
+                    "remove this setting to use the default",
+                ));
+            }
+        }
+        for (zero, key) in [
+            (zero_span(&raw.min_group_size), "min-group-size"),
+            (zero_span(&raw.batch_size), "batch-size"),
+            (zero_span(&raw.polling_interval), "polling-interval"),
+            (zero_span(&raw.timeout), "timeout"),
+            (zero_span(&raw.max_query_failures), "max-query-failures"),
+        ] {
+            if let Some(span) = zero {
+                return Err(error(
+                    &format!("{key} must be greater than zero"),
+                    span,
+                    "remove this setting to use the default",
+                ));
+            }
+        }
+        if let Some(steps) = &raw.promotion.steps {
+            let values = steps.get_ref();
+            let increasing = values.windows(2).all(|pair| pair[0] < pair[1]);
+            let in_range = values.iter().all(|step| (1..=100).contains(step));
+            let staged = values.first().is_some_and(|first| *first < 100);
+            if values.last() != Some(&100) || !increasing || !in_range || !staged {
+                return Err(error(
+                    "promotion steps must be increasing percentages ending at 100",
+                    steps.span(),
+                    "for example, steps = [1, 5, 25, 50, 100]",
+                ));
+            }
+        }
+        Ok(Self {
+            observer: raw.observer,
+            shifter: raw.shifter,
+            mapping: raw.mapping,
+            engine: raw.engine,
+            alpha,
+            correction: raw.correction,
+            min_group_size: raw.min_group_size.map(Spanned::into_inner),
+            test_method: raw.test_method,
+            status_code_sets,
+            batch_size: raw.batch_size.map(Spanned::into_inner),
+            polling_interval: raw.polling_interval.map(Spanned::into_inner),
+            min_sample_size: raw.min_sample_size.map(Spanned::into_inner),
+            timeout: raw.timeout.map(Spanned::into_inner),
+            max_query_failures: raw.max_query_failures.map(Spanned::into_inner),
+            sequential: raw.sequential,
+            equivalence_margin: raw.equivalence_margin.map(Spanned::into_inner),
+            promotion_steps: raw.promotion.steps.map(Spanned::into_inner),
+            latency_test: raw.latency.test,
+            min_effect_size: raw.latency.min_effect_size.map(Spanned::into_inner),
+            percentile_tolerance: raw.latency.percentile_tolerance.map(Spanned::into_inner),
+            margin: raw.bayesian.margin.map(Spanned::into_inner),
+            rollback_threshold: raw.bayesian.rollback_threshold.map(Spanned::into_inner),
+            loss_threshold: raw.bayesian.loss_threshold.map(Spanned::into_inner),
+        })
+    }
+}
+
+/// Returns the location of the setting if it's set to zero.
+fn zero_span<T: Default + PartialEq>(value: &Option<Spanned<T>>) -> Option<Range<usize>> {
+    value
+        .as_ref()
+        .filter(|value| *value.get_ref() == T::default())
+        .map(Spanned::span)
+}
+
+/// Build an error that points at a location in the config file.
+fn invalid(
+    name: &str,
+    contents: String,
+    message: String,
+    span: Option<Range<usize>>,
+    help: Option<String>,
+) -> ConfigError {
+    ConfigError::Invalid(Box::new(InvalidConfig {
+        message,
+        src: NamedSource::new(name, contents),
+        span: span.map(SourceSpan::from),
+        help,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{ConfigError, DeployConfig, MappingConfig, ObserverConfig, ShifterConfig};
+    use crate::stats::{
+        Alpha, Correction, EngineKind, GrpcCode, LatencyTest, StatusCodeSets, TestSelection,
+    };
+
+    #[test]
+    fn parses_complete_config() {
+        let contents = r#"
+            engine = "bayesian"
+            alpha = 0.01
+            correction = "bonferroni"
+            min-group-size = 50
+            test-method = "exact"
+            status-code-sets = [[429], [503, 502]]
+            batch-size = 256
+            polling-interval = 30
+            min-sample-size = 1000
+            timeout = 600
+            max-query-failures = 3
+            sequential = true
+            equivalence-margin = 0.01
+
+            [observer]
+            type = "cloudwatch-logs"
+            log-group = "api"
+
+            [shifter]
+            type = "lambda-alias"
+            function-name = "api"
+            alias = "live"
+            baseline-version = "1"
+            canary-version = "2"
+
+            [mapping]
+            control = "v1"
+            canary = "v2"
+            grpc-status-field = "grpc_status"
+            grpc-failure-codes = ["UNAVAILABLE", "INTERNAL"]
+
+            [promotion]
+            steps = [1, 5, 25, 50, 100]
+
+            [latency]
+            test = "kolmogorov-smirnov"
+            min-effect-size = 0.1
+            percentile-tolerance = 0.25
+
+            [bayesian]
+            margin = 0.002
+            rollback-threshold = 0.9
+            loss-threshold = 0.0005
+        "#;
+        let config = DeployConfig::parse("canary.toml", contents.to_owned()).unwrap();
+        let expected = DeployConfig {
+            observer: Some(ObserverConfig::CloudwatchLogs {
+                log_group: Some("api".to_owned()),
+                query: None,
+                endpoint_url: None,
+            }),
+            shifter: Some(ShifterConfig::LambdaAlias {
+                function_name: "api".to_owned(),
+                alias: "live".to_owned(),
+                baseline_version: "1".to_owned(),
+                canary_version: "2".to_owned(),
+                endpoint_url: None,
+            }),
+            mapping: MappingConfig {
+                control: Some("v1".to_owned()),
+                canary: Some("v2".to_owned()),
+                grpc_status_field: Some("grpc_status".to_owned()),
+                grpc_failure_codes: Some(vec![GrpcCode::Unavailable, GrpcCode::Internal]),
+                ..MappingConfig::default()
+            },
+            engine: Some(EngineKind::Bayesian),
+            alpha: Some(Alpha::new(0.01).unwrap()),
+            correction: Some(Correction::Bonferroni),
+            min_group_size: Some(50),
+            test_method: Some(TestSelection::Exact),
+            status_code_sets: Some(StatusCodeSets::new([vec![429], vec![502, 503]]).unwrap()),
+            batch_size: Some(256),
+            polling_interval: Some(30),
+            min_sample_size: Some(1000),
+            timeout: Some(600),
+            max_query_failures: Some(3),
+            sequential: Some(true),
+            equivalence_margin: Some(0.01),
+            promotion_steps: Some(vec![1, 5, 25, 50, 100]),
+            latency_test: Some(LatencyTest::KolmogorovSmirnov),
+            min_effect_size: Some(0.1),
+            percentile_tolerance: Some(0.25),
+            margin: Some(0.002),
+            rollback_threshold: Some(0.9),
+            loss_threshold: Some(0.0005),
+        };
+        assert_eq!(config, expected);
+    }
+
+    /// Validation errors point at the offending value.
+    #[test]
+    fn invalid_values_are_located() {
+        let contents = "batch-size = 10\nalpha = 1.5\n";
+        let err = DeployConfig::parse("canary.toml", contents.to_owned()).unwrap_err();
+        let ConfigError::Invalid(invalid) = err else {
+            panic!("expected an invalid config error, found {err:?}");
+        };
+        let span = invalid.span.expect("the error should have a location");
+        assert_eq!(&contents[span.offset()..span.offset() + span.len()], "1.5");
+    }
+
+    #[test]
+    fn rejects_overlapping_code_sets() {
+        let contents = "status-code-sets = [[502, 503], [503]]\n";
+        let err = DeployConfig::parse("canary.toml", contents.to_owned()).unwrap_err();
+        let ConfigError::Invalid(invalid) = err else {
+            panic!("expected an invalid config error, found {err:?}");
+        };
+        let span = invalid.span.expect("the error should have a location");
+        assert_eq!(
+            &contents[span.offset()..span.offset() + span.len()],
+            "[[502, 503], [503]]"
+        );
+    }
+
+    /// Bayesian probabilities must be below 1, and losses can't be negative.
+    #[test]
+    fn rejects_out_of_range_bayesian_thresholds() {
+        for (key, value) in [
+            ("rollback-threshold", "1.5"),
+            ("rollback-threshold", "0.0"),
+            ("loss-threshold", "-0.001"),
+            ("margin", "-0.01"),
+        ] {
+            let contents = format!("[bayesian]\n{key} = {value}\n");
+            let err = DeployConfig::parse("canary.toml", contents.clone()).unwrap_err();
+            let ConfigError::Invalid(invalid) = err else {
+                panic!("expected an invalid config error, found {err:?}");
+            };
+            let span = invalid.span.expect("the error should have a location");
+            assert_eq!(&contents[span.offset()..span.offset() + span.len()], value);
+        }
+    }
+
+    #[test]
+    fn rejects_unordered_promotion_steps() {
+        let contents = "[promotion]\nsteps = [50, 5, 100]\n";
+        let err = DeployConfig::parse("canary.toml", contents.to_owned()).unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)));
+    }
+
+    #[test]
+    fn parses_alb_shifter() {
+        let contents = r#"
+            [shifter]
+            type = "alb-listener-rule"
+            rule-arn = "rule"
+            baseline-target-group = "blue"
+            canary-target-group = "green"
+        "#;
+        let config = DeployConfig::parse("canary.toml", contents.to_owned()).unwrap();
+        assert_eq!(
+            config.shifter,
+            Some(ShifterConfig::AlbListenerRule {
+                rule_arn: "rule".to_owned(),
+                baseline_target_group: "blue".to_owned(),
+                canary_target_group: "green".to_owned(),
+                endpoint_url: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parses_prometheus_observer() {
+        let contents = r#"
+            [observer]
+            type = "prometheus"
+            url = "http://localhost:9090"
+            version-label = "deployment"
+        "#;
+        let config = DeployConfig::parse("canary.toml", contents.to_owned()).unwrap();
+        assert_eq!(
+            config.observer,
+            Some(ObserverConfig::Prometheus {
+                url: Some("http://localhost:9090".to_owned()),
+                metric: None,
+                version_label: Some("deployment".to_owned()),
+                code_label: None,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let contents = "[observer]\ntype = \"cloudwatch-logs\"\nlog-grup = \"api\"\n";
+        let err = DeployConfig::parse("canary.toml", contents.to_owned()).unwrap_err();
+        let ConfigError::Invalid(invalid) = err else {
+            panic!("expected an invalid config error, found {err:?}");
+        };
+        assert!(invalid.span.is_some());
+    }
+}
@@ -1,5 +1,8 @@
+pub use file::{ConfigError, DeployConfig, ObserverConfig, ShifterConfig};
 pub use flags::Flags;
 
 mod colors;
 mod command;
+/// The deployment config file, `canary.toml`.
+mod file;
 mod flags;
@@ -4,7 +4,7 @@ pub use config::Flags;
 
 /// An adapter connects to some observable resource (like CloudWatch) and
 /// emits events, like failed and succeeded requests.
-mod adapter;
+pub mod adapter;
 /// Contains the dispatch logic for running individual CLI subcommands.
 /// The CLI's main function calls into these entrypoints for each subcommand.
 mod cmd;
@@ -12,6 +12,11 @@ mod cmd;
 mod config;
 /// This is the data pipeline responsible for the control flow
 /// of data from observers into number crunchers.
-mod pipeline;
+pub mod pipeline;
+/// The promotion plan walks a canary through increasing shares of
+/// traffic, testing it against the control group at every stage.
+pub mod promotion;
+/// A shifter moves traffic between the control and canary deployments.
+pub mod shifter;
 /// Our statistics library.
 pub mod stats;
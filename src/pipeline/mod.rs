@@ -1,13 +1,19 @@
 use async_trait::async_trait;
+use tokio::time::MissedTickBehavior;
 use tokio::{pin, time::interval};
 use tokio_stream::{wrappers::IntervalStream, StreamExt};
 
+pub use retry::{QueryError, RetryPolicy};
+
+/// Backoff and error budgets for failed queries.
+mod retry;
+
 /// The maximum number of observations that can be recevied before we
 /// recompute statistical significance.
 /// If this number is too low, we'll be performing compute-intensive
 /// statical tests very often. If this number is too high, we could
 /// be waiting too long before computing, which could permit us to promote more eagerly.
-const DEFAULT_BATCH_SIZE: usize = 512;
+pub const DEFAULT_BATCH_SIZE: usize = 512;
 
 /// An [Observer] watches a particular external system (like AWS CloudWatch Logs)
 /// and converts them into observations before emitting them as a stream.
@@ -15,28 +21,35 @@ const DEFAULT_BATCH_SIZE: usize = 512;
 pub trait Observer {
     /// The kind of object emitted by the Observer.
     type Item;
+    /// The kind of error returned when the external system can't be queried.
+    type Error;
 
     /// The [query] method will query the observable external system on demand
     /// and produce a collection of observations. This collection of observations
     /// is supposed to represent the set that occurred since the last time this
-    /// function was called.
-    // TODO: This should return a result which we should account for in error handling.
-    async fn query(&mut self) -> Vec<Self::Item>;
+    /// function was called. An empty collection means no observations occurred,
+    /// which is distinct from failing to query the system.
+    async fn query(&mut self) -> Result<Vec<Self::Item>, Self::Error>;
 }
 
-// TODO: Add a call to chunk_timeout to ensure that items are arriving after a particular
-//       amount of time.
 /// [repeat_query] runs the query on an interval and returns a stream of items.
-/// This function runs indefinitely.
+/// Failed queries are emitted as errors and retried after a backoff. Once the
+/// retry policy's error budget is exhausted, a final error is emitted and the
+/// stream ends. Otherwise, this function runs indefinitely.
 pub fn repeat_query<T: Observer>(
     mut observer: T,
     duration: tokio::time::Duration,
-) -> impl tokio_stream::Stream<Item = T::Item> {
+    policy: RetryPolicy,
+) -> impl tokio_stream::Stream<Item = Result<T::Item, QueryError<T::Error>>> {
     // • Everything happens in this stream closure, which desugars
     //   into a background thread and a channel write at yield points.
     async_stream::stream! {
-        // • Initialize a timer that fires every interval.
-        let timer = IntervalStream::new(interval(duration));
+        // • Initialize a timer that fires every interval. If we spend
+        //   longer than an interval retrying, the timer waits a full
+        //   interval instead of firing several times in a row.
+        let mut timer = interval(duration);
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let timer = IntervalStream::new(timer);
         // • The timer must be pinned to use in an iterator
         //   because we must promise that its address must not
         //   be moved between iterations.
@@ -44,29 +57,111 @@ pub fn repeat_query<T: Observer>(
         // Each iteration of the loop represents one unit of tiem.
         while timer.next().await.is_some() {
             // • We perform the query then dump the results into the stream.
-            let items = observer.query().await;
-            for item in items {
-                yield item;
+            //   If the query fails, we retry with backoff until it succeeds
+            //   or we run out of retries.
+            let mut failures = 0;
+            loop {
+                match observer.query().await {
+                    Ok(items) => {
+                        for item in items {
+                            yield Ok(item);
+                        }
+                        break;
+                    }
+                    Err(source) => {
+                        failures += 1;
+                        if failures >= policy.max_consecutive_failures {
+                            yield Err(QueryError::Exhausted { failures, source });
+                            return;
+                        }
+                        yield Err(QueryError::Failed(source));
+                        tokio::time::sleep(policy.backoff(failures)).await;
+                    }
+                }
             }
         }
     }
 }
 
-// TODO: Honestly, this function can be inlined where used.
 /// Batch observations together into maximally sized chunks, and dump
-/// them to a stream every so often.
-pub fn batch_observations<T: Observer>(
-    obs: impl tokio_stream::Stream<Item = T::Item>,
+/// them to a stream every so often. An aggregated observation counts as a
+/// single item, however many observations it stands for.
+pub fn batch_observations<I>(
+    obs: impl tokio_stream::Stream<Item = I>,
+    batch_size: usize,
     duration: tokio::time::Duration,
-) -> impl tokio_stream::Stream<Item = Vec<T::Item>> {
-    obs.chunks_timeout(DEFAULT_BATCH_SIZE, duration)
+) -> impl tokio_stream::Stream<Item = Vec<I>> {
+    obs.chunks_timeout(batch_size, duration)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::VecDeque;
+
+    use async_trait::async_trait;
+    use futures_util::{pin_mut, StreamExt};
     use static_assertions::assert_obj_safe;
+    use tokio::time::Duration;
+
+    use super::{repeat_query, Observer, QueryError, RetryPolicy};
 
-    use super::Observer;
+    assert_obj_safe!(Observer<Item = (), Error = ()>);
 
-    assert_obj_safe!(Observer<Item = ()>);
+    /// This observer replays a scripted sequence of query results,
+    /// then returns no items forever.
+    struct ScriptedObserver(VecDeque<Result<Vec<u32>, &'static str>>);
+
+    #[async_trait]
+    impl Observer for ScriptedObserver {
+        type Item = u32;
+        type Error = &'static str;
+
+        async fn query(&mut self) -> Result<Vec<u32>, &'static str> {
+            self.0.pop_front().unwrap_or(Ok(vec![]))
+        }
+    }
+
+    fn policy(max_consecutive_failures: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_consecutive_failures,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+        }
+    }
+
+    /// Failed queries surface as errors, and are retried until they succeed.
+    #[tokio::test]
+    async fn failures_are_retried() {
+        let observer = ScriptedObserver(VecDeque::from([
+            Err("throttled"),
+            Err("throttled"),
+            Ok(vec![1, 2]),
+        ]));
+        let stream = repeat_query(observer, Duration::from_millis(1), policy(3));
+        pin_mut!(stream);
+        let items: Vec<_> = stream.take(4).collect().await;
+        assert!(matches!(items[0], Err(QueryError::Failed("throttled"))));
+        assert!(matches!(items[1], Err(QueryError::Failed("throttled"))));
+        assert!(matches!(items[2], Ok(1)));
+        assert!(matches!(items[3], Ok(2)));
+    }
+
+    /// After too many consecutive failures, the stream reports that
+    /// it's given up and ends.
+    #[tokio::test]
+    async fn stream_ends_when_budget_is_exhausted() {
+        let observer =
+            ScriptedObserver(VecDeque::from([Err("a"), Err("b"), Err("c"), Ok(vec![1])]));
+        let stream = repeat_query(observer, Duration::from_millis(1), policy(3));
+        pin_mut!(stream);
+        let items: Vec<_> = stream.collect().await;
+        assert_eq!(items.len(), 3);
+        assert!(matches!(
+            items[2],
+            Err(QueryError::Exhausted {
+                failures: 3,
+                source: "c"
+            })
+        ));
+    }
 }
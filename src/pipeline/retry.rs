@@ -0,0 +1,94 @@
+use rand::Rng;
+use thiserror::Error;
+use tokio::time::Duration;
+
+/// The number of consecutive failed queries we tolerate before giving up.
+const DEFAULT_MAX_CONSECUTIVE_FAILURES: u32 = 5;
+/// How long we wait before retrying the first failed query.
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// The longest we'll ever wait between retries.
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A [RetryPolicy] decides how long to wait after a failed query,
+/// and how many consecutive failures we tolerate before the deployment
+/// is considered inconclusive.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Once this many queries in a row have failed, we stop retrying.
+    pub max_consecutive_failures: u32,
+    /// The backoff before the first retry. It doubles after every failure.
+    pub initial_backoff: Duration,
+    /// The backoff never grows beyond this ceiling.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_consecutive_failures: DEFAULT_MAX_CONSECUTIVE_FAILURES,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns how long to wait after the given number of consecutive failures.
+    /// The backoff grows exponentially up to a ceiling, and we wait a random
+    /// amount of time between zero and the backoff ("full jitter") so many
+    /// canaries throttled at once don't retry in lockstep.
+    pub fn backoff(&self, failures: u32) -> Duration {
+        let exponent = failures.saturating_sub(1).min(31);
+        let ceiling = self
+            .initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+        ceiling.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
+
+/// A [QueryError] is emitted by [repeat_query](super::repeat_query)
+/// when the observer fails to query the external system.
+#[derive(Error, Debug)]
+pub enum QueryError<E> {
+    /// A single query failed. It will be retried after a backoff.
+    #[error("query failed, retrying")]
+    Failed(#[source] E),
+    /// Too many consecutive queries failed, so we've stopped querying.
+    /// No further items are emitted after this error.
+    #[error("giving up after {failures} consecutive query failures")]
+    Exhausted {
+        failures: u32,
+        #[source]
+        source: E,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::Duration;
+
+    use super::RetryPolicy;
+
+    /// The jittered backoff never exceeds the exponential ceiling.
+    #[test]
+    fn backoff_is_bounded() {
+        let policy = RetryPolicy {
+            max_consecutive_failures: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(1000),
+        };
+        for (failures, ceiling) in [
+            (1, 100),
+            (2, 200),
+            (3, 400),
+            (4, 800),
+            (5, 1000),
+            (40, 1000),
+        ] {
+            for _ in 0..100 {
+                assert!(policy.backoff(failures) <= Duration::from_millis(ceiling));
+            }
+        }
+    }
+}
@@ -0,0 +1,683 @@
+use tokio::pin;
+use tokio::time::Duration;
+use tokio_stream::{Stream, StreamExt};
+
+use crate::config::ConfigError;
+use crate::pipeline::QueryError;
+use crate::shifter::{ShifterError, TrafficShifter};
+use crate::stats::{
+    AggregatedObservation, Alpha, ChiSquareEngine, DecisionEngine, Diagnostics, ErrorRates,
+    LatencyResult, Observation, SequentialEngine, Verdict,
+};
+
+/// A [Stage] is one step of a promotion plan: the canary receives a
+/// fixed share of traffic until enough observations have accumulated
+/// to test it against the control group.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Stage {
+    /// The percentage of traffic routed to the canary during this stage.
+    /// When absent, traffic is left however it was already split.
+    pub weight: Option<u8>,
+    /// The number of observations required before the stage can pass or fail.
+    pub min_observations: usize,
+}
+
+/// A [PromotionPlan] is the sequence of stages a canary must pass
+/// through before it receives all of the traffic.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PromotionPlan {
+    stages: Vec<Stage>,
+}
+
+impl PromotionPlan {
+    /// Build a plan that shifts traffic through each step in turn, e.g.
+    /// 1% → 5% → 25% → 50% → 100%. Every step below 100% is a stage
+    /// that must pass before moving on; reaching 100% is the promotion.
+    ///
+    /// The steps must be increasing percentages, at least one below 100%.
+    pub fn from_steps(steps: &[u8], min_observations: usize) -> Result<Self, ConfigError> {
+        let increasing = steps.windows(2).all(|pair| pair[0] < pair[1]);
+        let in_range = steps.iter().all(|step| (1..=100).contains(step));
+        if !increasing || !in_range {
+            return Err(ConfigError::Steps {
+                steps: steps.to_vec(),
+            });
+        }
+        let stages: Vec<_> = steps
+            .iter()
+            .filter(|weight| **weight < 100)
+            .map(|weight| Stage {
+                weight: Some(*weight),
+                min_observations,
+            })
+            .collect();
+        if stages.is_empty() {
+            return Err(ConfigError::Steps {
+                steps: steps.to_vec(),
+            });
+        }
+        Ok(Self { stages })
+    }
+
+    /// Build a plan with a single stage which observes the canary
+    /// without changing how traffic is split.
+    pub fn single_stage(min_observations: usize) -> Self {
+        Self {
+            stages: vec![Stage {
+                weight: None,
+                min_observations,
+            }],
+        }
+    }
+
+    /// Returns the stages of this plan, in order.
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+}
+
+/// The [PromotionState] tracks how far along its plan a canary is.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PromotionState {
+    /// The canary is being observed during the stage at this index.
+    Observing { stage: usize },
+    /// The canary passed every stage and should receive all traffic.
+    Promoted,
+    /// The canary failed a stage and should receive no traffic.
+    RolledBack,
+    /// We stopped before reaching a decision, e.g. because we ran out of
+    /// time or the observer kept failing.
+    Abandoned,
+}
+
+impl PromotionState {
+    /// Returns true if no more transitions can happen.
+    pub fn is_finished(self) -> bool {
+        !matches!(self, Self::Observing { .. })
+    }
+}
+
+/// A [Promotion] is a state machine which walks a canary through its
+/// [PromotionPlan]. Each stage collects observations into a fresh
+/// [DecisionEngine], a [ChiSquareEngine] unless another is chosen; once a
+/// stage has enough observations, the canary advances if the engine finds
+/// no reason to roll it back.
+///
+/// Sequential engines, like the [SequentialEngine], are consulted after
+/// every batch: the canary is rolled back as soon as the engine says so,
+/// and advances early once the engine would promote it. An inconclusive
+/// verdict at the end of a stage is no reason to roll back.
+///
+/// To gate on latency as well as status codes, test each stage with a
+/// [VerdictPolicy](crate::stats::VerdictPolicy) that requires both.
+pub struct Promotion {
+    plan: PromotionPlan,
+    state: PromotionState,
+    /// Collects the observations made during the current stage.
+    engine: Box<dyn DecisionEngine>,
+    /// Builds the engine for each new stage.
+    new_engine: EngineFactory,
+    /// The alpha cutoff used for every stage's test.
+    alpha_cutoff: Alpha,
+    /// The number of observations made across every stage.
+    total_observations: usize,
+    /// The diagnostics of the most recent verdict, if any stage has been tested.
+    last_diagnostics: Option<Diagnostics>,
+    /// The error rates behind the most recent verdict, if the engine compares them.
+    last_error_rates: Option<ErrorRates>,
+    /// The result of the most recent latency test, if any.
+    last_latency_result: Option<LatencyResult>,
+}
+
+/// An [EngineFactory] builds a fresh decision engine for each stage.
+pub type EngineFactory = Box<dyn Fn() -> Box<dyn DecisionEngine> + Send>;
+
+impl Promotion {
+    /// Start a promotion at the first stage of the plan, testing
+    /// each stage with a chi-square test at the given alpha cutoff.
+    pub fn new(plan: PromotionPlan, alpha_cutoff: Alpha) -> Self {
+        let new_engine: EngineFactory =
+            Box::new(move || Box::new(ChiSquareEngine::new().with_alpha_cutoff(alpha_cutoff)));
+        Self {
+            plan,
+            state: PromotionState::Observing { stage: 0 },
+            engine: new_engine(),
+            new_engine,
+            alpha_cutoff,
+            total_observations: 0,
+            last_diagnostics: None,
+            last_error_rates: None,
+            last_latency_result: None,
+        }
+    }
+
+    /// Test every stage with a decision engine built by the factory.
+    pub fn with_engine(self, new_engine: EngineFactory) -> Self {
+        Self {
+            engine: new_engine(),
+            new_engine,
+            ..self
+        }
+    }
+
+    /// Test every stage with the always-valid sequential test, so a
+    /// failing canary can be rolled back after any batch without
+    /// inflating the false-positive rate.
+    pub fn with_sequential_testing(self) -> Self {
+        let alpha_cutoff = self.alpha_cutoff;
+        self.with_engine(Box::new(move || {
+            Box::new(SequentialEngine::new().with_alpha_cutoff(alpha_cutoff))
+        }))
+    }
+
+    /// Returns the current state of the promotion.
+    pub fn state(&self) -> PromotionState {
+        self.state
+    }
+
+    /// Returns the stage currently being observed, if any.
+    pub fn stage(&self) -> Option<&Stage> {
+        match self.state {
+            PromotionState::Observing { stage } => self.plan.stages.get(stage),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of observations made across every stage.
+    pub fn total_observations(&self) -> usize {
+        self.total_observations
+    }
+
+    /// Returns the diagnostics of the most recent verdict.
+    pub fn last_diagnostics(&self) -> Option<&Diagnostics> {
+        self.last_diagnostics.as_ref()
+    }
+
+    /// Returns the groups' error rates, with confidence intervals,
+    /// as of the most recent verdict.
+    pub fn last_error_rates(&self) -> Option<ErrorRates> {
+        self.last_error_rates
+    }
+
+    /// Returns the result of the most recent latency test, if the engine
+    /// compares latencies.
+    pub fn last_latency_result(&self) -> Option<LatencyResult> {
+        self.last_latency_result
+    }
+
+    /// Record an observation made during the current stage. Observations
+    /// made after the promotion has finished are ignored.
+    pub fn add_observation(&mut self, obs: Observation) {
+        if !self.state.is_finished() {
+            self.total_observations += 1;
+            self.engine.add_observation(obs);
+        }
+    }
+
+    /// Record every observation an aggregate counts during the current
+    /// stage.
+    pub fn add_aggregate(&mut self, agg: AggregatedObservation) {
+        if !self.state.is_finished() {
+            self.total_observations += agg.count as usize;
+            self.engine.add_aggregate(agg);
+        }
+    }
+
+    /// Test the current stage and transition if it's done. A stage is done
+    /// when it has enough observations, or when a sequential engine would
+    /// promote the canary. The canary advances to the next stage (or is
+    /// promoted, after the final stage) when the engine finds no reason to
+    /// roll it back, and is rolled back otherwise. A sequential engine may
+    /// roll the canary back at any time.
+    pub fn evaluate(&mut self) -> PromotionState {
+        let PromotionState::Observing { stage } = self.state else {
+            return self.state;
+        };
+        // • The engine may need more observations from a group than the
+        //   stage does, e.g. while the canary only gets 1% of traffic.
+        let enough = self.engine.total_count() >= self.plan.stages[stage].min_observations
+            && self.engine.has_min_sample();
+        if !enough && !self.engine.is_sequential() {
+            return self.state;
+        }
+        let verdict = self.engine.evaluate();
+        self.last_diagnostics = Some(self.engine.diagnostics());
+        self.last_error_rates = self.engine.error_rates();
+        self.last_latency_result = self.engine.latency();
+        let done = enough || verdict == Verdict::Promote;
+        if verdict == Verdict::Rollback {
+            self.state = PromotionState::RolledBack;
+        } else if done && stage + 1 < self.plan.stages.len() {
+            // • Traffic is split differently in the next stage, so
+            //   its observations are tested separately.
+            self.engine = (self.new_engine)();
+            self.state = PromotionState::Observing { stage: stage + 1 };
+        } else if done {
+            self.state = PromotionState::Promoted;
+        }
+        self.state
+    }
+
+    /// Stop the promotion without a decision.
+    pub fn abandon(&mut self) {
+        if !self.state.is_finished() {
+            self.state = PromotionState::Abandoned;
+        }
+    }
+}
+
+/// A [Sample] is an item of the pipeline that a [Promotion] can record:
+/// either a single [Observation], or an [AggregatedObservation] counting
+/// many at once.
+pub trait Sample {
+    /// Record the sample during the promotion's current stage.
+    fn record(self, promotion: &mut Promotion);
+}
+
+impl Sample for Observation {
+    fn record(self, promotion: &mut Promotion) {
+        promotion.add_observation(self);
+    }
+}
+
+impl Sample for AggregatedObservation {
+    fn record(self, promotion: &mut Promotion) {
+        promotion.add_aggregate(self);
+    }
+}
+
+/// A [DriveEvent] is something that happened while driving a promotion
+/// which doesn't change its outcome, but which the caller may want to
+/// tell the user about.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DriveEvent<E> {
+    /// A query failed, and will be retried after a backoff.
+    QueryFailed(E),
+    /// Too many consecutive queries failed, so the promotion is abandoned.
+    GaveUp { failures: u32, source: E },
+    /// A stage passed, and the canary now receives this percentage of traffic.
+    Advanced { weight: u8 },
+}
+
+/// Drive the promotion with batches of observations from the pipeline until
+/// it finishes, moving traffic with the shifter as the canary advances. After
+/// each batch, the current stage is evaluated. If the timeout elapses, the
+/// stream ends, or the observer gives up after too many failed queries, the
+/// promotion is abandoned: a lack of data must never be mistaken for a
+/// healthy canary, so traffic is rolled back just as if the canary had failed.
+/// Failed queries and advancing stages are passed to `report` as they happen.
+pub async fn drive<S: Sample, E>(
+    promotion: &mut Promotion,
+    batches: impl Stream<Item = Vec<Result<S, QueryError<E>>>>,
+    shifter: &mut dyn TrafficShifter,
+    timeout: Duration,
+    mut report: impl FnMut(DriveEvent<E>),
+) -> Result<PromotionState, ShifterError> {
+    let deadline = tokio::time::sleep(timeout);
+    pin!(batches);
+    pin!(deadline);
+    if let Some(Stage {
+        weight: Some(weight),
+        ..
+    }) = promotion.stage()
+    {
+        shifter.set_canary_weight(*weight).await?;
+    }
+    while !promotion.state().is_finished() {
+        tokio::select! {
+            _ = &mut deadline => promotion.abandon(),
+            batch = batches.next() => {
+                let Some(batch) = batch else {
+                    promotion.abandon();
+                    break;
+                };
+                for item in batch {
+                    match item {
+                        Ok(sample) => sample.record(promotion),
+                        Err(QueryError::Failed(source)) => {
+                            report(DriveEvent::QueryFailed(source));
+                        }
+                        Err(QueryError::Exhausted { failures, source }) => {
+                            report(DriveEvent::GaveUp { failures, source });
+                            promotion.abandon();
+                        }
+                    }
+                }
+                let before = promotion.state();
+                let after = promotion.evaluate();
+                if before != after {
+                    if let Some(Stage { weight: Some(weight), .. }) = promotion.stage() {
+                        report(DriveEvent::Advanced { weight: *weight });
+                        shifter.set_canary_weight(*weight).await?;
+                    }
+                }
+            }
+        }
+    }
+    match promotion.state() {
+        PromotionState::Promoted => shifter.promote().await?,
+        PromotionState::RolledBack | PromotionState::Abandoned => shifter.rollback().await?,
+        PromotionState::Observing { .. } => unreachable!("the promotion finished"),
+    }
+    Ok(promotion.state())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use async_trait::async_trait;
+    use pretty_assertions::assert_eq;
+    use tokio::time::Duration;
+
+    use super::{drive, DriveEvent, Promotion, PromotionPlan, PromotionState, Sample};
+    use crate::config::ConfigError;
+    use crate::pipeline::{batch_observations, repeat_query, Observer, QueryError, RetryPolicy};
+    use crate::shifter::{ShifterError, TrafficShifter};
+    use crate::stats::fixtures;
+    use crate::stats::{
+        AggregatedObservation, BayesianEngine, ChiSquareEngine, Correction, Group, LatencyEngine,
+        LatencyTest, Observation, StatusCategory, VerdictPolicy, DEFAULT_ALPHA_CUTOFF,
+    };
+
+    /// This observer replays a scripted sequence of query results.
+    /// Once the script runs out, every query fails.
+    struct FakeObserver<T>(VecDeque<Vec<T>>);
+
+    #[async_trait]
+    impl<T: Send> Observer for FakeObserver<T> {
+        type Item = T;
+        type Error = &'static str;
+
+        async fn query(&mut self) -> Result<Vec<T>, &'static str> {
+            self.0.pop_front().ok_or("no more observations")
+        }
+    }
+
+    /// This shifter records every weight it's asked to route to the canary.
+    #[derive(Default)]
+    struct RecordingShifter(Vec<u8>);
+
+    #[async_trait]
+    impl TrafficShifter for RecordingShifter {
+        async fn set_canary_weight(&mut self, percent: u8) -> Result<(), ShifterError> {
+            self.0.push(percent);
+            Ok(())
+        }
+
+        async fn promote(&mut self) -> Result<(), ShifterError> {
+            self.set_canary_weight(100).await
+        }
+
+        async fn rollback(&mut self) -> Result<(), ShifterError> {
+            self.set_canary_weight(0).await
+        }
+    }
+
+    /// Build a query result with the given number of successes and
+    /// server errors from each group.
+    fn traffic(control: (usize, usize), canary: (usize, usize)) -> Vec<Observation> {
+        [
+            fixtures::traffic(Group::Control, control.0, control.1),
+            fixtures::traffic(Group::Experimental, canary.0, canary.1),
+        ]
+        .concat()
+    }
+
+    /// Run the promotion against the scripted queries, returning the
+    /// promotion and the weights the canary was routed.
+    async fn run<S: Sample + Send + 'static>(
+        plan: PromotionPlan,
+        script: Vec<Vec<S>>,
+    ) -> (Promotion, Vec<u8>) {
+        let batch_size = script.first().map_or(1, Vec::len);
+        let observer = FakeObserver(script.into());
+        let policy = RetryPolicy {
+            max_consecutive_failures: 1,
+            ..RetryPolicy::default()
+        };
+        let observations = repeat_query(observer, Duration::from_millis(1), policy);
+        // Each query produces one batch.
+        let batches = batch_observations(observations, batch_size, Duration::from_millis(1));
+        let mut promotion = Promotion::new(plan, DEFAULT_ALPHA_CUTOFF);
+        let mut shifter = RecordingShifter::default();
+        drive(
+            &mut promotion,
+            batches,
+            &mut shifter,
+            Duration::from_secs(5),
+            |_| {},
+        )
+        .await
+        .unwrap();
+        (promotion, shifter.0)
+    }
+
+    #[test]
+    fn plan_excludes_full_promotion() {
+        let plan = PromotionPlan::from_steps(&[1, 5, 25, 50, 100], 100).unwrap();
+        let weights: Vec<_> = plan.stages().iter().map(|stage| stage.weight).collect();
+        assert_eq!(weights, vec![Some(1), Some(5), Some(25), Some(50)]);
+    }
+
+    /// Steps that don't stage the canary are refused rather than panicking.
+    #[test]
+    fn invalid_steps_are_rejected() {
+        for steps in [&[][..], &[100], &[50, 10, 100], &[0, 50, 100], &[10, 150]] {
+            assert!(
+                matches!(
+                    PromotionPlan::from_steps(steps, 100),
+                    Err(ConfigError::Steps { .. })
+                ),
+                "{steps:?}"
+            );
+        }
+    }
+
+    /// A stage without observations never passes, even if
+    /// neither the plan nor the engine asks for any.
+    #[test]
+    fn empty_stage_never_passes() {
+        let plan = PromotionPlan::from_steps(&[10, 100], 0).unwrap();
+        let mut promotion =
+            Promotion::new(plan, DEFAULT_ALPHA_CUTOFF).with_engine(Box::new(|| {
+                Box::new(ChiSquareEngine::new().with_min_group_size(0))
+            }));
+        assert_eq!(promotion.evaluate(), PromotionState::Observing { stage: 0 });
+        for observation in fixtures::traffic(Group::Control, 100, 0) {
+            promotion.add_observation(observation);
+        }
+        assert_eq!(promotion.evaluate(), PromotionState::Observing { stage: 0 });
+    }
+
+    /// A stage won't pass or fail until it has enough observations.
+    #[test]
+    fn stage_waits_for_enough_observations() {
+        let plan = PromotionPlan::from_steps(&[10, 100], 200).unwrap();
+        let mut promotion = Promotion::new(plan, DEFAULT_ALPHA_CUTOFF);
+        for observation in traffic((50, 0), (0, 50)) {
+            promotion.add_observation(observation);
+        }
+        assert_eq!(promotion.evaluate(), PromotionState::Observing { stage: 0 });
+    }
+
+    /// With sequential testing, a failing canary is rolled back
+    /// before the stage has collected enough observations.
+    #[test]
+    fn sequential_testing_rolls_back_early() {
+        let plan = PromotionPlan::from_steps(&[10, 100], 10_000).unwrap();
+        let mut promotion = Promotion::new(plan, DEFAULT_ALPHA_CUTOFF).with_sequential_testing();
+        for observation in traffic((99, 1), (60, 40)) {
+            promotion.add_observation(observation);
+        }
+        assert_eq!(promotion.evaluate(), PromotionState::RolledBack);
+        assert_eq!(promotion.total_observations(), 200);
+    }
+
+    /// A canary that serves the same status codes, but more slowly,
+    /// is rolled back when a policy also requires its latency to pass.
+    #[test]
+    fn latency_regression_rolls_back() {
+        let plan = PromotionPlan::from_steps(&[10, 100], 200).unwrap();
+        let mut promotion =
+            Promotion::new(plan, DEFAULT_ALPHA_CUTOFF).with_engine(Box::new(|| {
+                Box::new(
+                    VerdictPolicy::new()
+                        .with_correction(Correction::Holm, DEFAULT_ALPHA_CUTOFF)
+                        .require("errors", ChiSquareEngine::new())
+                        .require("latency", LatencyEngine::new(LatencyTest::MannWhitney)),
+                )
+            }));
+        for (i, observation) in traffic((100, 0), (100, 0)).into_iter().enumerate() {
+            let millis = match observation.group {
+                Group::Control => 100 + i as u64 % 50,
+                Group::Experimental => 200 + i as u64 % 50,
+            };
+            promotion.add_observation(observation.with_latency(Duration::from_millis(millis)));
+        }
+        assert_eq!(promotion.evaluate(), PromotionState::RolledBack);
+        assert!(promotion
+            .last_diagnostics()
+            .is_some_and(|diagnostics| diagnostics.engine == "latency"));
+        assert!(promotion
+            .last_latency_result()
+            .is_some_and(|result| result.significant));
+    }
+
+    /// Any decision engine can be chosen. A Bayesian engine rolls back a
+    /// canary that's almost surely worse before the stage is complete.
+    #[test]
+    fn engine_can_be_chosen() {
+        let plan = PromotionPlan::from_steps(&[10, 100], 10_000).unwrap();
+        let mut promotion = Promotion::new(plan, DEFAULT_ALPHA_CUTOFF)
+            .with_engine(Box::new(|| Box::new(BayesianEngine::new())));
+        for observation in traffic((99, 1), (60, 40)) {
+            promotion.add_observation(observation);
+        }
+        assert_eq!(promotion.evaluate(), PromotionState::RolledBack);
+        let diagnostics = promotion.last_diagnostics().unwrap();
+        assert_eq!(diagnostics.engine, "bayesian");
+        assert!(diagnostics.get("P(canary worse)").unwrap() > 0.999);
+        let rates = promotion.last_error_rates().unwrap();
+        assert!(rates.experimental.rate > rates.control.rate);
+        assert!(rates.difference.interval.lower > 0.0);
+    }
+
+    /// A healthy canary passes through every stage, then is promoted.
+    #[tokio::test]
+    async fn healthy_canary_is_promoted_stage_by_stage() {
+        let plan = PromotionPlan::from_steps(&[5, 50, 100], 200).unwrap();
+        let healthy = traffic((95, 5), (95, 5));
+        let (promotion, weights) = run(plan, vec![healthy.clone(), healthy]).await;
+        assert_eq!(promotion.state(), PromotionState::Promoted);
+        assert_eq!(promotion.total_observations(), 400);
+        assert_eq!(weights, vec![5, 50, 100]);
+    }
+
+    /// A failure in any stage rolls the canary back.
+    #[tokio::test]
+    async fn failing_stage_rolls_back() {
+        let plan = PromotionPlan::from_steps(&[5, 50, 100], 200).unwrap();
+        let healthy = traffic((95, 5), (95, 5));
+        let failing = traffic((95, 5), (60, 40));
+        let (promotion, weights) = run(plan, vec![healthy, failing]).await;
+        assert_eq!(promotion.state(), PromotionState::RolledBack);
+        assert_eq!(weights, vec![5, 50, 0]);
+    }
+
+    /// Sources that report counts drive the promotion with aggregates,
+    /// which count towards each stage like the observations they stand for.
+    #[tokio::test]
+    async fn aggregates_drive_the_promotion() {
+        let plan = PromotionPlan::from_steps(&[5, 50, 100], 20_000).unwrap();
+        let minute = |canary_errors| {
+            vec![
+                AggregatedObservation::new(Group::Control, StatusCategory::_2XX, 9_950),
+                AggregatedObservation::new(Group::Control, StatusCategory::_5XX, 50),
+                AggregatedObservation::new(Group::Experimental, StatusCategory::_2XX, 9_950),
+                AggregatedObservation::new(
+                    Group::Experimental,
+                    StatusCategory::_5XX,
+                    canary_errors,
+                ),
+            ]
+        };
+        let (promotion, weights) = run(plan, vec![minute(50), minute(400)]).await;
+        assert_eq!(promotion.state(), PromotionState::RolledBack);
+        assert_eq!(promotion.total_observations(), 40_350);
+        assert_eq!(weights, vec![5, 50, 0]);
+    }
+
+    /// Timing out before any observations arrive abandons the
+    /// promotion and rolls the canary back, rather than promoting it.
+    #[tokio::test]
+    async fn timeout_without_observations_rolls_back() {
+        let plan = PromotionPlan::from_steps(&[5, 50, 100], 200).unwrap();
+        let mut promotion = Promotion::new(plan, DEFAULT_ALPHA_CUTOFF);
+        let mut shifter = RecordingShifter::default();
+        let batches = tokio_stream::pending::<Vec<Result<Observation, QueryError<&str>>>>();
+        let state = drive(
+            &mut promotion,
+            batches,
+            &mut shifter,
+            Duration::from_millis(10),
+            |_| {},
+        )
+        .await
+        .unwrap();
+        assert_eq!(state, PromotionState::Abandoned);
+        assert_eq!(promotion.total_observations(), 0);
+        assert_eq!(shifter.0, vec![5, 0]);
+    }
+
+    /// Failed queries and advancing stages are reported to the caller,
+    /// and giving up on the queries abandons the promotion.
+    #[tokio::test]
+    async fn events_are_reported() {
+        let plan = PromotionPlan::from_steps(&[5, 50, 100], 200).unwrap();
+        let healthy = traffic((95, 5), (95, 5)).into_iter().map(Ok).collect();
+        let batches = tokio_stream::iter(vec![
+            healthy,
+            vec![Err(QueryError::Failed("throttled"))],
+            vec![Err(QueryError::Exhausted {
+                failures: 2,
+                source: "throttled",
+            })],
+        ]);
+        let mut promotion = Promotion::new(plan, DEFAULT_ALPHA_CUTOFF);
+        let mut shifter = RecordingShifter::default();
+        let mut events = Vec::new();
+        let state = drive(
+            &mut promotion,
+            batches,
+            &mut shifter,
+            Duration::from_secs(5),
+            |event| events.push(event),
+        )
+        .await
+        .unwrap();
+        assert_eq!(state, PromotionState::Abandoned);
+        assert_eq!(shifter.0, vec![5, 50, 0]);
+        assert_eq!(
+            events,
+            vec![
+                DriveEvent::Advanced { weight: 50 },
+                DriveEvent::QueryFailed("throttled"),
+                DriveEvent::GaveUp {
+                    failures: 2,
+                    source: "throttled",
+                },
+            ]
+        );
+    }
+
+    /// Running out of observations is never mistaken for a healthy canary.
+    #[tokio::test]
+    async fn missing_observations_abandon_the_promotion() {
+        let plan = PromotionPlan::from_steps(&[5, 50, 100], 200).unwrap();
+        let healthy = traffic((95, 5), (95, 5));
+        let (promotion, weights) = run(plan, vec![healthy]).await;
+        assert_eq!(promotion.state(), PromotionState::Abandoned);
+        assert_eq!(weights, vec![5, 50, 0]);
+    }
+}
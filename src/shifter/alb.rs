@@ -0,0 +1,425 @@
+use async_trait::async_trait;
+use aws_sdk_elasticloadbalancingv2::types::{
+    Action, ActionTypeEnum, ForwardActionConfig, Rule, TargetGroupTuple,
+};
+use aws_sdk_elasticloadbalancingv2::Client;
+
+use super::{ShifterError, TrafficShifter};
+
+/// An [AlbRuleShifter] splits traffic between two target groups by
+/// adjusting the weights of an Application Load Balancer listener rule's
+/// forward action.
+///
+/// Before every change we read the rule back and compare its weights with
+/// the ones we last set. If they differ, someone else is shifting traffic
+/// on the same rule, and we report [ShifterError::Drift] instead of
+/// silently overwriting their change.
+pub struct AlbRuleShifter {
+    /// The AWS client for reading and modifying listener rules.
+    client: Client,
+    /// The ARN of the listener rule whose forward action we adjust.
+    rule_arn: String,
+    /// The ARN of the target group currently serving production traffic.
+    baseline_target_group: String,
+    /// The ARN of the target group being canaried.
+    canary_target_group: String,
+    /// The (baseline, canary) weights we last set, if any.
+    expected: Option<(i32, i32)>,
+}
+
+impl AlbRuleShifter {
+    /// Create a new shifter for the given listener rule.
+    pub fn new(
+        client: Client,
+        rule_arn: impl Into<String>,
+        baseline_target_group: impl Into<String>,
+        canary_target_group: impl Into<String>,
+    ) -> Self {
+        Self {
+            client,
+            rule_arn: rule_arn.into(),
+            baseline_target_group: baseline_target_group.into(),
+            canary_target_group: canary_target_group.into(),
+            expected: None,
+        }
+    }
+
+    /// Build an ELBv2 client from the ambient AWS configuration.
+    /// If an endpoint URL is provided, requests are sent there instead
+    /// of to AWS, which is useful for testing against a local stub.
+    pub async fn client(endpoint_url: Option<&str>) -> Client {
+        let mut loader = aws_config::from_env();
+        if let Some(url) = endpoint_url {
+            loader = loader.endpoint_url(url);
+        }
+        Client::new(&loader.load().await)
+    }
+
+    /// Read the listener rule.
+    async fn describe_rule(&self) -> Result<Rule, ShifterError> {
+        let output = self
+            .client
+            .describe_rules()
+            .rule_arns(&self.rule_arn)
+            .send()
+            .await
+            .map_err(|err| ShifterError::DescribeRules {
+                rule_arn: self.rule_arn.clone(),
+                source: Box::new(err),
+            })?;
+        output
+            .rules()
+            .first()
+            .cloned()
+            .ok_or_else(|| ShifterError::RuleNotFound {
+                rule_arn: self.rule_arn.clone(),
+            })
+    }
+
+    /// Returns the rule's (baseline, canary) weights. A target
+    /// group missing from the forward action receives no traffic.
+    fn weights(&self, rule: &Rule) -> Result<(i32, i32), ShifterError> {
+        let target_groups = rule
+            .actions()
+            .iter()
+            .find_map(forward_config)
+            .map(ForwardActionConfig::target_groups)
+            .ok_or_else(|| self.no_forward_action())?;
+        let weight_of = |arn: &str| {
+            target_groups
+                .iter()
+                .filter(|group| group.target_group_arn() == Some(arn))
+                .map(|group| group.weight().unwrap_or(1))
+                .sum::<i32>()
+        };
+        Ok((
+            weight_of(&self.baseline_target_group),
+            weight_of(&self.canary_target_group),
+        ))
+    }
+
+    /// Fail if the rule's weights differ from the ones we last set.
+    fn check_drift(&self, rule: &Rule) -> Result<(), ShifterError> {
+        let Some(expected) = self.expected else {
+            return Ok(());
+        };
+        let found = self.weights(rule)?;
+        if found != expected {
+            return Err(ShifterError::Drift {
+                rule_arn: self.rule_arn.clone(),
+                expected,
+                found,
+            });
+        }
+        Ok(())
+    }
+
+    /// Forward traffic to both target groups with the given weights.
+    /// Only the weights change: the rule's other actions, e.g. to
+    /// authenticate users first, and the forward action's stickiness
+    /// and any other target groups are written back as they were.
+    async fn modify_rule(
+        &mut self,
+        rule: &Rule,
+        baseline: i32,
+        canary: i32,
+    ) -> Result<(), ShifterError> {
+        if !rule
+            .actions()
+            .iter()
+            .any(|action| forward_config(action).is_some())
+        {
+            return Err(self.no_forward_action());
+        }
+        let actions = rule
+            .actions()
+            .iter()
+            .map(|action| match forward_config(action) {
+                Some(forward) => Action::builder()
+                    .r#type(ActionTypeEnum::Forward)
+                    .set_order(action.order())
+                    .forward_config(self.reweigh(forward, baseline, canary))
+                    .build(),
+                None => action.clone(),
+            })
+            .collect();
+        self.client
+            .modify_rule()
+            .rule_arn(&self.rule_arn)
+            .set_actions(Some(actions))
+            .send()
+            .await
+            .map_err(|err| ShifterError::ModifyRule {
+                rule_arn: self.rule_arn.clone(),
+                source: Box::new(err),
+            })?;
+        self.expected = Some((baseline, canary));
+        Ok(())
+    }
+
+    /// Returns a copy of the forward action with our target groups'
+    /// weights replaced, adding either of them if it's missing.
+    fn reweigh(
+        &self,
+        forward: &ForwardActionConfig,
+        baseline: i32,
+        canary: i32,
+    ) -> ForwardActionConfig {
+        let mut target_groups: Vec<TargetGroupTuple> = forward.target_groups().to_vec();
+        for (arn, weight) in [
+            (&self.baseline_target_group, baseline),
+            (&self.canary_target_group, canary),
+        ] {
+            let tuple = TargetGroupTuple::builder()
+                .target_group_arn(arn)
+                .weight(weight)
+                .build();
+            match target_groups
+                .iter_mut()
+                .find(|group| group.target_group_arn() == Some(arn.as_str()))
+            {
+                Some(group) => *group = tuple,
+                None => target_groups.push(tuple),
+            }
+        }
+        ForwardActionConfig::builder()
+            .set_target_groups(Some(target_groups))
+            .set_target_group_stickiness_config(forward.target_group_stickiness_config().cloned())
+            .build()
+    }
+
+    /// Returns the error for a rule without a forward action.
+    fn no_forward_action(&self) -> ShifterError {
+        ShifterError::NoForwardAction {
+            rule_arn: self.rule_arn.clone(),
+        }
+    }
+}
+
+/// Returns the target groups an action forwards to, if it's a forward action.
+fn forward_config(action: &Action) -> Option<&ForwardActionConfig> {
+    if action.r#type() == Some(&ActionTypeEnum::Forward) {
+        action.forward_config()
+    } else {
+        None
+    }
+}
+
+#[async_trait]
+impl TrafficShifter for AlbRuleShifter {
+    async fn set_canary_weight(&mut self, percent: u8) -> Result<(), ShifterError> {
+        let rule = self.describe_rule().await?;
+        self.check_drift(&rule)?;
+        let canary = i32::from(percent.min(100));
+        self.modify_rule(&rule, 100 - canary, canary).await
+    }
+
+    async fn promote(&mut self) -> Result<(), ShifterError> {
+        let rule = self.describe_rule().await?;
+        self.check_drift(&rule)?;
+        self.modify_rule(&rule, 0, 100).await
+    }
+
+    async fn rollback(&mut self) -> Result<(), ShifterError> {
+        // • Rolling back is always safe, so we do it even if someone
+        //   else has changed the weights in the meantime.
+        let rule = self.describe_rule().await?;
+        self.modify_rule(&rule, 100, 0).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_elasticloadbalancingv2::config::{BehaviorVersion, Credentials, Region};
+    use aws_sdk_elasticloadbalancingv2::{Client, Config};
+    use wiremock::matchers::{body_string_contains, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::AlbRuleShifter;
+    use crate::shifter::{ShifterError, TrafficShifter};
+
+    const NAMESPACE: &str = "http://elasticloadbalancing.amazonaws.com/doc/2015-12-01/";
+
+    /// Build a shifter that sends its requests to the stub server.
+    fn stub_shifter(server: &MockServer) -> AlbRuleShifter {
+        let config = Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::for_tests())
+            .endpoint_url(server.uri())
+            .build();
+        AlbRuleShifter::new(Client::from_conf(config), "rule", "baseline", "canary")
+    }
+
+    /// Respond to every DescribeRules request with the given weights.
+    async fn describe_weights(server: &MockServer, baseline: i32, canary: i32) {
+        let forward = format!(
+            r#"<member>
+                <Type>forward</Type>
+                <ForwardConfig><TargetGroups>
+                    <member><TargetGroupArn>baseline</TargetGroupArn><Weight>{baseline}</Weight></member>
+                    <member><TargetGroupArn>canary</TargetGroupArn><Weight>{canary}</Weight></member>
+                </TargetGroups></ForwardConfig>
+            </member>"#
+        );
+        describe_actions(server, &forward).await;
+    }
+
+    /// Respond to every DescribeRules request with a rule with the given actions.
+    async fn describe_actions(server: &MockServer, actions: &str) {
+        let body = format!(
+            r#"<DescribeRulesResponse xmlns="{NAMESPACE}">
+                <DescribeRulesResult><Rules><member>
+                    <RuleArn>rule</RuleArn>
+                    <Actions>{actions}</Actions>
+                </member></Rules></DescribeRulesResult>
+            </DescribeRulesResponse>"#
+        );
+        Mock::given(method("POST"))
+            .and(body_string_contains("Action=DescribeRules"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(server)
+            .await;
+    }
+
+    /// Expect the given number of ModifyRule requests setting the given weights.
+    async fn expect_modify(server: &MockServer, baseline: i32, canary: i32, times: u64) {
+        let body = format!(
+            r#"<ModifyRuleResponse xmlns="{NAMESPACE}">
+                <ModifyRuleResult><Rules/></ModifyRuleResult>
+            </ModifyRuleResponse>"#
+        );
+        Mock::given(method("POST"))
+            .and(body_string_contains("Action=ModifyRule"))
+            .and(body_string_contains(format!(
+                "TargetGroups.member.1.Weight={baseline}&"
+            )))
+            .and(body_string_contains(format!(
+                "TargetGroups.member.2.Weight={canary}"
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .expect(times)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn weights_are_split_between_target_groups() {
+        let server = MockServer::start().await;
+        describe_weights(&server, 95, 5).await;
+        expect_modify(&server, 95, 5, 1).await;
+        expect_modify(&server, 75, 25, 1).await;
+        let mut shifter = stub_shifter(&server);
+        shifter.set_canary_weight(5).await.unwrap();
+        shifter.set_canary_weight(25).await.unwrap();
+    }
+
+    /// If the weights change behind our back, we refuse to continue.
+    #[tokio::test]
+    async fn drift_is_detected() {
+        let server = MockServer::start().await;
+        describe_weights(&server, 50, 50).await;
+        expect_modify(&server, 95, 5, 1).await;
+        let mut shifter = stub_shifter(&server);
+        shifter.set_canary_weight(5).await.unwrap();
+        let err = shifter.promote().await.unwrap_err();
+        assert!(matches!(
+            err,
+            ShifterError::Drift {
+                expected: (95, 5),
+                found: (50, 50),
+                ..
+            }
+        ));
+    }
+
+    /// A rule ARN that matches no rule is reported as such,
+    /// rather than as a rule without a forward action.
+    #[tokio::test]
+    async fn missing_rule_is_reported() {
+        let server = MockServer::start().await;
+        let body = format!(
+            r#"<DescribeRulesResponse xmlns="{NAMESPACE}">
+                <DescribeRulesResult><Rules></Rules></DescribeRulesResult>
+            </DescribeRulesResponse>"#
+        );
+        Mock::given(method("POST"))
+            .and(body_string_contains("Action=DescribeRules"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(&server)
+            .await;
+        let err = stub_shifter(&server)
+            .set_canary_weight(5)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ShifterError::RuleNotFound { rule_arn } if rule_arn == "rule"));
+    }
+
+    #[tokio::test]
+    async fn rollback_ignores_drift() {
+        let server = MockServer::start().await;
+        describe_weights(&server, 50, 50).await;
+        expect_modify(&server, 95, 5, 1).await;
+        expect_modify(&server, 100, 0, 1).await;
+        let mut shifter = stub_shifter(&server);
+        shifter.set_canary_weight(5).await.unwrap();
+        shifter.rollback().await.unwrap();
+    }
+
+    /// Shifting traffic only rewrites the forward action's weights, so
+    /// users are still authenticated first, and sessions stay sticky.
+    #[tokio::test]
+    async fn other_actions_are_kept() {
+        let server = MockServer::start().await;
+        let actions = r#"<member>
+                <Type>authenticate-oidc</Type>
+                <Order>1</Order>
+                <AuthenticateOidcConfig>
+                    <Issuer>https://idp.example.com</Issuer>
+                    <AuthorizationEndpoint>https://idp.example.com/authorize</AuthorizationEndpoint>
+                    <TokenEndpoint>https://idp.example.com/token</TokenEndpoint>
+                    <UserInfoEndpoint>https://idp.example.com/userinfo</UserInfoEndpoint>
+                    <ClientId>client</ClientId>
+                </AuthenticateOidcConfig>
+            </member>
+            <member>
+                <Type>forward</Type>
+                <Order>2</Order>
+                <ForwardConfig>
+                    <TargetGroups>
+                        <member><TargetGroupArn>baseline</TargetGroupArn><Weight>100</Weight></member>
+                    </TargetGroups>
+                    <TargetGroupStickinessConfig>
+                        <Enabled>true</Enabled>
+                        <DurationSeconds>3600</DurationSeconds>
+                    </TargetGroupStickinessConfig>
+                </ForwardConfig>
+            </member>"#;
+        describe_actions(&server, actions).await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("Action=ModifyRule"))
+            .and(body_string_contains(
+                "Actions.member.1.Type=authenticate-oidc",
+            ))
+            .and(body_string_contains(
+                "Actions.member.1.AuthenticateOidcConfig.ClientId=client",
+            ))
+            .and(body_string_contains("Actions.member.2.Order=2"))
+            .and(body_string_contains(
+                "Actions.member.2.ForwardConfig.TargetGroupStickinessConfig.Enabled=true",
+            ))
+            .and(body_string_contains(
+                "Actions.member.2.ForwardConfig.TargetGroups.member.2.Weight=5",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+                r#"<ModifyRuleResponse xmlns="{NAMESPACE}">
+                    <ModifyRuleResult><Rules/></ModifyRuleResult>
+                </ModifyRuleResponse>"#
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut shifter = stub_shifter(&server);
+        shifter.set_canary_weight(5).await.unwrap();
+    }
+}
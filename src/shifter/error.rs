@@ -0,0 +1,72 @@
+use aws_sdk_elasticloadbalancingv2::operation::describe_rules::DescribeRulesError;
+use aws_sdk_elasticloadbalancingv2::operation::modify_rule::ModifyRuleError;
+use aws_sdk_lambda::error::SdkError;
+use aws_sdk_lambda::operation::update_alias::UpdateAliasError;
+use miette::Diagnostic;
+use thiserror::Error;
+
+/// A [ShifterError] describes why we couldn't move traffic
+/// between the control and canary deployments.
+#[derive(Error, Diagnostic, Debug)]
+pub enum ShifterError {
+    /// Lambda refused to update the alias's routing configuration.
+    #[error("failed to update the routing configuration of Lambda alias {alias}")]
+    #[diagnostic(
+        code(canary::shifter::update_alias),
+        help("make sure the function, alias and both versions exist")
+    )]
+    UpdateAlias {
+        alias: String,
+        #[source]
+        source: Box<SdkError<UpdateAliasError>>,
+    },
+    /// The load balancer refused to describe the listener rule.
+    #[error("failed to read the listener rule {rule_arn}")]
+    #[diagnostic(
+        code(canary::shifter::describe_rules),
+        help("make sure the rule exists and you may call elasticloadbalancing:DescribeRules")
+    )]
+    DescribeRules {
+        rule_arn: String,
+        #[source]
+        source: Box<SdkError<DescribeRulesError>>,
+    },
+    /// The load balancer refused to update the listener rule.
+    #[error("failed to update the listener rule {rule_arn}")]
+    #[diagnostic(
+        code(canary::shifter::modify_rule),
+        help("make sure both target groups belong to the rule's load balancer")
+    )]
+    ModifyRule {
+        rule_arn: String,
+        #[source]
+        source: Box<SdkError<ModifyRuleError>>,
+    },
+    /// The load balancer has no listener rule with this ARN.
+    #[error("the listener rule {rule_arn} doesn't exist")]
+    #[diagnostic(
+        code(canary::shifter::rule_not_found),
+        help("make sure `rule-arn` in canary.toml is the ARN of a listener rule in this region")
+    )]
+    RuleNotFound { rule_arn: String },
+    /// The listener rule doesn't forward traffic to target groups,
+    /// so there are no weights to shift.
+    #[error("the listener rule {rule_arn} has no forward action")]
+    #[diagnostic(code(canary::shifter::no_forward_action))]
+    NoForwardAction { rule_arn: String },
+    /// Someone else changed the routing weights since we last set them.
+    /// We stop rather than overwrite their change.
+    #[error(
+        "the weights of listener rule {rule_arn} changed during the deployment: \
+         expected {expected:?} (baseline, canary), found {found:?}"
+    )]
+    #[diagnostic(
+        code(canary::shifter::drift),
+        help("another deployment or an operator may be shifting traffic on this rule")
+    )]
+    Drift {
+        rule_arn: String,
+        expected: (i32, i32),
+        found: (i32, i32),
+    },
+}
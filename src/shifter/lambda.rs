@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use aws_sdk_lambda::types::AliasRoutingConfiguration;
+use aws_sdk_lambda::Client;
+
+use super::{ShifterError, TrafficShifter};
+
+/// A [LambdaAliasShifter] splits traffic between two versions of a
+/// Lambda function by adjusting the routing configuration of an alias.
+/// The alias points at the baseline version, and routes an additional
+/// weight of its traffic to the canary version.
+pub struct LambdaAliasShifter {
+    /// The AWS client for updating Lambda aliases.
+    client: Client,
+    /// The name or ARN of the Lambda function.
+    function_name: String,
+    /// The name of the alias callers invoke.
+    alias: String,
+    /// The version currently serving production traffic.
+    baseline_version: String,
+    /// The version being canaried.
+    canary_version: String,
+}
+
+impl LambdaAliasShifter {
+    /// Create a new shifter for the given function alias.
+    pub fn new(
+        client: Client,
+        function_name: impl Into<String>,
+        alias: impl Into<String>,
+        baseline_version: impl Into<String>,
+        canary_version: impl Into<String>,
+    ) -> Self {
+        Self {
+            client,
+            function_name: function_name.into(),
+            alias: alias.into(),
+            baseline_version: baseline_version.into(),
+            canary_version: canary_version.into(),
+        }
+    }
+
+    /// Build a Lambda client from the ambient AWS configuration.
+    /// If an endpoint URL is provided, requests are sent there instead
+    /// of to AWS, which is useful for testing against a local stub.
+    pub async fn client(endpoint_url: Option<&str>) -> Client {
+        let mut loader = aws_config::from_env();
+        if let Some(url) = endpoint_url {
+            loader = loader.endpoint_url(url);
+        }
+        Client::new(&loader.load().await)
+    }
+
+    /// Point the alias at the given version, routing the given
+    /// additional weights to other versions.
+    async fn update_alias(
+        &self,
+        version: &str,
+        weights: HashMap<String, f64>,
+    ) -> Result<(), ShifterError> {
+        let routing = AliasRoutingConfiguration::builder()
+            .set_additional_version_weights(Some(weights))
+            .build();
+        self.client
+            .update_alias()
+            .function_name(&self.function_name)
+            .name(&self.alias)
+            .function_version(version)
+            .routing_config(routing)
+            .send()
+            .await
+            .map_err(|err| ShifterError::UpdateAlias {
+                alias: self.alias.clone(),
+                source: Box::new(err),
+            })?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TrafficShifter for LambdaAliasShifter {
+    async fn set_canary_weight(&mut self, percent: u8) -> Result<(), ShifterError> {
+        // • Lambda requires additional weights to be strictly less
+        //   than one, so sending all traffic is a promotion.
+        match percent {
+            0 => self.rollback().await,
+            100.. => self.promote().await,
+            _ => {
+                let weight = f64::from(percent) / 100.0;
+                let weights = HashMap::from([(self.canary_version.clone(), weight)]);
+                self.update_alias(&self.baseline_version, weights).await
+            }
+        }
+    }
+
+    async fn promote(&mut self) -> Result<(), ShifterError> {
+        self.update_alias(&self.canary_version, HashMap::new())
+            .await
+    }
+
+    async fn rollback(&mut self) -> Result<(), ShifterError> {
+        self.update_alias(&self.baseline_version, HashMap::new())
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_lambda::config::{BehaviorVersion, Credentials, Region};
+    use aws_sdk_lambda::{Client, Config};
+    use serde_json::json;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::LambdaAliasShifter;
+    use crate::shifter::TrafficShifter;
+
+    const ALIAS_PATH: &str = "/2015-03-31/functions/checkout/aliases/live";
+
+    /// Build a shifter that sends its requests to the stub server.
+    fn stub_shifter(server: &MockServer) -> LambdaAliasShifter {
+        let config = Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::for_tests())
+            .endpoint_url(server.uri())
+            .build();
+        LambdaAliasShifter::new(Client::from_conf(config), "checkout", "live", "1", "2")
+    }
+
+    /// Expect exactly one UpdateAlias request with the given body.
+    async fn expect_update(server: &MockServer, body: serde_json::Value) {
+        Mock::given(method("PUT"))
+            .and(path(ALIAS_PATH))
+            .and(body_partial_json(body))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "Name": "live" })))
+            .expect(1)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn canary_weight_is_routed_to_canary_version() {
+        let server = MockServer::start().await;
+        expect_update(
+            &server,
+            json!({
+                "FunctionVersion": "1",
+                "RoutingConfig": { "AdditionalVersionWeights": { "2": 0.05 } },
+            }),
+        )
+        .await;
+        stub_shifter(&server).set_canary_weight(5).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn promote_points_alias_at_canary_version() {
+        let server = MockServer::start().await;
+        expect_update(
+            &server,
+            json!({
+                "FunctionVersion": "2",
+                "RoutingConfig": { "AdditionalVersionWeights": {} },
+            }),
+        )
+        .await;
+        stub_shifter(&server).promote().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rollback_points_alias_at_baseline_version() {
+        let server = MockServer::start().await;
+        expect_update(
+            &server,
+            json!({
+                "FunctionVersion": "1",
+                "RoutingConfig": { "AdditionalVersionWeights": {} },
+            }),
+        )
+        .await;
+        stub_shifter(&server).rollback().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn api_errors_are_reported() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+                "Type": "User",
+                "message": "Function not found",
+            })))
+            .mount(&server)
+            .await;
+        assert!(stub_shifter(&server).set_canary_weight(5).await.is_err());
+    }
+}
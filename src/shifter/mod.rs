@@ -0,0 +1,57 @@
+use async_trait::async_trait;
+
+pub use alb::AlbRuleShifter;
+pub use error::ShifterError;
+pub use lambda::LambdaAliasShifter;
+
+/// A shifter that adjusts the weights of an ALB listener rule.
+mod alb;
+/// The errors shifters report when they fail to move traffic.
+mod error;
+/// A shifter that adjusts the routing weights of a Lambda alias.
+mod lambda;
+
+/// A [TrafficShifter] controls how much traffic is routed to the canary
+/// deployment, and how much to the control deployment.
+#[async_trait]
+pub trait TrafficShifter: Send {
+    /// Route the given percentage of traffic to the canary, and the rest
+    /// to the control deployment.
+    async fn set_canary_weight(&mut self, percent: u8) -> Result<(), ShifterError>;
+
+    /// Route all traffic to the canary, making it the new control deployment.
+    async fn promote(&mut self) -> Result<(), ShifterError>;
+
+    /// Route all traffic back to the control deployment.
+    async fn rollback(&mut self) -> Result<(), ShifterError>;
+}
+
+/// The [ManualShifter] doesn't move any traffic. It's used when traffic is
+/// managed outside of canary, e.g. by a deployment pipeline that acts on
+/// our exit code.
+#[derive(Default)]
+pub struct ManualShifter;
+
+#[async_trait]
+impl TrafficShifter for ManualShifter {
+    async fn set_canary_weight(&mut self, _percent: u8) -> Result<(), ShifterError> {
+        Ok(())
+    }
+
+    async fn promote(&mut self) -> Result<(), ShifterError> {
+        Ok(())
+    }
+
+    async fn rollback(&mut self) -> Result<(), ShifterError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use static_assertions::assert_obj_safe;
+
+    use super::TrafficShifter;
+
+    assert_obj_safe!(TrafficShifter);
+}
@@ -0,0 +1,102 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use super::StatsError;
+
+/// The alpha cutoff is the amount of confidence we must have in the result
+/// to feel comfortable that the result is not due to chance, but instead
+/// due to the independent variable. The value is the probability we accept
+/// of a false positive: 0.05 means we are 95% confident that the observed
+/// difference is not due to chance, but actually because the experimental
+/// group differs from the control group.
+pub const DEFAULT_ALPHA_CUTOFF: Alpha = Alpha(0.05);
+
+/// An [Alpha] is a significance level: the largest p-value at which we
+/// still treat a difference as real. It's always strictly between 0 and 1,
+/// so any engine holding one can use it without checking it again.
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy, Deserialize)]
+#[serde(try_from = "f64")]
+pub struct Alpha(f64);
+
+impl Alpha {
+    /// Validate a significance level.
+    pub fn new(alpha: f64) -> Result<Self, StatsError> {
+        if 0.0 < alpha && alpha < 1.0 {
+            Ok(Self(alpha))
+        } else {
+            Err(StatsError::Alpha(alpha))
+        }
+    }
+
+    /// Returns the significance level as a probability.
+    pub fn get(self) -> f64 {
+        self.0
+    }
+
+    /// Returns true if the p-value is significant at this level.
+    pub fn rejects(self, p_value: f64) -> bool {
+        p_value < self.0
+    }
+
+    /// Split this level evenly between the given number of tests,
+    /// as the Bonferroni correction does.
+    pub fn split(self, tests: usize) -> Self {
+        Self(self.0 / tests.max(1) as f64)
+    }
+}
+
+impl Default for Alpha {
+    fn default() -> Self {
+        DEFAULT_ALPHA_CUTOFF
+    }
+}
+
+impl TryFrom<f64> for Alpha {
+    type Error = StatsError;
+
+    fn try_from(alpha: f64) -> Result<Self, Self::Error> {
+        Self::new(alpha)
+    }
+}
+
+impl FromStr for Alpha {
+    type Err = StatsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let alpha = s.parse().map_err(|_| StatsError::Parse(s.to_owned()))?;
+        Self::new(alpha)
+    }
+}
+
+impl Display for Alpha {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::Alpha;
+    use crate::stats::StatsError;
+
+    #[test]
+    fn alpha_is_validated() {
+        assert_eq!(Alpha::new(0.01).map(Alpha::get), Ok(0.01));
+        assert_eq!(Alpha::new(0.95), Ok(Alpha(0.95)));
+        for invalid in [0.0, 1.0, -0.05, 5.0, f64::NAN] {
+            assert!(matches!(Alpha::new(invalid), Err(StatsError::Alpha(_))));
+        }
+        assert_eq!("0.1".parse(), Ok(Alpha(0.1)));
+        assert_eq!(
+            "five".parse::<Alpha>(),
+            Err(StatsError::Parse("five".into()))
+        );
+        assert!(Alpha(0.05).rejects(0.049));
+        assert!(!Alpha(0.05).rejects(0.05));
+        assert_eq!(Alpha(0.05).split(2), Alpha(0.025));
+    }
+}
@@ -0,0 +1,348 @@
+use statrs::distribution::{Beta, ContinuousCDF};
+
+use super::{
+    AggregatedObservation, Alpha, DecisionEngine, Diagnostics, ErrorRates, Group, Observation,
+    StatsError, StatusCategory, Verdict, DEFAULT_ALPHA_CUTOFF,
+};
+
+/// By default, the canary only counts as worse if its error rate exceeds
+/// the control group's by more than this margin.
+pub const DEFAULT_MARGIN: f64 = 0.0;
+/// By default, we roll back once we're this sure the canary is worse.
+pub const DEFAULT_ROLLBACK_THRESHOLD: f64 = 0.95;
+/// By default, the canary passes once promoting it would cost us less than
+/// this much error rate on average, e.g. 0.001 is a tenth of a percent.
+pub const DEFAULT_LOSS_THRESHOLD: f64 = 0.001;
+
+/// The number of points used to integrate over the control group's posterior.
+const INTEGRATION_POINTS: usize = 1000;
+/// The number of bisection steps used to invert the posterior's CDF.
+const BISECTION_STEPS: usize = 52;
+
+/// The [BayesianEngine] models the 5XX rate of each group as a Beta-Binomial:
+/// starting from a uniform Beta(1, 1) prior, each group's posterior is
+/// Beta(1 + errors, 1 + successes). Instead of a p-value, it reports the
+/// probability that the canary is worse than the control group, and the
+/// expected loss of promoting it: how much error rate we'd add, on average,
+/// if we promoted it and it turned out to be worse.
+///
+/// The engine rolls back once P(canary rate > control rate + margin) exceeds
+/// the rollback threshold, and passes once the expected loss falls below
+/// the loss threshold.
+#[derive(Clone)]
+pub struct BayesianEngine {
+    /// The number of (errors, successes) in each group.
+    control: (u64, u64),
+    experimental: (u64, u64),
+    margin: f64,
+    rollback_threshold: f64,
+    loss_threshold: f64,
+    /// The alpha cutoff of the confidence intervals around the error
+    /// rates we report. It plays no part in the decision itself.
+    alpha_cutoff: Alpha,
+    /// The result of the most recent decision.
+    last_result: Option<BayesianResult>,
+}
+
+/// The [BayesianResult] summarizes the posterior comparison of the groups.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BayesianResult {
+    /// The posterior mean of the control group's 5XX rate.
+    pub control_rate: f64,
+    /// The posterior mean of the canary's 5XX rate.
+    pub experimental_rate: f64,
+    /// The probability that the canary's 5XX rate exceeds the
+    /// control group's by more than the margin.
+    pub probability_worse: f64,
+    /// The expected amount by which the canary's 5XX rate exceeds
+    /// the control group's, counting only the cases where it does.
+    pub expected_loss: f64,
+}
+
+impl Default for BayesianEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BayesianEngine {
+    pub fn new() -> Self {
+        Self {
+            control: (0, 0),
+            experimental: (0, 0),
+            margin: DEFAULT_MARGIN,
+            rollback_threshold: DEFAULT_ROLLBACK_THRESHOLD,
+            loss_threshold: DEFAULT_LOSS_THRESHOLD,
+            alpha_cutoff: DEFAULT_ALPHA_CUTOFF,
+            last_result: None,
+        }
+    }
+
+    /// Only count the canary as worse if its 5XX rate exceeds the control
+    /// group's by more than this margin, e.g. 0.005 for half a percent.
+    pub fn with_margin(self, margin: f64) -> Result<Self, StatsError> {
+        if !(0.0..1.0).contains(&margin) {
+            return Err(StatsError::Margin(margin));
+        }
+        Ok(Self { margin, ..self })
+    }
+
+    /// Roll back once the probability that the canary is worse exceeds
+    /// this, which must be strictly between 0 and 1.
+    pub fn with_rollback_threshold(self, rollback_threshold: f64) -> Result<Self, StatsError> {
+        if !(0.0 < rollback_threshold && rollback_threshold < 1.0) {
+            return Err(StatsError::RollbackThreshold(rollback_threshold));
+        }
+        Ok(Self {
+            rollback_threshold,
+            ..self
+        })
+    }
+
+    /// Pass the canary once its expected loss falls below this, which
+    /// must be at least 0 and less than 1.
+    pub fn with_loss_threshold(self, loss_threshold: f64) -> Result<Self, StatsError> {
+        if !(0.0..1.0).contains(&loss_threshold) {
+            return Err(StatsError::LossThreshold(loss_threshold));
+        }
+        Ok(Self {
+            loss_threshold,
+            ..self
+        })
+    }
+
+    /// Report the error rates with confidence intervals at this alpha cutoff.
+    pub fn with_alpha_cutoff(self, alpha_cutoff: Alpha) -> Self {
+        Self {
+            alpha_cutoff,
+            ..self
+        }
+    }
+
+    pub fn add_observation(&mut self, obs: Observation) {
+        self.add_aggregate(obs.into());
+    }
+
+    /// Record every observation an aggregate counts at once.
+    pub fn add_aggregate(&mut self, agg: AggregatedObservation) {
+        let counts = match agg.group {
+            Group::Control => &mut self.control,
+            Group::Experimental => &mut self.experimental,
+        };
+        if agg.outcome == StatusCategory::_5XX {
+            counts.0 += agg.count;
+        } else {
+            counts.1 += agg.count;
+        }
+    }
+
+    /// Compare the posterior 5XX rates of the two groups.
+    pub fn test(&self) -> BayesianResult {
+        let control = posterior(self.control);
+        let experimental = posterior(self.experimental);
+        let (errors, successes) = self.experimental;
+        let experimental_mean = (1 + errors) as f64 / (2 + errors + successes) as f64;
+        // • The mean of a Beta(a, b) restricted to values above x is
+        //   related to the tail of a Beta(a + 1, b), which gives the
+        //   expected loss in closed form for each control rate.
+        let shifted = Beta::new(2.0 + errors as f64, 1.0 + successes as f64)
+            .expect("Beta parameters must be positive");
+        // • Integrate over the control group's posterior by taking evenly
+        //   spaced quantiles of it, so the points concentrate where its
+        //   mass is, however sharply peaked it has become.
+        let mut probability_worse = 0.0;
+        let mut expected_loss = 0.0;
+        for i in 0..INTEGRATION_POINTS {
+            let quantile = (i as f64 + 0.5) / INTEGRATION_POINTS as f64;
+            let control_rate = inverse_cdf(&control, quantile);
+            probability_worse += experimental.sf(control_rate + self.margin);
+            expected_loss += experimental_mean * shifted.sf(control_rate)
+                - control_rate * experimental.sf(control_rate);
+        }
+        let (errors, successes) = self.control;
+        BayesianResult {
+            control_rate: (1 + errors) as f64 / (2 + errors + successes) as f64,
+            experimental_rate: experimental_mean,
+            probability_worse: probability_worse / INTEGRATION_POINTS as f64,
+            expected_loss: (expected_loss / INTEGRATION_POINTS as f64).max(0.0),
+        }
+    }
+
+    /// Returns the observed 5XX rate of each group and their difference,
+    /// with confidence intervals at the configured alpha cutoff, or None
+    /// while either group is empty.
+    pub fn error_rates(&self) -> Option<ErrorRates> {
+        let counts = |(errors, successes): (u64, u64)| (errors, errors + successes);
+        ErrorRates::new(
+            counts(self.control),
+            counts(self.experimental),
+            self.alpha_cutoff,
+        )
+    }
+
+    /// returns the total number of observations recorded across both groups.
+    pub fn total_count(&self) -> usize {
+        let (control_errors, control_successes) = self.control;
+        let (errors, successes) = self.experimental;
+        (control_errors + control_successes + errors + successes) as usize
+    }
+}
+
+impl DecisionEngine for BayesianEngine {
+    fn add_observation(&mut self, obs: Observation) {
+        BayesianEngine::add_observation(self, obs);
+    }
+
+    fn add_aggregate(&mut self, agg: AggregatedObservation) {
+        BayesianEngine::add_aggregate(self, agg);
+    }
+
+    fn total_count(&self) -> usize {
+        BayesianEngine::total_count(self)
+    }
+
+    fn evaluate(&mut self) -> Verdict {
+        let result = self.test();
+        self.last_result = Some(result);
+        if result.probability_worse > self.rollback_threshold {
+            Verdict::Rollback
+        } else if result.expected_loss < self.loss_threshold {
+            Verdict::Promote
+        } else {
+            Verdict::Continue
+        }
+    }
+
+    fn error_rates(&self) -> Option<ErrorRates> {
+        BayesianEngine::error_rates(self)
+    }
+
+    /// The posterior is valid whenever it's computed,
+    /// so it can be consulted after every batch.
+    fn is_sequential(&self) -> bool {
+        true
+    }
+
+    fn diagnostics(&self) -> Diagnostics {
+        Diagnostics {
+            engine: "bayesian",
+            values: self.last_result.map_or_else(Vec::new, |result| {
+                vec![
+                    ("P(canary worse)", result.probability_worse),
+                    ("expected loss", result.expected_loss),
+                ]
+            }),
+            direction: None,
+        }
+    }
+}
+
+/// Returns the posterior of an error rate after observing the
+/// given (errors, successes), starting from a uniform prior.
+fn posterior((errors, successes): (u64, u64)) -> Beta {
+    Beta::new(1.0 + errors as f64, 1.0 + successes as f64)
+        .expect("Beta parameters must be positive")
+}
+
+/// Find the value at the given quantile of a distribution on [0, 1] by
+/// bisection. statrs's default inversion stops well short of the precision
+/// needed for error rates of a fraction of a percent.
+fn inverse_cdf(distribution: &Beta, quantile: f64) -> f64 {
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..BISECTION_STEPS {
+        let mid = (low + high) / 2.0;
+        if distribution.cdf(mid) < quantile {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    (low + high) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BayesianEngine;
+    use crate::stats::fixtures::traffic;
+    use crate::stats::{Alpha, DecisionEngine, Group, StatsError, Verdict};
+
+    /// With identical data, each group is equally likely to be worse.
+    #[test]
+    fn identical_groups_are_a_coin_flip() {
+        let mut engine = BayesianEngine::new();
+        engine.add_observations(&traffic(Group::Control, 990, 10));
+        engine.add_observations(&traffic(Group::Experimental, 990, 10));
+        let result = engine.test();
+        assert!((result.probability_worse - 0.5).abs() < 0.01);
+        // The posterior standard deviation of each rate is about 0.003,
+        // so the expected loss is on that order.
+        assert!(0.0005 < result.expected_loss && result.expected_loss < 0.003);
+    }
+
+    /// A canary with four times the error rate is almost surely worse.
+    #[test]
+    fn worse_canary_is_rolled_back() {
+        let mut engine = BayesianEngine::new();
+        engine.add_observations(&traffic(Group::Control, 990, 10));
+        engine.add_observations(&traffic(Group::Experimental, 960, 40));
+        assert_eq!(engine.evaluate(), Verdict::Rollback);
+        let result = engine.test();
+        assert!(result.probability_worse > 0.999);
+        assert!((result.expected_loss - 0.03).abs() < 0.005);
+    }
+
+    /// The margin tolerates small regressions, and a low expected
+    /// loss lets a healthy canary pass.
+    #[test]
+    fn margin_and_loss_thresholds() {
+        let mut engine = BayesianEngine::new()
+            .with_margin(0.01)
+            .and_then(|engine| engine.with_loss_threshold(0.002))
+            .unwrap();
+        engine.add_observations(&traffic(Group::Control, 99_000, 1_000));
+        engine.add_observations(&traffic(Group::Experimental, 98_900, 1_100));
+        let result = engine.test();
+        assert!(result.probability_worse < 0.001);
+        // The canary's rate is a tenth of a percent higher, so that's
+        // about how much promoting it is expected to cost.
+        assert!((result.expected_loss - 0.001).abs() < 0.0002);
+        assert_eq!(engine.evaluate(), Verdict::Promote);
+    }
+
+    /// The reported error rates use the configured alpha cutoff, so a
+    /// stricter cutoff widens their intervals.
+    #[test]
+    fn error_rates_follow_alpha_cutoff() {
+        let interval = |alpha| {
+            let mut engine = BayesianEngine::new().with_alpha_cutoff(Alpha::new(alpha).unwrap());
+            engine.add_observations(&traffic(Group::Control, 990, 10));
+            engine.add_observations(&traffic(Group::Experimental, 960, 40));
+            engine.error_rates().unwrap().difference.interval
+        };
+        let (loose, strict) = (interval(0.05), interval(0.001));
+        assert!(strict.lower < loose.lower && loose.upper < strict.upper);
+    }
+
+    /// Thresholds outside their ranges are refused rather than stored.
+    #[test]
+    fn invalid_thresholds_are_rejected() {
+        let engine = BayesianEngine::new;
+        assert_eq!(
+            engine().with_margin(-0.01).err(),
+            Some(StatsError::Margin(-0.01))
+        );
+        for threshold in [0.0, 1.0, 1.5, f64::NAN] {
+            assert!(matches!(
+                engine().with_rollback_threshold(threshold),
+                Err(StatsError::RollbackThreshold(_))
+            ));
+        }
+        for threshold in [-0.001, 1.0, f64::NAN] {
+            assert!(matches!(
+                engine().with_loss_threshold(threshold),
+                Err(StatsError::LossThreshold(_))
+            ));
+        }
+        assert!(engine().with_loss_threshold(0.0).is_ok());
+    }
+}
@@ -4,6 +4,8 @@ use std::num::NonZeroU64;
 
 use statrs::distribution::{ChiSquared, ContinuousCDF};
 
+use super::{Alpha, StatsError};
+
 /// A ContingencyTable expresses the frequency with which a group was observed.
 /// Usually, it tracks the number of observations in ecah group, but when the
 /// number is already known (i.e. its fixed, like a fair dice or coin), it can
@@ -25,14 +27,12 @@ pub trait ContingencyTable<Group> {
 
 /// returns the number of degrees of freedom for this table.
 /// This is typically the number of groups minus one.
-/// # Panics
-/// This method panics if the number of groups returned by `groups` is less than 2.
-fn degrees_of_freedom<Cat: EnumerableCategory>(table: &impl ContingencyTable<Cat>) -> NonZeroU64 {
+/// The experiment must have at least two groups.
+fn degrees_of_freedom<Cat: EnumerableCategory>(
+    table: &impl ContingencyTable<Cat>,
+) -> Result<NonZeroU64, StatsError> {
     let group_count = table.groups().count() as u64;
-    if group_count < 2 {
-        panic!("The experiment must have at least two groups. Only {group_count} groups provided");
-    }
-    NonZeroU64::new(group_count - 1).unwrap()
+    NonZeroU64::new(group_count.saturating_sub(1)).ok_or(StatsError::TooFewCategories(group_count))
 }
 
 /// This helper trait identifies a category with a known set of groups.
@@ -120,25 +120,29 @@ where
     }
 }
 
-/// Alpha represents the alpha cutoff, expressed as a floating point from [0, 1] inclusive.
-/// For example, 0.95 is the standard 5% confidency interval.
+/// returns true if the observed frequencies differ significantly from the
+/// expected ones at the given alpha cutoff. For example, an alpha of 0.05
+/// accepts a 5% chance that the difference is due to chance alone.
+///
+/// Both tables must have the same categories, and at least two of them.
 pub fn chi_square_test<Cat>(
     observed: &impl ContingencyTable<Cat>,
     expected: &impl ContingencyTable<Cat>,
-    alpha: f64,
-) -> bool
+    alpha: Alpha,
+) -> Result<bool, StatsError>
 where
     Cat: EnumerableCategory + Hash + Eq,
 {
-    assert!(alpha < 1.0);
-    assert_eq!(
-        degrees_of_freedom(observed),
-        degrees_of_freedom(expected),
-        "Expected the degrees of freedom from both groups to be the same."
-    );
+    let freedom = degrees_of_freedom(observed)?;
+    let expected_freedom = degrees_of_freedom(expected)?;
+    if freedom != expected_freedom {
+        return Err(StatsError::MismatchedTables {
+            observed: freedom.get(),
+            expected: expected_freedom.get(),
+        });
+    }
     let stat = test_statistic(expected, observed);
-    let pval = p_value(stat, degrees_of_freedom(observed));
-    pval < alpha
+    Ok(alpha.rejects(p_value(stat, freedom)))
 }
 
 // calculate the chi square test statistic using the provided contingency tables.
@@ -177,7 +181,8 @@ mod tests {
 
     use crate::stats::chi::{degrees_of_freedom, p_value, FixedContingencyTable};
 
-    use super::{test_statistic, ContingencyTable, EnumerableCategory};
+    use super::{chi_square_test, test_statistic, ContingencyTable, EnumerableCategory};
+    use crate::stats::{Alpha, StatsError};
     use pretty_assertions::assert_eq;
     use static_assertions::assert_obj_safe;
 
@@ -216,7 +221,7 @@ mod tests {
             assert_eq!(expected, observed);
         }
         // Demonstrate the number of degrees of freedom matches expectations.
-        assert_eq!(degrees_of_freedom(&table), NonZeroU64::new(1).unwrap());
+        assert_eq!(degrees_of_freedom(&table), Ok(NonZeroU64::new(1).unwrap()));
     }
 
     /// Scenario: You flip a coin 50 times, and get 21 Heads and 29 Tails.
@@ -232,11 +237,11 @@ mod tests {
         experimental_group.set_group_count(false, 29);
         assert_eq!(
             degrees_of_freedom(&control_group),
-            NonZeroU64::new(1).unwrap()
+            Ok(NonZeroU64::new(1).unwrap())
         );
         assert_eq!(
             degrees_of_freedom(&experimental_group),
-            NonZeroU64::new(1).unwrap()
+            Ok(NonZeroU64::new(1).unwrap())
         );
         let stat = test_statistic(&control_group, &experimental_group);
         // Round the statistic to two decimal places.
@@ -244,7 +249,38 @@ mod tests {
         let expected = 1.28;
         assert_eq!(observed, expected);
         // Now, calculate the p-value using the test statistic.
-        let pval = p_value(stat, degrees_of_freedom(&control_group));
+        let pval = p_value(stat, degrees_of_freedom(&control_group).unwrap());
         assert!(0.25 < pval && pval < 0.30);
     }
+
+    /// A fair coin isn't rejected, and malformed tables are errors
+    /// rather than panics.
+    #[test]
+    fn chi_square_test_validates_tables() {
+        let mut expected = FixedContingencyTable::new();
+        expected.set_group_count(true, 25);
+        expected.set_group_count(false, 25);
+        let mut observed = FixedContingencyTable::new();
+        observed.set_group_count(true, 21);
+        observed.set_group_count(false, 29);
+        let alpha = Alpha::new(0.05).unwrap();
+        assert_eq!(chi_square_test(&observed, &expected, alpha), Ok(false));
+        assert_eq!(
+            degrees_of_freedom(&SingleCategory),
+            Err(StatsError::TooFewCategories(1))
+        );
+    }
+
+    /// This table only has one category, so there's nothing to compare.
+    struct SingleCategory;
+
+    impl ContingencyTable<bool> for SingleCategory {
+        fn group_count(&self, _: &bool) -> u64 {
+            1
+        }
+
+        fn groups(&self) -> Box<dyn Iterator<Item = bool>> {
+            Box::new(std::iter::once(true))
+        }
+    }
 }
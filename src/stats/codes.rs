@@ -0,0 +1,174 @@
+use std::collections::BTreeSet;
+
+use super::{EnumerableCategory, Observation, StatsError, StatusCategory};
+
+/// A [StatusClass] is a column of the chi-square engine's contingency table:
+/// either a whole [StatusCategory], or a set of exact status codes split out
+/// of one, so e.g. throttling (429) isn't lumped in with other client errors.
+#[derive(Hash, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum StatusClass {
+    /// Every code in the category that hasn't been split out.
+    Category(StatusCategory),
+    /// The codes of one set in the [StatusCodeSets], named by the smallest.
+    Codes(u16),
+}
+
+impl StatusClass {
+    /// Returns true if the class holds server errors.
+    pub fn is_server_error(self) -> bool {
+        match self {
+            Self::Category(category) => category == StatusCategory::_5XX,
+            Self::Codes(code) => (500..=599).contains(&code),
+        }
+    }
+}
+
+impl EnumerableCategory for StatusClass {
+    fn groups() -> Box<dyn Iterator<Item = Self>> {
+        Box::new(
+            StatusCategory::groups()
+                .map(Self::Category)
+                .chain((100..=599).map(Self::Codes)),
+        )
+    }
+}
+
+/// The [StatusCodeSets] are the exact status codes the chi-square engine
+/// tests as columns of their own. Each set becomes one column, and codes in
+/// no set stay in their category's column. Without any sets, the engine
+/// only compares the five categories.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct StatusCodeSets {
+    sets: Vec<BTreeSet<u16>>,
+}
+
+impl StatusCodeSets {
+    /// Split each set of codes into a column of its own. Every code must be
+    /// a valid HTTP status, each set must stay within one category, so it's
+    /// clear whether it holds server errors, and no code may be in two sets.
+    pub fn new(sets: impl IntoIterator<Item = Vec<u16>>) -> Result<Self, StatsError> {
+        let mut seen = BTreeSet::new();
+        let mut validated = Vec::new();
+        for set in sets {
+            let set: BTreeSet<u16> = set.into_iter().collect();
+            let mut categories = BTreeSet::new();
+            for code in &set {
+                categories.insert(StatusCategory::try_from(*code)?);
+                if !seen.insert(*code) {
+                    return Err(StatsError::CodeSet(set.into_iter().collect()));
+                }
+            }
+            if categories.len() > 1 {
+                return Err(StatsError::CodeSet(set.into_iter().collect()));
+            }
+            if !set.is_empty() {
+                validated.push(set);
+            }
+        }
+        Ok(Self { sets: validated })
+    }
+
+    /// Returns the column each set is counted in.
+    pub fn classes(&self) -> impl Iterator<Item = StatusClass> + '_ {
+        self.sets
+            .iter()
+            .filter_map(|set| set.first())
+            .map(|code| StatusClass::Codes(*code))
+    }
+
+    /// Returns the column this observation belongs in. Observations
+    /// without an exact status code stay in their category's column.
+    pub fn classify(&self, obs: &Observation) -> StatusClass {
+        obs.status
+            .and_then(|status| self.sets.iter().find(|set| set.contains(&status)))
+            .and_then(|set| set.first())
+            .map_or(StatusClass::Category(obs.outcome), |code| {
+                StatusClass::Codes(*code)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{StatusClass, StatusCodeSets};
+    use crate::stats::{
+        ChiSquareEngine, DecisionEngine, Direction, Group, Observation, SequentialEngine,
+        StatsError, StatusCategory, Verdict,
+    };
+
+    /// Build an observation with an exact status code.
+    fn status(group: Group, code: u16) -> Observation {
+        Observation::new(group, StatusCategory::try_from(code).unwrap()).with_status(code)
+    }
+
+    #[test]
+    fn classifies_by_code_set() {
+        let sets = StatusCodeSets::new([vec![429], vec![503, 502, 504]]).unwrap();
+        let classify = |code| sets.classify(&status(Group::Control, code));
+        assert_eq!(classify(429), StatusClass::Codes(429));
+        assert_eq!(classify(503), StatusClass::Codes(502));
+        assert_eq!(classify(404), StatusClass::Category(StatusCategory::_4XX));
+        assert!(classify(504).is_server_error());
+        assert!(!classify(429).is_server_error());
+        // • Without an exact code, only the category is known.
+        let obs = Observation::new(Group::Control, StatusCategory::_4XX);
+        assert_eq!(
+            sets.classify(&obs),
+            StatusClass::Category(StatusCategory::_4XX)
+        );
+    }
+
+    #[test]
+    fn invalid_sets_are_rejected() {
+        assert_eq!(
+            StatusCodeSets::new([vec![429, 600]]),
+            Err(StatsError::StatusCode(600))
+        );
+        assert_eq!(
+            StatusCodeSets::new([vec![429, 503]]),
+            Err(StatsError::CodeSet(vec![429, 503]))
+        );
+        assert_eq!(
+            StatusCodeSets::new([vec![502, 503], vec![503]]),
+            Err(StatsError::CodeSet(vec![503]))
+        );
+    }
+
+    /// Throttling looks like any other client error until it's split
+    /// out, and then a canary that's throttled more often fails, even
+    /// though it serves no more 5XX than the control group.
+    #[test]
+    fn split_codes_are_tested_separately() {
+        let traffic: Vec<Observation> = [
+            (Group::Control, 200, 800),
+            (Group::Control, 404, 150),
+            (Group::Control, 429, 50),
+            (Group::Experimental, 200, 800),
+            (Group::Experimental, 404, 100),
+            (Group::Experimental, 429, 100),
+        ]
+        .into_iter()
+        .flat_map(|(group, code, count)| std::iter::repeat_n(status(group, code), count))
+        .collect();
+        let mut lumped = ChiSquareEngine::new();
+        let sets = StatusCodeSets::new([vec![429]]).unwrap();
+        let mut split = ChiSquareEngine::new().with_code_sets(sets.clone());
+        let mut sequential = SequentialEngine::new().with_code_sets(sets);
+        lumped.add_observations(&traffic);
+        split.add_observations(&traffic);
+        sequential.add_observations(&traffic);
+        assert!(!lumped.is_significant());
+        assert_eq!(DecisionEngine::evaluate(&mut lumped), Verdict::Promote);
+        let result = split.test();
+        assert_eq!(result.degrees_of_freedom, 2);
+        assert!(result.significant);
+        assert_eq!(split.direction(), Some(Direction::NoDifference));
+        let (class, throttled) = split.code_set_tests()[0];
+        assert_eq!(class, StatusClass::Codes(429));
+        assert_eq!(throttled.direction, Direction::Worse);
+        assert_eq!(DecisionEngine::evaluate(&mut split), Verdict::Rollback);
+        assert_eq!(sequential.evaluate(), Verdict::Rollback);
+    }
+}
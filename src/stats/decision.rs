@@ -0,0 +1,538 @@
+use std::fmt::{self, Display};
+
+use serde::Deserialize;
+
+use super::{
+    AggregatedObservation, ChiSquareEngine, Direction, ErrorRates, LatencyEngine, LatencyResult,
+    Observation, SequentialEngine,
+};
+
+/// A [Verdict] is what a [DecisionEngine] concludes from the
+/// observations it has seen so far.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Verdict {
+    /// The canary is good enough to advance.
+    Promote,
+    /// The canary is worse than the control group.
+    Rollback,
+    /// There isn't enough evidence either way yet.
+    Continue,
+    /// The observations can't be tested at all, e.g. because
+    /// a group is empty or every request had the same outcome.
+    Inconclusive,
+}
+
+/// The [Diagnostics] explain the most recent [Verdict] to operators,
+/// with the statistics the engine based it on.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostics {
+    /// The name of the engine, e.g. "chi-square".
+    pub engine: &'static str,
+    /// The statistics behind the verdict, by name.
+    pub values: Vec<(&'static str, f64)>,
+    /// Which way the canary's error rate differs, if the engine tests it.
+    pub direction: Option<Direction>,
+}
+
+impl Diagnostics {
+    /// Returns the statistic with the given name, if the engine reported it.
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.values
+            .iter()
+            .find_map(|(key, value)| (*key == name).then_some(*value))
+    }
+}
+
+impl Display for Diagnostics {
+    /// Formats the diagnostics like
+    /// "chi-square: p = 0.0312, X² = 4.6512 (canary worse)".
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.engine)?;
+        if self.values.is_empty() {
+            return write!(f, ": untested");
+        }
+        for (i, (name, value)) in self.values.iter().enumerate() {
+            let separator = if i == 0 { ":" } else { "," };
+            write!(f, "{separator} {name} = {value:.4}")?;
+        }
+        match self.direction {
+            Some(direction) => write!(f, " ({})", direction.name()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A [DecisionEngine] accumulates observations of both groups and decides
+/// whether the canary should be promoted or rolled back. The promotion loop
+/// programs against this trait, so engines can be swapped by configuration
+/// or combined with a [VerdictPolicy](super::VerdictPolicy).
+pub trait DecisionEngine: Send {
+    /// Record a single observation.
+    fn add_observation(&mut self, obs: Observation);
+
+    /// Record every observation in a batch.
+    fn add_observations(&mut self, observations: &[Observation]) {
+        for obs in observations {
+            self.add_observation(*obs);
+        }
+    }
+
+    /// Record every observation an aggregate counts. By default, each is
+    /// recorded in turn; engines that only count outcomes should record
+    /// the whole aggregate at once.
+    fn add_aggregate(&mut self, agg: AggregatedObservation) {
+        for _ in 0..agg.count {
+            self.add_observation(agg.observation());
+        }
+    }
+
+    /// Record every aggregate in a batch.
+    fn add_aggregates(&mut self, aggregates: &[AggregatedObservation]) {
+        for agg in aggregates {
+            self.add_aggregate(*agg);
+        }
+    }
+
+    /// Returns the number of observations recorded across both groups.
+    fn total_count(&self) -> usize;
+
+    /// Reach a verdict based on every observation recorded so far.
+    fn evaluate(&mut self) -> Verdict;
+
+    /// Returns true if [evaluate](DecisionEngine::evaluate) may be called
+    /// after every batch. Otherwise, the engine must only be consulted once
+    /// it has all the observations it's going to get, or its error rate
+    /// guarantees don't hold.
+    fn is_sequential(&self) -> bool {
+        false
+    }
+
+    /// Returns false while the engine refuses to decide because it hasn't
+    /// seen enough observations for its verdict to be meaningful.
+    fn has_min_sample(&self) -> bool {
+        true
+    }
+
+    /// Returns the p-value of the hypothesis test behind the most recent
+    /// verdict, if it rests on one. A [VerdictPolicy](super::VerdictPolicy)
+    /// uses it to correct for testing several metrics at once.
+    fn p_value(&self) -> Option<f64> {
+        None
+    }
+
+    /// Returns the groups' 5XX rates and their difference, with confidence
+    /// intervals, if the engine compares error rates.
+    fn error_rates(&self) -> Option<ErrorRates> {
+        None
+    }
+
+    /// Returns the comparison of the groups' latencies, if the engine
+    /// compares them and both groups have reported some.
+    fn latency(&self) -> Option<LatencyResult> {
+        None
+    }
+
+    /// Explain the most recent verdict.
+    fn diagnostics(&self) -> Diagnostics;
+}
+
+/// The [EngineKind] selects the [DecisionEngine] used to compare
+/// the error rates of the control and experimental groups.
+#[derive(Deserialize, clap::ValueEnum, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum EngineKind {
+    /// A chi-square test of homogeneity over the status categories.
+    #[default]
+    ChiSquare,
+    /// A Beta-Binomial model of the 5XX rate, which reports the
+    /// probability that the canary is worse.
+    Bayesian,
+    /// A one-sided two-proportion z-test, which only rejects
+    /// when the canary's 5XX rate is higher.
+    ZTest,
+}
+
+impl EngineKind {
+    /// Returns the name of the engine, as it's written in flags.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ChiSquare => "chi-square",
+            Self::Bayesian => "bayesian",
+            Self::ZTest => "z-test",
+        }
+    }
+}
+
+/// A boxed engine decides like the engine inside it, so engines chosen at
+/// runtime can be combined in a [VerdictPolicy](super::VerdictPolicy).
+impl<E: DecisionEngine + ?Sized> DecisionEngine for Box<E> {
+    fn add_observation(&mut self, obs: Observation) {
+        (**self).add_observation(obs);
+    }
+
+    fn add_aggregate(&mut self, agg: AggregatedObservation) {
+        (**self).add_aggregate(agg);
+    }
+
+    fn total_count(&self) -> usize {
+        (**self).total_count()
+    }
+
+    fn evaluate(&mut self) -> Verdict {
+        (**self).evaluate()
+    }
+
+    fn is_sequential(&self) -> bool {
+        (**self).is_sequential()
+    }
+
+    fn has_min_sample(&self) -> bool {
+        (**self).has_min_sample()
+    }
+
+    fn p_value(&self) -> Option<f64> {
+        (**self).p_value()
+    }
+
+    fn error_rates(&self) -> Option<ErrorRates> {
+        (**self).error_rates()
+    }
+
+    fn latency(&self) -> Option<LatencyResult> {
+        (**self).latency()
+    }
+
+    fn diagnostics(&self) -> Diagnostics {
+        (**self).diagnostics()
+    }
+}
+
+impl DecisionEngine for ChiSquareEngine {
+    fn add_observation(&mut self, obs: Observation) {
+        ChiSquareEngine::add_observation(self, obs);
+    }
+
+    fn add_aggregate(&mut self, agg: AggregatedObservation) {
+        ChiSquareEngine::add_aggregate(self, agg);
+    }
+
+    fn total_count(&self) -> usize {
+        ChiSquareEngine::total_count(self)
+    }
+
+    /// Only a significantly higher 5XX rate fails the canary. The test of
+    /// the whole table is reported, but a shift between other status codes
+    /// doesn't decide anything. An empty or degenerate table is no
+    /// evidence either way.
+    fn evaluate(&mut self) -> Verdict {
+        if self.total_count() == 0 {
+            Verdict::Inconclusive
+        } else if !self.has_min_sample() {
+            Verdict::Continue
+        } else if self.test().degrees_of_freedom == 0 {
+            Verdict::Inconclusive
+        } else if self.is_worse() {
+            Verdict::Rollback
+        } else {
+            Verdict::Promote
+        }
+    }
+
+    fn has_min_sample(&self) -> bool {
+        ChiSquareEngine::has_min_sample(self)
+    }
+
+    /// The verdict rests on the one-sided tests of the 5XX rate and
+    /// every tested set of codes, so this is the smallest of their
+    /// p-values, scaled up by the number of tests.
+    fn p_value(&self) -> Option<f64> {
+        let error_test = self.error_test()?;
+        let sets = self.code_set_tests().into_iter();
+        Some(self.family_p_value(
+            std::iter::once(error_test.p_value).chain(sets.map(|(_, result)| result.p_value)),
+        ))
+    }
+
+    fn error_rates(&self) -> Option<ErrorRates> {
+        ChiSquareEngine::error_rates(self)
+    }
+
+    /// Reports the p-value the verdict rests on, then the test of the
+    /// whole table.
+    fn diagnostics(&self) -> Diagnostics {
+        let result = self.test();
+        let mut values: Vec<_> = DecisionEngine::p_value(self)
+            .map(|p_value| ("p", p_value))
+            .into_iter()
+            .collect();
+        if result.degrees_of_freedom > 0 {
+            values.extend([("X²", result.statistic), ("X² p", result.p_value)]);
+        }
+        Diagnostics {
+            engine: result.method.name(),
+            values,
+            direction: worse_or(self.is_worse(), self.direction()),
+        }
+    }
+}
+
+impl DecisionEngine for SequentialEngine {
+    fn add_observation(&mut self, obs: Observation) {
+        SequentialEngine::add_observation(self, obs);
+    }
+
+    fn add_aggregate(&mut self, agg: AggregatedObservation) {
+        SequentialEngine::add_aggregate(self, agg);
+    }
+
+    fn total_count(&self) -> usize {
+        SequentialEngine::total_count(self)
+    }
+
+    fn evaluate(&mut self) -> Verdict {
+        // • Failing to reject either test says nothing until the stage
+        //   has enough observations. Rejecting in the canary's favour
+        //   isn't a reason to fail it.
+        self.update();
+        if self.is_worse() {
+            Verdict::Rollback
+        } else if self.has_min_sample() && self.is_equivalent() {
+            Verdict::Promote
+        } else {
+            Verdict::Continue
+        }
+    }
+
+    fn is_sequential(&self) -> bool {
+        true
+    }
+
+    fn has_min_sample(&self) -> bool {
+        SequentialEngine::has_min_sample(self)
+    }
+
+    fn p_value(&self) -> Option<f64> {
+        SequentialEngine::p_value(self)
+    }
+
+    fn error_rates(&self) -> Option<ErrorRates> {
+        SequentialEngine::error_rates(self)
+    }
+
+    fn diagnostics(&self) -> Diagnostics {
+        let statistic = self.last_result().map(|result| ("X²", result.statistic));
+        let margin = self
+            .equivalence_p_value()
+            .map(|p_value| ("margin p", p_value));
+        Diagnostics {
+            engine: "sequential",
+            values: SequentialEngine::p_value(self)
+                .map(|p_value| ("p", p_value))
+                .into_iter()
+                .chain(statistic)
+                .chain(margin)
+                .collect(),
+            direction: worse_or(self.is_worse(), self.direction()),
+        }
+    }
+}
+
+impl DecisionEngine for LatencyEngine {
+    fn add_observation(&mut self, obs: Observation) {
+        LatencyEngine::add_observation(self, obs);
+    }
+
+    /// Aggregates don't carry latencies, so there's nothing to record.
+    fn add_aggregate(&mut self, _: AggregatedObservation) {}
+
+    fn total_count(&self) -> usize {
+        LatencyEngine::total_count(self)
+    }
+
+    fn evaluate(&mut self) -> Verdict {
+        if self.control().count() == 0 || self.experimental().count() == 0 {
+            Verdict::Inconclusive
+        } else if self.test().regressed() {
+            Verdict::Rollback
+        } else {
+            Verdict::Promote
+        }
+    }
+
+    /// A canary whose percentiles exceed the tolerance is rolled back
+    /// regardless of the test, so that verdict has no p-value.
+    fn p_value(&self) -> Option<f64> {
+        let result = self.test();
+        let exceeded = result.percentiles.is_some_and(|p| p.exceeded);
+        (!exceeded).then_some(result.p_value)
+    }
+
+    fn latency(&self) -> Option<LatencyResult> {
+        let empty = self.control().count() == 0 || self.experimental().count() == 0;
+        (!empty).then(|| self.test())
+    }
+
+    fn diagnostics(&self) -> Diagnostics {
+        let result = self.test();
+        Diagnostics {
+            engine: "latency",
+            values: vec![("p", result.p_value), ("effect size", result.effect_size)],
+            direction: None,
+        }
+    }
+}
+
+/// Returns the canary as worse if any of its tests says so, even
+/// when its 5XX rate, which the direction describes, is no different.
+fn worse_or(worse: bool, direction: Option<Direction>) -> Option<Direction> {
+    if worse {
+        Some(Direction::Worse)
+    } else {
+        direction
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use static_assertions::assert_obj_safe;
+
+    use super::{DecisionEngine, Verdict};
+    use crate::stats::fixtures::{outcomes, requests};
+    use crate::stats::{
+        AggregatedObservation, BayesianEngine, ChiSquareEngine, Direction, Group, LatencyEngine,
+        LatencyTest, Observation, ProportionEngine, SequentialEngine, StatusCategory,
+    };
+
+    assert_obj_safe!(DecisionEngine);
+
+    /// Build lopsided traffic: the canary fails 40 times as often,
+    /// and takes twice as long to serve each request.
+    fn failing() -> Vec<Observation> {
+        [
+            requests(Group::Control, 1, 100),
+            requests(Group::Experimental, 40, 200),
+        ]
+        .concat()
+    }
+
+    /// Feed the failing traffic to an engine.
+    fn evaluate(mut engine: impl DecisionEngine) -> Verdict {
+        engine.add_observations(&failing());
+        assert_eq!(engine.total_count(), 200);
+        engine.evaluate()
+    }
+
+    #[test]
+    fn engines_roll_back_failing_canaries() {
+        assert_eq!(evaluate(ChiSquareEngine::new()), Verdict::Rollback);
+        assert_eq!(evaluate(SequentialEngine::new()), Verdict::Rollback);
+        assert_eq!(evaluate(BayesianEngine::new()), Verdict::Rollback);
+        let latency = LatencyEngine::new(LatencyTest::MannWhitney);
+        assert_eq!(evaluate(latency), Verdict::Rollback);
+    }
+
+    /// Engines reach the same verdict from aggregates as from the
+    /// observations they count, without recording them one by one.
+    #[test]
+    fn engines_record_aggregates() {
+        let aggregates = [
+            (Group::Control, StatusCategory::_2XX, 99),
+            (Group::Control, StatusCategory::_5XX, 1),
+            (Group::Experimental, StatusCategory::_2XX, 60),
+            (Group::Experimental, StatusCategory::_5XX, 40),
+        ]
+        .map(|(group, outcome, count)| AggregatedObservation::new(group, outcome, count));
+        let engines: [Box<dyn DecisionEngine>; 4] = [
+            Box::new(ChiSquareEngine::new()),
+            Box::new(SequentialEngine::new()),
+            Box::new(BayesianEngine::new()),
+            Box::new(ProportionEngine::new()),
+        ];
+        for mut engine in engines {
+            engine.add_aggregates(&aggregates);
+            assert_eq!(engine.total_count(), 200);
+            assert_eq!(engine.evaluate(), Verdict::Rollback);
+        }
+        let mut latency = LatencyEngine::new(LatencyTest::MannWhitney);
+        latency.add_aggregates(&aggregates);
+        assert_eq!(DecisionEngine::total_count(&latency), 0);
+    }
+
+    /// Swapping the groups makes the canary the healthy one, which the
+    /// two-sided tests still find significant but don't fail it for.
+    /// It's far enough below the margin that the sequential test passes it.
+    #[test]
+    fn better_canaries_are_not_rolled_back() {
+        let improving: Vec<Observation> = failing()
+            .into_iter()
+            .map(|obs| Observation {
+                group: match obs.group {
+                    Group::Control => Group::Experimental,
+                    Group::Experimental => Group::Control,
+                },
+                ..obs
+            })
+            .collect();
+        let mut engine = ChiSquareEngine::new();
+        engine.add_observations(&improving);
+        assert!(engine.test().significant);
+        assert_eq!(engine.direction(), Some(Direction::Better));
+        assert_eq!(DecisionEngine::evaluate(&mut engine), Verdict::Promote);
+        let mut engine = SequentialEngine::new();
+        engine.add_observations(&improving);
+        assert_eq!(DecisionEngine::evaluate(&mut engine), Verdict::Promote);
+        assert_eq!(engine.direction(), Some(Direction::Better));
+        let mut engine = ProportionEngine::new();
+        engine.add_observations(&improving);
+        assert_eq!(engine.evaluate(), Verdict::Promote);
+        assert_eq!(evaluate(ProportionEngine::new()), Verdict::Rollback);
+    }
+
+    /// A canary that serves more 4XX but just as many 5XX differs
+    /// significantly from the control group, but isn't worse.
+    #[test]
+    fn client_errors_do_not_roll_back() {
+        let observations = [
+            outcomes(Group::Control, StatusCategory::_2XX, 900),
+            outcomes(Group::Control, StatusCategory::_4XX, 50),
+            outcomes(Group::Control, StatusCategory::_5XX, 50),
+            outcomes(Group::Experimental, StatusCategory::_2XX, 800),
+            outcomes(Group::Experimental, StatusCategory::_4XX, 150),
+            outcomes(Group::Experimental, StatusCategory::_5XX, 50),
+        ]
+        .concat();
+        let mut engine = ChiSquareEngine::new();
+        engine.add_observations(&observations);
+        assert!(engine.test().significant);
+        assert_eq!(engine.direction(), Some(Direction::NoDifference));
+        assert_eq!(DecisionEngine::evaluate(&mut engine), Verdict::Promote);
+        let mut engine = SequentialEngine::new();
+        engine.add_observations(&observations);
+        assert_eq!(engine.evaluate(), Verdict::Continue);
+    }
+
+    /// The chi-square engine won't decide on a handful of observations,
+    /// however lopsided they are.
+    #[test]
+    fn small_samples_are_not_decided() {
+        let mut engine = ChiSquareEngine::new().with_min_group_size(50);
+        engine.add_observations(&failing()[60..140]);
+        assert!(engine.test().significant);
+        assert_eq!(DecisionEngine::evaluate(&mut engine), Verdict::Continue);
+        engine.add_observations(&failing()[..60]);
+        engine.add_observations(&failing()[140..]);
+        assert!(DecisionEngine::has_min_sample(&engine));
+        assert_eq!(DecisionEngine::evaluate(&mut engine), Verdict::Rollback);
+    }
+
+    /// With no observations, there's nothing to test.
+    #[test]
+    fn empty_engines_are_inconclusive() {
+        let mut engine = ChiSquareEngine::new().with_min_group_size(0);
+        assert_eq!(DecisionEngine::evaluate(&mut engine), Verdict::Inconclusive);
+        assert_eq!(engine.diagnostics().to_string(), "chi-square: untested");
+        let mut engine = LatencyEngine::new(LatencyTest::MannWhitney);
+        assert_eq!(DecisionEngine::evaluate(&mut engine), Verdict::Inconclusive);
+    }
+}
@@ -0,0 +1,84 @@
+use miette::Diagnostic;
+use thiserror::Error;
+
+/// A [StatsError] describes why a statistical test couldn't be run
+/// with the parameters it was given.
+#[derive(Error, Diagnostic, Debug, PartialEq, Clone)]
+pub enum StatsError {
+    /// A significance level must be a probability strictly between 0 and 1.
+    #[error("alpha must be between 0 and 1, but was {0}")]
+    #[diagnostic(
+        code(canary::stats::alpha),
+        help("0.05 is a common choice, for a 5% chance of a false positive")
+    )]
+    Alpha(f64),
+    /// The significance level wasn't a number.
+    #[error("alpha must be a number, but was {0:?}")]
+    #[diagnostic(code(canary::stats::parse))]
+    Parse(String),
+    /// The power must be a probability strictly between 0 and 1.
+    #[error("power must be between 0 and 1, but was {0}")]
+    #[diagnostic(code(canary::stats::power), help("0.8 is a common choice"))]
+    Power(f64),
+    /// The error rates of a power calculation must be probabilities,
+    /// and the canary's must be higher.
+    #[error("the baseline error rate ({baseline}) plus the effect ({effect}) must be less than 1")]
+    #[diagnostic(
+        code(canary::stats::rates),
+        help("both must be positive, and the canary's error rate can't exceed 100%")
+    )]
+    Rates { baseline: f64, effect: f64 },
+    /// The gRPC status wasn't a known code or name.
+    #[error("unknown gRPC status code {0:?}")]
+    #[diagnostic(
+        code(canary::stats::grpc_code),
+        help("use a number from 0 to 16, or a name like UNAVAILABLE")
+    )]
+    GrpcCode(String),
+    /// HTTP status codes have three digits, from 100 to 599.
+    #[error("{0} isn't an HTTP status code")]
+    #[diagnostic(
+        code(canary::stats::status_code),
+        help("status codes range from 100 to 599")
+    )]
+    StatusCode(u16),
+    /// A set of status codes tested separately must stay within one
+    /// category, and mustn't share codes with another set.
+    #[error("the status codes {0:?} can't be tested as one set")]
+    #[diagnostic(
+        code(canary::stats::code_set),
+        help("keep each set within one category, like [502, 503, 504], and list each code once")
+    )]
+    CodeSet(Vec<u16>),
+    /// The Bayesian engine's margin is a difference between error rates,
+    /// so it must be at least 0 and less than 1.
+    #[error("the margin must be at least 0 and less than 1, but was {0}")]
+    #[diagnostic(
+        code(canary::stats::margin),
+        help("0.005 ignores differences of up to half a percent")
+    )]
+    Margin(f64),
+    /// The rollback threshold is a probability strictly between 0 and 1.
+    #[error("the rollback threshold must be between 0 and 1, but was {0}")]
+    #[diagnostic(
+        code(canary::stats::rollback_threshold),
+        help("0.95 rolls back once the canary is worse with 95% probability")
+    )]
+    RollbackThreshold(f64),
+    /// The loss threshold is an error rate, so it must be at least 0
+    /// and less than 1.
+    #[error("the loss threshold must be at least 0 and less than 1, but was {0}")]
+    #[diagnostic(
+        code(canary::stats::loss_threshold),
+        help("0.001 passes the canary once promoting it costs less than a tenth of a percent")
+    )]
+    LossThreshold(f64),
+    /// A contingency table needs at least two categories to compare.
+    #[error("a contingency table needs at least two categories, but had {0}")]
+    #[diagnostic(code(canary::stats::categories))]
+    TooFewCategories(u64),
+    /// The observed and expected tables have different categories.
+    #[error("the observed table has {observed} degrees of freedom, but the expected table has {expected}")]
+    #[diagnostic(code(canary::stats::degrees_of_freedom))]
+    MismatchedTables { observed: u64, expected: u64 },
+}
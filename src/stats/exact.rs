@@ -0,0 +1,130 @@
+use rand::distributions::Distribution;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use statrs::distribution::Hypergeometric;
+use statrs::function::factorial::ln_binomial;
+
+use super::pearson_statistic;
+
+/// By default, the permutation test samples this many random tables,
+/// which puts the p-value within about half a percentage point.
+pub const DEFAULT_PERMUTATIONS: usize = 10_000;
+
+/// The permutation test draws its tables from a fixed seed,
+/// so the same observations always produce the same p-value.
+const PERMUTATION_SEED: u64 = 0x5EED;
+
+/// Probabilities this close to the observed table's are treated as
+/// equal, so rounding error doesn't exclude tables that tie with it.
+const RELATIVE_TOLERANCE: f64 = 1e-7;
+
+/// Returns the two-sided p-value of Fisher's exact test on a 2×2 table,
+/// given as two (control, experimental) columns with no empty rows.
+///
+/// With the row and column totals fixed, the control group's count in the
+/// first column follows a hypergeometric distribution. The p-value is the
+/// total probability of every table no more likely than the observed one.
+/// # Panics
+/// This method panics unless there are exactly two columns.
+pub fn fisher_exact(columns: &[(u64, u64)]) -> f64 {
+    let [(a, c), (b, d)] = columns else {
+        panic!("Fisher's exact test requires a 2×2 table.");
+    };
+    let control_total = a + b;
+    let (first, second) = (a + c, b + d);
+    let grand_total = first + second;
+    // • The log-probability that the control group has x
+    //   observations in the first column.
+    let log_probability = |x: u64| {
+        ln_binomial(first, x) + ln_binomial(second, control_total - x)
+            - ln_binomial(grand_total, control_total)
+    };
+    let observed = log_probability(*a);
+    let low = control_total.saturating_sub(second);
+    let high = control_total.min(first);
+    let p_value: f64 = (low..=high)
+        .map(log_probability)
+        .filter(|log_p| *log_p <= observed + RELATIVE_TOLERANCE)
+        .map(f64::exp)
+        .sum();
+    p_value.min(1.0)
+}
+
+/// Returns the p-value of a Monte Carlo permutation test on a 2×k table,
+/// given as (control, experimental) columns with no empty rows or columns.
+///
+/// Under the null hypothesis, the group labels are exchangeable: every way
+/// of splitting each column between the groups, keeping the row totals
+/// fixed, is equally likely. We sample that many random splits and count
+/// how often the Pearson statistic is at least as large as the observed one.
+pub fn permutation_test(columns: &[(u64, u64)], statistic: f64, rounds: usize) -> f64 {
+    let control_total: u64 = columns.iter().map(|(control, _)| control).sum();
+    let grand_total: u64 = columns.iter().map(|(c, e)| c + e).sum();
+    // • Drawing the smallest columns first keeps sampling cheap: the
+    //   largest column, usually the successes, is whatever remains.
+    let mut sizes: Vec<u64> = columns.iter().map(|(c, e)| c + e).collect();
+    sizes.sort_unstable();
+    let mut rng = StdRng::seed_from_u64(PERMUTATION_SEED);
+    let mut table = vec![(0, 0); sizes.len()];
+    let threshold = statistic * (1.0 - RELATIVE_TOLERANCE);
+    let extreme = (0..rounds)
+        .filter(|_| {
+            let (mut population, mut control) = (grand_total, control_total);
+            for (cell, size) in table.iter_mut().zip(&sizes) {
+                let drawn = if population == *size {
+                    control
+                } else {
+                    Hypergeometric::new(population, control, *size)
+                        .expect("the column fits in the remaining population")
+                        .sample(&mut rng) as u64
+                };
+                *cell = (drawn, size - drawn);
+                population -= size;
+                control -= drawn;
+            }
+            pearson_statistic(&table) >= threshold
+        })
+        .count();
+    // • Counting the observed table among the samples
+    //   keeps the p-value valid, and never zero.
+    (1 + extreme) as f64 / (1 + rounds) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fisher_exact, permutation_test};
+    use crate::stats::pearson_statistic;
+
+    /// Fisher's tea-tasting experiment: a lady correctly identifies three
+    /// of four cups with milk poured first. The two-sided p-value is 34/70.
+    #[test]
+    fn tea_tasting() {
+        let p_value = fisher_exact(&[(3, 1), (1, 3)]);
+        assert!((p_value - 34.0 / 70.0).abs() < 1e-9, "p = {p_value}");
+        // • Identifying all four is the most extreme result.
+        let p_value = fisher_exact(&[(4, 0), (0, 4)]);
+        assert!((p_value - 2.0 / 70.0).abs() < 1e-9, "p = {p_value}");
+    }
+
+    /// A canary with a handful of server errors, where the control
+    /// group has none, is detected despite the tiny expected counts.
+    #[test]
+    fn rare_errors() {
+        let p_value = fisher_exact(&[(200, 193), (0, 7)]);
+        assert!(0.005 < p_value && p_value < 0.02, "p = {p_value}");
+        let p_value = fisher_exact(&[(200, 199), (0, 1)]);
+        assert!(p_value > 0.999, "p = {p_value}");
+    }
+
+    /// The permutation test agrees with Fisher's exact test on a 2×2
+    /// table, and is reproducible.
+    #[test]
+    fn permutation_agrees_with_fisher() {
+        let columns = [(30, 22), (2, 9)];
+        let exact = fisher_exact(&columns);
+        let statistic = pearson_statistic(&columns);
+        let permuted = permutation_test(&columns, statistic, 20_000);
+        assert!((exact - permuted).abs() < 0.01, "{exact} vs {permuted}");
+        assert_eq!(permuted, permutation_test(&columns, statistic, 20_000));
+    }
+}
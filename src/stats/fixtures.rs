@@ -0,0 +1,68 @@
+use std::iter::repeat_n;
+use std::time::Duration;
+
+use rand::Rng;
+
+use super::{Group, Observation, StatusCategory};
+
+/// Build `count` observations of the outcome in the given group.
+pub fn outcomes(group: Group, outcome: StatusCategory, count: usize) -> Vec<Observation> {
+    repeat_n(Observation::new(group, outcome), count).collect()
+}
+
+/// Build the given number of successes, then server errors, in a group.
+pub fn traffic(group: Group, successes: usize, errors: usize) -> Vec<Observation> {
+    let mut observations = outcomes(group, StatusCategory::_2XX, successes);
+    observations.extend(outcomes(group, StatusCategory::_5XX, errors));
+    observations
+}
+
+/// Build one successful request per latency, in milliseconds.
+pub fn latencies(group: Group, millis: impl IntoIterator<Item = u64>) -> Vec<Observation> {
+    millis
+        .into_iter()
+        .map(|millis| {
+            Observation::new(group, StatusCategory::_2XX)
+                .with_latency(Duration::from_millis(millis))
+        })
+        .collect()
+}
+
+/// Build 100 requests from a group, the first `errors` of which fail,
+/// taking between `millis` and `millis + 49` milliseconds each.
+pub fn requests(group: Group, errors: usize, millis: u64) -> Vec<Observation> {
+    (0..100)
+        .map(|i| {
+            let outcome = if i < errors {
+                StatusCategory::_5XX
+            } else {
+                StatusCategory::_2XX
+            };
+            Observation::new(group, outcome)
+                .with_latency(Duration::from_millis(millis + i as u64 % 50))
+        })
+        .collect()
+}
+
+/// Build 100 requests from each group, which
+/// fail with the given probabilities.
+pub fn random_traffic(rng: &mut impl Rng, error_rates: (f64, f64)) -> Vec<Observation> {
+    [
+        (Group::Control, error_rates.0),
+        (Group::Experimental, error_rates.1),
+    ]
+    .into_iter()
+    .flat_map(|(group, rate)| {
+        (0..100)
+            .map(|_| {
+                let outcome = if rng.gen_bool(rate) {
+                    StatusCategory::_5XX
+                } else {
+                    StatusCategory::_2XX
+                };
+                Observation::new(group, outcome)
+            })
+            .collect::<Vec<_>>()
+    })
+    .collect()
+}
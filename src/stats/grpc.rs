@@ -0,0 +1,226 @@
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use super::{EnumerableCategory, StatsError, StatusCategory};
+
+/// By default, these codes count as failures: the ones a server returns
+/// when it's at fault, which are also the ones gRPC gateways map to 5XX.
+pub const DEFAULT_GRPC_FAILURES: [GrpcCode; 6] = [
+    GrpcCode::Unknown,
+    GrpcCode::DeadlineExceeded,
+    GrpcCode::Unimplemented,
+    GrpcCode::Internal,
+    GrpcCode::Unavailable,
+    GrpcCode::DataLoss,
+];
+
+/// A [GrpcCode] is the status a gRPC call finished with. gRPC responses
+/// are almost always HTTP 200, so the outcome of the call lives in the
+/// `grpc-status` trailer instead.
+#[derive(Deserialize, Hash, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum GrpcCode {
+    Ok,
+    Cancelled,
+    Unknown,
+    InvalidArgument,
+    DeadlineExceeded,
+    NotFound,
+    AlreadyExists,
+    PermissionDenied,
+    ResourceExhausted,
+    FailedPrecondition,
+    Aborted,
+    OutOfRange,
+    Unimplemented,
+    Internal,
+    Unavailable,
+    DataLoss,
+    Unauthenticated,
+}
+
+impl GrpcCode {
+    /// Every code, in order of its numeric value.
+    const ALL: [GrpcCode; 17] = [
+        Self::Ok,
+        Self::Cancelled,
+        Self::Unknown,
+        Self::InvalidArgument,
+        Self::DeadlineExceeded,
+        Self::NotFound,
+        Self::AlreadyExists,
+        Self::PermissionDenied,
+        Self::ResourceExhausted,
+        Self::FailedPrecondition,
+        Self::Aborted,
+        Self::OutOfRange,
+        Self::Unimplemented,
+        Self::Internal,
+        Self::Unavailable,
+        Self::DataLoss,
+        Self::Unauthenticated,
+    ];
+
+    /// Returns the code with the given numeric value, if there is one.
+    pub fn from_code(code: u32) -> Option<Self> {
+        Self::ALL.get(code as usize).copied()
+    }
+
+    /// Returns the numeric value of the code, e.g. 14 for UNAVAILABLE.
+    pub fn code(self) -> u32 {
+        self as u32
+    }
+
+    /// Returns the canonical name of the code, e.g. "DEADLINE_EXCEEDED".
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Ok => "OK",
+            Self::Cancelled => "CANCELLED",
+            Self::Unknown => "UNKNOWN",
+            Self::InvalidArgument => "INVALID_ARGUMENT",
+            Self::DeadlineExceeded => "DEADLINE_EXCEEDED",
+            Self::NotFound => "NOT_FOUND",
+            Self::AlreadyExists => "ALREADY_EXISTS",
+            Self::PermissionDenied => "PERMISSION_DENIED",
+            Self::ResourceExhausted => "RESOURCE_EXHAUSTED",
+            Self::FailedPrecondition => "FAILED_PRECONDITION",
+            Self::Aborted => "ABORTED",
+            Self::OutOfRange => "OUT_OF_RANGE",
+            Self::Unimplemented => "UNIMPLEMENTED",
+            Self::Internal => "INTERNAL",
+            Self::Unavailable => "UNAVAILABLE",
+            Self::DataLoss => "DATA_LOSS",
+            Self::Unauthenticated => "UNAUTHENTICATED",
+        }
+    }
+}
+
+impl FromStr for GrpcCode {
+    type Err = StatsError;
+
+    /// Parse a code from its numeric value, as it appears in the
+    /// `grpc-status` trailer, or from its name in any case.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let code = match value.parse::<u32>() {
+            Ok(number) => Self::from_code(number),
+            Err(_) => Self::ALL
+                .into_iter()
+                .find(|code| code.name().eq_ignore_ascii_case(&value.replace('-', "_"))),
+        };
+        code.ok_or_else(|| StatsError::GrpcCode(value.to_owned()))
+    }
+}
+
+impl EnumerableCategory for GrpcCode {
+    fn groups() -> Box<dyn Iterator<Item = Self>> {
+        Box::new(Self::ALL.into_iter())
+    }
+}
+
+/// The [GrpcFailures] are the codes that count against a deployment. Which
+/// codes those are depends on the service: a NOT_FOUND may be a client's
+/// mistake for one service and a lost record for another.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct GrpcFailures {
+    codes: BTreeSet<GrpcCode>,
+}
+
+impl Default for GrpcFailures {
+    fn default() -> Self {
+        Self::new(DEFAULT_GRPC_FAILURES)
+    }
+}
+
+impl GrpcFailures {
+    /// Count exactly these codes as failures.
+    pub fn new(codes: impl IntoIterator<Item = GrpcCode>) -> Self {
+        Self {
+            codes: codes.into_iter().collect(),
+        }
+    }
+
+    /// Returns true if the code counts as a failure.
+    pub fn is_failure(&self, code: GrpcCode) -> bool {
+        self.codes.contains(&code)
+    }
+
+    /// Classify a code like the equivalent HTTP response, so every engine
+    /// that compares status categories also works on gRPC traffic: failures
+    /// are server errors, OK is a success, and anything else is a client
+    /// error.
+    pub fn outcome(&self, code: GrpcCode) -> StatusCategory {
+        if self.is_failure(code) {
+            StatusCategory::_5XX
+        } else if code == GrpcCode::Ok {
+            StatusCategory::_2XX
+        } else {
+            StatusCategory::_4XX
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{GrpcCode, GrpcFailures};
+    use crate::stats::{
+        ChiSquareEngine, EnumerableCategory, Group, Observation, StatsError, StatusCategory,
+    };
+
+    #[test]
+    fn parses_codes_and_names() {
+        assert_eq!("14".parse(), Ok(GrpcCode::Unavailable));
+        assert_eq!("0".parse(), Ok(GrpcCode::Ok));
+        assert_eq!("deadline-exceeded".parse(), Ok(GrpcCode::DeadlineExceeded));
+        assert_eq!("DATA_LOSS".parse(), Ok(GrpcCode::DataLoss));
+        assert_eq!(
+            "17".parse::<GrpcCode>(),
+            Err(StatsError::GrpcCode("17".to_owned()))
+        );
+        assert!(GrpcCode::groups().all(|code| GrpcCode::from_code(code.code()) == Some(code)));
+    }
+
+    /// Which codes fail a deployment is configurable.
+    #[test]
+    fn failures_are_configurable() {
+        let failures = GrpcFailures::default();
+        assert_eq!(
+            failures.outcome(GrpcCode::Unavailable),
+            StatusCategory::_5XX
+        );
+        assert_eq!(failures.outcome(GrpcCode::NotFound), StatusCategory::_4XX);
+        assert_eq!(failures.outcome(GrpcCode::Ok), StatusCategory::_2XX);
+        let failures = GrpcFailures::new([GrpcCode::NotFound]);
+        assert!(failures.is_failure(GrpcCode::NotFound));
+        assert!(!failures.is_failure(GrpcCode::Unavailable));
+    }
+
+    /// The chi-square engine can compare the groups' gRPC codes
+    /// directly, as well as the status categories they map to.
+    #[test]
+    fn grpc_traffic_is_testable() {
+        let failures = GrpcFailures::default();
+        let mut codes = ChiSquareEngine::<GrpcCode>::default();
+        let mut categories = ChiSquareEngine::new();
+        for (group, code, count) in [
+            (Group::Control, GrpcCode::Ok, 95),
+            (Group::Control, GrpcCode::Unavailable, 5),
+            (Group::Experimental, GrpcCode::Ok, 75),
+            (Group::Experimental, GrpcCode::Unavailable, 25),
+        ] {
+            for _ in 0..count {
+                let obs = Observation::grpc(group, code, &failures);
+                assert_eq!(obs.grpc_status, Some(code));
+                codes.add(group, code);
+                categories.add_observation(obs);
+            }
+        }
+        assert!(codes.test().significant);
+        assert!(categories.test().significant);
+        assert_eq!(codes.test().statistic, categories.test().statistic);
+    }
+}
@@ -0,0 +1,193 @@
+use std::fmt::{self, Display};
+
+use statrs::distribution::{ContinuousCDF, Normal};
+
+use super::Alpha;
+
+/// An [Interval] is a confidence interval around an estimate.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Interval {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl Interval {
+    /// Returns true if the value lies within the interval.
+    pub fn contains(&self, value: f64) -> bool {
+        self.lower <= value && value <= self.upper
+    }
+}
+
+/// A [RateEstimate] is an observed rate with its confidence interval.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RateEstimate {
+    pub rate: f64,
+    pub interval: Interval,
+}
+
+/// The [ErrorRates] of both groups, and the difference between them,
+/// each with a confidence interval at 1 - alpha.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ErrorRates {
+    /// The control group's 5XX rate, with its Wilson score interval.
+    pub control: RateEstimate,
+    /// The canary's 5XX rate, with its Wilson score interval.
+    pub experimental: RateEstimate,
+    /// The canary's rate minus the control group's, with Newcombe's
+    /// hybrid score interval.
+    pub difference: RateEstimate,
+}
+
+impl ErrorRates {
+    /// Estimate the error rates from the (errors, observations) of each
+    /// group. Returns None if either group has no observations.
+    pub fn new(control: (u64, u64), experimental: (u64, u64), alpha: Alpha) -> Option<Self> {
+        if control.1 == 0 || experimental.1 == 0 {
+            return None;
+        }
+        let control = wilson(control, alpha);
+        let experimental = wilson(experimental, alpha);
+        Some(Self {
+            control,
+            experimental,
+            difference: newcombe(control, experimental),
+        })
+    }
+}
+
+impl Display for ErrorRates {
+    /// Formats the rates as percentages, like "canary 5XX rate 0.80%
+    /// [0.50, 1.20] vs baseline 0.30% [0.20, 0.40], difference +0.50%
+    /// [+0.21, +0.87]".
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = |estimate: RateEstimate| {
+            let Interval { lower, upper } = estimate.interval;
+            format!(
+                "{:.2}% [{:.2}, {:.2}]",
+                estimate.rate * 100.0,
+                lower * 100.0,
+                upper * 100.0
+            )
+        };
+        let Interval { lower, upper } = self.difference.interval;
+        write!(
+            f,
+            "canary 5XX rate {} vs baseline {}, difference {:+.2}% [{:+.2}, {:+.2}]",
+            percent(self.experimental),
+            percent(self.control),
+            self.difference.rate * 100.0,
+            lower * 100.0,
+            upper * 100.0,
+        )
+    }
+}
+
+/// Returns the observed rate of the given (errors, observations), with its
+/// Wilson score interval. Unlike the normal approximation, the Wilson
+/// interval stays within [0, 1] and keeps its coverage for rates near zero,
+/// which is where error rates usually are:
+///
+/// ```text
+/// (p̂ + z²/2n ± z·√(p̂(1 - p̂)/n + z²/4n²)) / (1 + z²/n)
+/// ```
+///
+/// where z is the 1 - α/2 quantile of the standard normal distribution.
+pub fn wilson((errors, total): (u64, u64), alpha: Alpha) -> RateEstimate {
+    if total == 0 {
+        return RateEstimate {
+            rate: 0.0,
+            interval: Interval {
+                lower: 0.0,
+                upper: 1.0,
+            },
+        };
+    }
+    let n = total as f64;
+    let rate = errors as f64 / n;
+    let z = Normal::new(0.0, 1.0)
+        .expect("the standard normal distribution is valid")
+        .inverse_cdf(1.0 - alpha.get() / 2.0);
+    let z2 = z * z;
+    let center = (rate + z2 / (2.0 * n)) / (1.0 + z2 / n);
+    let half_width = z / (1.0 + z2 / n) * (rate * (1.0 - rate) / n + z2 / (4.0 * n * n)).sqrt();
+    // • At a rate of 0 or 1, one bound is exactly that rate,
+    //   but the subtraction leaves a little rounding error.
+    RateEstimate {
+        rate,
+        interval: Interval {
+            lower: if errors == 0 {
+                0.0
+            } else {
+                center - half_width
+            },
+            upper: if errors == total {
+                1.0
+            } else {
+                center + half_width
+            },
+        },
+    }
+}
+
+/// Returns the difference between two rates, the second minus the first,
+/// with Newcombe's hybrid score interval. Each bound combines the distances
+/// from the rates to their own Wilson bounds in quadrature, which keeps the
+/// interval well behaved when either rate is zero.
+pub fn newcombe(first: RateEstimate, second: RateEstimate) -> RateEstimate {
+    let difference = second.rate - first.rate;
+    let below = (second.rate - second.interval.lower).hypot(first.interval.upper - first.rate);
+    let above = (second.interval.upper - second.rate).hypot(first.rate - first.interval.lower);
+    RateEstimate {
+        rate: difference,
+        interval: Interval {
+            lower: difference - below,
+            upper: difference + above,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{newcombe, wilson, ErrorRates};
+    use crate::stats::DEFAULT_ALPHA_CUTOFF;
+
+    /// Compare to four decimal places.
+    fn close(actual: f64, expected: f64) -> bool {
+        (actual - expected).abs() < 5e-5
+    }
+
+    /// The examples are from Newcombe, "Two-sided confidence intervals for
+    /// the single proportion" and "Interval estimation for the difference
+    /// between independent proportions" (1998).
+    #[test]
+    fn published_examples() {
+        let estimate = wilson((81, 263), DEFAULT_ALPHA_CUTOFF);
+        assert!(close(estimate.interval.lower, 0.2553), "{estimate:?}");
+        assert!(close(estimate.interval.upper, 0.3662), "{estimate:?}");
+        let estimate = wilson((0, 10), DEFAULT_ALPHA_CUTOFF);
+        assert_eq!(estimate.interval.lower, 0.0);
+        assert!(close(estimate.interval.upper, 0.2775), "{estimate:?}");
+
+        let difference = newcombe(
+            wilson((48, 80), DEFAULT_ALPHA_CUTOFF),
+            wilson((56, 70), DEFAULT_ALPHA_CUTOFF),
+        );
+        assert!(close(difference.rate, 0.2));
+        assert!(close(difference.interval.lower, 0.0524), "{difference:?}");
+        assert!(close(difference.interval.upper, 0.3339), "{difference:?}");
+    }
+
+    #[test]
+    fn formats_as_percentages() {
+        let rates = ErrorRates::new((30, 10_000), (80, 10_000), DEFAULT_ALPHA_CUTOFF).unwrap();
+        assert!(rates.difference.interval.contains(0.005));
+        assert!(!rates.difference.interval.contains(0.0));
+        assert_eq!(
+            rates.to_string(),
+            "canary 5XX rate 0.80% [0.64, 0.99] vs baseline 0.30% [0.21, 0.43], difference +0.50% [+0.30, +0.71]"
+        );
+        assert_eq!(ErrorRates::new((0, 0), (1, 1), DEFAULT_ALPHA_CUTOFF), None);
+    }
+}
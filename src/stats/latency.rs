@@ -0,0 +1,453 @@
+use serde::Deserialize;
+use statrs::distribution::{ContinuousCDF, Normal};
+
+use super::{Alpha, DDSketch, Group, Observation, DEFAULT_ALPHA_CUTOFF};
+
+/// By default, a latency difference must be at least this large before
+/// it's considered a regression, no matter how significant it is.
+pub const DEFAULT_MIN_EFFECT_SIZE: f64 = 0.05;
+
+/// The [LatencyTest] selects the non-parametric test used to compare
+/// the latency distributions of the control and experimental groups.
+/// Both tests are one-sided: only a canary that is *slower* than the
+/// control group is significant.
+#[derive(Deserialize, clap::ValueEnum, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum LatencyTest {
+    /// The Mann–Whitney U test asks whether a request served by the canary
+    /// tends to be slower than one served by the control group. Its effect
+    /// size is how much more often than not the canary is slower: 0.05 means
+    /// the canary is slower in 55% of pairs of requests.
+    #[default]
+    MannWhitney,
+    /// The two-sample Kolmogorov–Smirnov test finds the largest gap between
+    /// the two latency distributions, wherever it is, so it also catches
+    /// regressions confined to the tail. Its effect size is that gap: 0.05
+    /// means that, at some latency, 5% more of the canary's requests are
+    /// slower than it than the control group's.
+    KolmogorovSmirnov,
+}
+
+/// The [LatencyResult] summarizes a comparison of the latency
+/// distributions of the control and experimental groups.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LatencyResult {
+    /// The test that produced this result.
+    pub test: LatencyTest,
+    /// The test statistic: U for Mann–Whitney, D⁺ for Kolmogorov–Smirnov.
+    pub statistic: f64,
+    /// The probability of a statistic at least this large if the
+    /// canary were no slower than the control group.
+    pub p_value: f64,
+    /// How much slower the canary is, on the test's own scale.
+    /// Negative values mean the canary is faster.
+    pub effect_size: f64,
+    /// Whether the canary is significantly slower by at
+    /// least the minimum effect size.
+    pub significant: bool,
+    /// The comparison of the groups' percentiles, if one was requested.
+    pub percentiles: Option<PercentileComparison>,
+}
+
+impl LatencyResult {
+    /// The result reported when there isn't enough data to run the test.
+    fn inconclusive(test: LatencyTest) -> Self {
+        Self {
+            test,
+            statistic: 0.0,
+            p_value: 1.0,
+            effect_size: 0.0,
+            significant: false,
+            percentiles: None,
+        }
+    }
+
+    /// Returns true if the canary's latency regressed: either it's
+    /// significantly slower, or its percentiles exceed the tolerance.
+    pub fn regressed(&self) -> bool {
+        self.significant || self.percentiles.is_some_and(|p| p.exceeded)
+    }
+}
+
+/// A [PercentileDelta] compares one percentile of the two groups' latencies.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PercentileDelta {
+    /// The quantile being compared, e.g. 0.99 for p99.
+    pub quantile: f64,
+    /// The control group's latency at this quantile, in milliseconds.
+    pub control: f64,
+    /// The canary's latency at this quantile, in milliseconds.
+    pub experimental: f64,
+}
+
+impl PercentileDelta {
+    /// Returns how much slower the canary is at this percentile, relative to
+    /// the control group: 0.1 means 10% slower. Since both estimates are
+    /// within the sketch's relative accuracy, so is this delta, roughly.
+    pub fn relative_delta(&self) -> f64 {
+        if self.control > 0.0 {
+            (self.experimental - self.control) / self.control
+        } else if self.experimental > 0.0 {
+            f64::INFINITY
+        } else {
+            0.0
+        }
+    }
+}
+
+/// A [PercentileComparison] compares the p50, p90 and p99 latencies of the
+/// two groups against a tolerance. Unlike the hypothesis tests, it has no
+/// p-value: it's a guardrail on the percentiles operators watch.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PercentileComparison {
+    /// The p50, p90 and p99 deltas, in that order.
+    pub deltas: [PercentileDelta; 3],
+    /// Whether the canary exceeded the tolerance at any of them.
+    pub exceeded: bool,
+}
+
+/// The [LatencyEngine] sketches the latency of every observation and tests
+/// whether the canary is slower than the control group. Observations
+/// without a latency are ignored.
+///
+/// Latencies are kept in a [DDSketch] per group, so memory stays bounded
+/// however long the canary runs. The tests treat latencies in the same bin
+/// as tied, which costs a little power but no validity.
+///
+/// A large enough sample makes even a negligible difference significant,
+/// so a regression must also reach the minimum effect size.
+#[derive(Clone)]
+pub struct LatencyEngine {
+    test: LatencyTest,
+    /// Latencies in milliseconds, by group.
+    control: DDSketch,
+    experimental: DDSketch,
+    alpha_cutoff: Alpha,
+    min_effect_size: f64,
+    /// How much slower the canary's percentiles may be, if they're compared.
+    percentile_tolerance: Option<f64>,
+}
+
+impl LatencyEngine {
+    pub fn new(test: LatencyTest) -> Self {
+        Self {
+            test,
+            control: DDSketch::new(),
+            experimental: DDSketch::new(),
+            alpha_cutoff: DEFAULT_ALPHA_CUTOFF,
+            min_effect_size: DEFAULT_MIN_EFFECT_SIZE,
+            percentile_tolerance: None,
+        }
+    }
+
+    /// Test for significance at the given alpha cutoff.
+    pub fn with_alpha_cutoff(self, alpha_cutoff: Alpha) -> Self {
+        Self {
+            alpha_cutoff,
+            ..self
+        }
+    }
+
+    /// Ignore regressions smaller than the given effect size.
+    pub fn with_min_effect_size(self, min_effect_size: f64) -> Self {
+        Self {
+            min_effect_size,
+            ..self
+        }
+    }
+
+    /// Also compare the p50, p90 and p99 latencies, treating a canary
+    /// that's slower than the given relative tolerance at any of
+    /// them as a regression.
+    pub fn with_percentile_tolerance(self, tolerance: f64) -> Self {
+        Self {
+            percentile_tolerance: Some(tolerance),
+            ..self
+        }
+    }
+
+    pub fn add_observation(&mut self, obs: Observation) {
+        let Some(latency) = obs.latency else {
+            return;
+        };
+        let millis = latency.as_secs_f64() * 1000.0;
+        match obs.group {
+            Group::Control => self.control.add(millis),
+            Group::Experimental => self.experimental.add(millis),
+        }
+    }
+
+    /// Forget every latency, keeping the engine's settings.
+    pub fn reset(&mut self) {
+        self.control = DDSketch::new();
+        self.experimental = DDSketch::new();
+    }
+
+    /// Compare the latency distributions of the two groups.
+    /// If either group has no latencies, the result is never significant.
+    pub fn test(&self) -> LatencyResult {
+        if self.control.count() == 0 || self.experimental.count() == 0 {
+            return LatencyResult::inconclusive(self.test);
+        }
+        let control: Vec<_> = self.control.bins().collect();
+        let experimental: Vec<_> = self.experimental.bins().collect();
+        let result = match self.test {
+            LatencyTest::MannWhitney => mann_whitney_u(&control, &experimental),
+            LatencyTest::KolmogorovSmirnov => kolmogorov_smirnov(&control, &experimental),
+        };
+        LatencyResult {
+            significant: self.alpha_cutoff.rejects(result.p_value)
+                && result.effect_size >= self.min_effect_size,
+            percentiles: self.compare_percentiles(),
+            ..result
+        }
+    }
+
+    /// Compare the p50, p90 and p99 latencies of the two groups, if a
+    /// tolerance is set and both groups have latencies.
+    fn compare_percentiles(&self) -> Option<PercentileComparison> {
+        let tolerance = self.percentile_tolerance?;
+        let delta = |quantile| {
+            Some(PercentileDelta {
+                quantile,
+                control: self.control.quantile(quantile)?,
+                experimental: self.experimental.quantile(quantile)?,
+            })
+        };
+        let deltas = [delta(0.50)?, delta(0.90)?, delta(0.99)?];
+        Some(PercentileComparison {
+            deltas,
+            exceeded: deltas
+                .iter()
+                .any(|delta| delta.relative_delta() > tolerance),
+        })
+    }
+
+    /// Returns the sketch of the control group's latencies.
+    pub fn control(&self) -> &DDSketch {
+        &self.control
+    }
+
+    /// Returns the sketch of the canary's latencies.
+    pub fn experimental(&self) -> &DDSketch {
+        &self.experimental
+    }
+
+    /// returns the number of latencies recorded across both groups.
+    pub fn total_count(&self) -> usize {
+        (self.control.count() + self.experimental.count()) as usize
+    }
+}
+
+/// Merge two samples of (latency, count) pairs, each in ascending order,
+/// into the (control count, experimental count) at each distinct latency.
+fn pool(control: &[(f64, u64)], experimental: &[(f64, u64)]) -> Vec<(u64, u64)> {
+    let mut pooled = Vec::with_capacity(control.len() + experimental.len());
+    let (mut i, mut j) = (0, 0);
+    while i < control.len() || j < experimental.len() {
+        let latency = match (control.get(i), experimental.get(j)) {
+            (Some(c), Some(e)) => c.0.min(e.0),
+            (Some(c), None) => c.0,
+            (None, Some(e)) => e.0,
+            (None, None) => unreachable!("the loop ends when both samples are consumed"),
+        };
+        let mut counts = (0, 0);
+        if let Some((_, count)) = control.get(i).filter(|c| c.0 == latency) {
+            counts.0 = *count;
+            i += 1;
+        }
+        if let Some((_, count)) = experimental.get(j).filter(|e| e.0 == latency) {
+            counts.1 = *count;
+            j += 1;
+        }
+        pooled.push(counts);
+    }
+    pooled
+}
+
+/// Returns the total count of a sample of (latency, count) pairs.
+fn total(sample: &[(f64, u64)]) -> f64 {
+    sample.iter().map(|(_, count)| *count as f64).sum()
+}
+
+/// Run a one-sided Mann–Whitney U test of whether the experimental latencies
+/// tend to be larger than the control latencies. Each sample is a list of
+/// (latency, count) pairs in ascending order. The p-value uses the normal
+/// approximation with a continuity correction, and accounts for ties.
+fn mann_whitney_u(control: &[(f64, u64)], experimental: &[(f64, u64)]) -> LatencyResult {
+    let n_control = total(control);
+    let n_experimental = total(experimental);
+    let n = n_control + n_experimental;
+    // • Rank every latency in the pooled sample, giving tied
+    //   latencies the average of the ranks they span.
+    let mut experimental_rank_sum = 0.0;
+    let mut tie_correction = 0.0;
+    let mut ranked = 0.0;
+    for (control_ties, experimental_ties) in pool(control, experimental) {
+        let ties = (control_ties + experimental_ties) as f64;
+        // Ranks are 1-based, so this run spans ranks ranked + 1 through ranked + ties.
+        let rank = ranked + (ties + 1.0) / 2.0;
+        experimental_rank_sum += rank * experimental_ties as f64;
+        tie_correction += ties.powi(3) - ties;
+        ranked += ties;
+    }
+    // • U counts the pairs in which the canary is slower, with ties
+    //   counting as half.
+    let u = experimental_rank_sum - n_experimental * (n_experimental + 1.0) / 2.0;
+    let pairs = n_control * n_experimental;
+    let mean = pairs / 2.0;
+    let variance = pairs / 12.0 * ((n + 1.0) - tie_correction / (n * (n - 1.0)));
+    let p_value = if variance > 0.0 {
+        let z = (u - mean - 0.5) / variance.sqrt();
+        Normal::standard().sf(z)
+    } else {
+        1.0
+    };
+    LatencyResult {
+        test: LatencyTest::MannWhitney,
+        statistic: u,
+        p_value,
+        effect_size: u / pairs - 0.5,
+        significant: false,
+        percentiles: None,
+    }
+}
+
+/// Run a one-sided two-sample Kolmogorov–Smirnov test of whether the
+/// experimental latencies are stochastically larger than the control
+/// latencies. Each sample is a list of (latency, count) pairs in ascending
+/// order. D⁺ is the largest amount by which the control group's empirical
+/// CDF exceeds the canary's; the p-value uses Smirnov's asymptotic
+/// approximation.
+fn kolmogorov_smirnov(control: &[(f64, u64)], experimental: &[(f64, u64)]) -> LatencyResult {
+    let n_control = total(control);
+    let n_experimental = total(experimental);
+    // • Walk both samples in order, comparing the CDFs after
+    //   consuming every latency equal to the current one.
+    let (mut control_seen, mut experimental_seen) = (0.0, 0.0);
+    let mut statistic: f64 = 0.0;
+    for (control_count, experimental_count) in pool(control, experimental) {
+        control_seen += control_count as f64;
+        experimental_seen += experimental_count as f64;
+        statistic = statistic.max(control_seen / n_control - experimental_seen / n_experimental);
+    }
+    let effective_size = n_control * n_experimental / (n_control + n_experimental);
+    let p_value = (-2.0 * effective_size * statistic.powi(2)).exp().min(1.0);
+    LatencyResult {
+        test: LatencyTest::KolmogorovSmirnov,
+        statistic,
+        p_value,
+        effect_size: statistic,
+        significant: false,
+        percentiles: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{kolmogorov_smirnov, mann_whitney_u, LatencyEngine, LatencyTest};
+    use crate::stats::fixtures::latencies;
+    use crate::stats::{DecisionEngine, Group};
+
+    /// Count each of the distinct latencies once.
+    fn sample(latencies: &[f64]) -> Vec<(f64, u64)> {
+        latencies.iter().map(|latency| (*latency, 1)).collect()
+    }
+
+    /// Scenario: the canary is slower in 8 of the 9 pairs of requests,
+    /// so U = 8. Without ties, the variance of U is 9 × 7 / 12 = 5.25.
+    #[test]
+    fn mann_whitney_counts_slower_pairs() {
+        let result = mann_whitney_u(&sample(&[1.0, 2.0, 4.0]), &sample(&[3.0, 5.0, 6.0]));
+        assert_eq!(result.statistic, 8.0);
+        assert!((result.effect_size - (8.0 / 9.0 - 0.5)).abs() < 1e-12);
+        // z = (8 - 4.5 - 0.5) / √5.25 ≈ 1.309
+        assert!((result.p_value - 0.0952).abs() < 0.001);
+    }
+
+    /// Tied latencies count as half a slower pair.
+    #[test]
+    fn mann_whitney_handles_ties() {
+        let result = mann_whitney_u(&sample(&[1.0, 2.0]), &[(2.0, 2)]);
+        assert_eq!(result.statistic, 3.0);
+        let identical = mann_whitney_u(&[(5.0, 4)], &[(5.0, 4)]);
+        assert_eq!(identical.p_value, 1.0);
+    }
+
+    /// D⁺ is the largest gap by which the control group's CDF leads.
+    #[test]
+    fn kolmogorov_smirnov_finds_largest_gap() {
+        let slow = sample(&[3.0, 4.0, 5.0, 6.0]);
+        let fast = sample(&[1.0, 2.0, 3.0, 4.0]);
+        let result = kolmogorov_smirnov(&fast, &slow);
+        assert_eq!(result.statistic, 0.5);
+        let faster = kolmogorov_smirnov(&slow, &fast);
+        assert_eq!(faster.statistic, 0.0);
+        assert_eq!(faster.p_value, 1.0);
+    }
+
+    /// A canary whose slowest half of requests take 400ms is a regression,
+    /// even though its other requests are as fast as the control group's.
+    #[test]
+    fn tail_regression_is_significant() {
+        for test in [LatencyTest::MannWhitney, LatencyTest::KolmogorovSmirnov] {
+            let mut engine = LatencyEngine::new(test);
+            engine.add_observations(&latencies(Group::Control, (0..1000).map(|i| 100 + i % 100)));
+            engine.add_observations(&latencies(
+                Group::Experimental,
+                (0..1000).map(|i| if i % 100 < 50 { 100 + i % 100 } else { 400 }),
+            ));
+            let result = engine.test();
+            assert!(result.significant, "{result:?}");
+        }
+    }
+
+    /// A faster canary is never a regression, and neither is a
+    /// significant difference smaller than the minimum effect size.
+    #[test]
+    fn only_meaningful_slowdowns_are_significant() {
+        let mut faster = LatencyEngine::new(LatencyTest::MannWhitney);
+        faster.add_observations(&latencies(Group::Control, (0..500).map(|i| 200 + i % 100)));
+        faster.add_observations(&latencies(
+            Group::Experimental,
+            (0..500).map(|i| 100 + i % 100),
+        ));
+        let result = faster.test();
+        assert!(result.effect_size < 0.0);
+        assert!(!result.significant);
+
+        let mut slightly_slower =
+            LatencyEngine::new(LatencyTest::KolmogorovSmirnov).with_min_effect_size(0.2);
+        slightly_slower
+            .add_observations(&latencies(Group::Control, (0..5000).map(|i| 100 + i % 100)));
+        slightly_slower.add_observations(&latencies(
+            Group::Experimental,
+            (0..5000).map(|i| 110 + i % 100),
+        ));
+        let result = slightly_slower.test();
+        assert!(result.p_value < 0.05);
+        assert!(!result.significant);
+    }
+
+    /// The canary's tail may regress beyond the percentile tolerance
+    /// even when the hypothesis test sees no meaningful difference.
+    #[test]
+    fn percentile_tolerance_catches_tail_regressions() {
+        let mut engine =
+            LatencyEngine::new(LatencyTest::MannWhitney).with_percentile_tolerance(0.2);
+        engine.add_observations(&latencies(Group::Control, (0..1000).map(|i| 100 + i % 100)));
+        engine.add_observations(&latencies(
+            Group::Experimental,
+            (0..1000).map(|i| if i % 100 < 98 { 100 + i % 100 } else { 1000 }),
+        ));
+        let result = engine.test();
+        assert!(!result.significant);
+        let percentiles = result.percentiles.expect("percentiles should be compared");
+        let [p50, p90, p99] = percentiles.deltas;
+        assert!(p50.relative_delta().abs() <= 0.02);
+        assert!(p90.relative_delta().abs() <= 0.02);
+        assert!(p99.relative_delta() > 4.0);
+        assert!(percentiles.exceeded);
+        assert!(result.regressed());
+    }
+}
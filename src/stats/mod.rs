@@ -1,104 +1,644 @@
-use std::collections::HashMap;
+use std::cell::OnceCell;
+use std::hash::Hash;
+use std::time::Duration;
 
-pub use chi::EnumerableCategory;
+use serde::Deserialize;
+use statrs::distribution::{ChiSquared, ContinuousCDF};
 
-/// The alpha cutoff is the amount of confidence must have in the result
-/// to feel comfortable that the result is not due to chance, but instead
-/// do to the independent variable. The valu is expressed as a confidence
-/// percentage: 0.05 means we are 95% confident that the observed difference
-/// is not due to chance, but actually because the experimental group differs
-/// from the control group.
-const DEFAULT_ALPHA_CUTOFF: f64 = 0.05;
+pub use alpha::{Alpha, DEFAULT_ALPHA_CUTOFF};
+pub use bayes::{
+    BayesianEngine, BayesianResult, DEFAULT_LOSS_THRESHOLD, DEFAULT_MARGIN,
+    DEFAULT_ROLLBACK_THRESHOLD,
+};
+pub use chi::{ContingencyTable, EnumerableCategory};
+pub use codes::{StatusClass, StatusCodeSets};
+pub use decision::{DecisionEngine, Diagnostics, EngineKind, Verdict};
+pub use error::StatsError;
+pub use exact::DEFAULT_PERMUTATIONS;
+pub use grpc::{GrpcCode, GrpcFailures, DEFAULT_GRPC_FAILURES};
+pub use interval::{newcombe, wilson, ErrorRates, Interval, RateEstimate};
+pub use latency::{
+    LatencyEngine, LatencyResult, LatencyTest, PercentileComparison, PercentileDelta,
+    DEFAULT_MIN_EFFECT_SIZE,
+};
+pub use policy::{Correction, VerdictPolicy, DEFAULT_WEIGHT_THRESHOLD};
+pub use power::{power, sample_size};
+pub use proportion::{z_test, Direction, ProportionEngine, ProportionResult};
+pub use sequential::{SequentialEngine, DEFAULT_EQUIVALENCE_MARGIN, DEFAULT_MIXING_VARIANCE};
+pub use sketch::{DDSketch, DEFAULT_RELATIVE_ACCURACY};
+pub use table::ObservedTable;
+
+/// The chi-square statistic only approximately follows the chi-square
+/// distribution, and the approximation breaks down for small samples. By
+/// default, the engine refuses to decide until each group has this many
+/// observations.
+pub const DEFAULT_MIN_GROUP_SIZE: usize = 30;
 
 /// The [ChiSquareEngine] calculates the Chi Square test statistic
-/// based on the data stored in its contingency tables.
-pub struct ChiSquareEngine {
-    control: ContingencyTable,
-    experimental: ContingencyTable,
-    total_control_count: usize,
-    total_experimental_count: usize,
-    alpha_cutoff: f64,
+/// based on the data stored in its contingency tables. By default, it
+/// compares the groups' HTTP status categories, with any configured sets
+/// of exact codes split out, but any [EnumerableCategory] can serve as the
+/// outcome, e.g. gRPC status codes or custom error classes.
+#[derive(Clone)]
+pub struct ChiSquareEngine<C = StatusClass>
+where
+    C: EnumerableCategory + Hash + Eq,
+{
+    control: ObservedTable<C>,
+    experimental: ObservedTable<C>,
+    alpha_cutoff: Alpha,
+    /// The number of observations each group needs before we decide.
+    min_group_size: usize,
+    /// Which test computes the p-value.
+    selection: TestSelection,
+    /// The exact status codes tested apart from their category.
+    code_sets: StatusCodeSets,
+    /// The result of the test of the whole table, computed on demand,
+    /// since a permutation test is expensive, and cleared whenever an
+    /// observation is added or the test's settings change.
+    result: OnceCell<ChiSquareResult>,
 }
 
-impl Default for ChiSquareEngine {
+impl<C> Default for ChiSquareEngine<C>
+where
+    C: EnumerableCategory + Hash + Eq,
+{
     fn default() -> Self {
-        Self::new()
+        Self {
+            control: ObservedTable::new(),
+            experimental: ObservedTable::new(),
+            alpha_cutoff: DEFAULT_ALPHA_CUTOFF,
+            min_group_size: DEFAULT_MIN_GROUP_SIZE,
+            selection: TestSelection::default(),
+            code_sets: StatusCodeSets::default(),
+            result: OnceCell::new(),
+        }
     }
 }
 
 impl ChiSquareEngine {
+    /// Construct an engine comparing HTTP status categories. Engines for
+    /// other categories are constructed with [Default::default].
     pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Test these sets of exact status codes as columns of their own.
+    pub fn with_code_sets(self, code_sets: StatusCodeSets) -> Self {
         Self {
-            control: HashMap::default(),
-            experimental: HashMap::default(),
-            total_control_count: 0,
-            total_experimental_count: 0,
-            alpha_cutoff: DEFAULT_ALPHA_CUTOFF,
+            code_sets,
+            result: OnceCell::new(),
+            ..self
         }
     }
 
     pub fn add_observation(&mut self, obs: Observation) {
-        // Fetch the count of observations for the given group.
-        let entry = match obs.group {
-            Group::Control => {
-                self.total_control_count += 1;
-                self.control.entry(obs.outcome)
-            }
-            Group::Experimental => {
-                self.total_experimental_count += 1;
-                self.experimental.entry(obs.outcome)
-            }
+        let class = self.code_sets.classify(&obs);
+        self.add(obs.group, class);
+    }
+
+    /// Record every observation an aggregate counts at once.
+    pub fn add_aggregate(&mut self, agg: AggregatedObservation) {
+        let class = self.code_sets.classify(&agg.observation());
+        self.add_count(agg.group, class, agg.count);
+    }
+
+    /// Returns which way the canary's 5XX rate differs from the control
+    /// group's, by the one-sided [error_test](Self::error_test), or None
+    /// if there's nothing to compare. The test of the whole table may be
+    /// significant because other status codes moved, but that's no reason
+    /// to call the canary worse.
+    pub fn direction(&self) -> Option<Direction> {
+        self.error_test().map(|result| result.direction)
+    }
+
+    /// Returns true if the canary's 5XX rate, or its share of any split
+    /// set of codes that aren't server errors, is significantly higher.
+    pub fn is_worse(&self) -> bool {
+        self.direction() == Some(Direction::Worse)
+            || self
+                .code_set_tests()
+                .iter()
+                .any(|(_, result)| result.direction == Direction::Worse)
+    }
+
+    /// Run a one-sided two-proportion z-test on the groups' 5XX rates
+    /// alone, which decides whether the canary is worse. Returns None
+    /// while either group is empty. If neither rate can vary, e.g. because
+    /// no request failed, the groups don't differ.
+    pub fn error_test(&self) -> Option<ProportionResult> {
+        let (control, experimental) = self.error_counts();
+        one_sided_test(control, experimental, self.family_alpha())
+    }
+
+    /// Run the same one-sided test on each group's share of every split
+    /// set of codes that aren't server errors, e.g. throttling, so a canary
+    /// that's throttled more often fails even if it serves no more 5XX.
+    /// Split sets of server errors already count towards the 5XX rate.
+    pub fn code_set_tests(&self) -> Vec<(StatusClass, ProportionResult)> {
+        let alpha = self.family_alpha();
+        self.tested_sets()
+            .into_iter()
+            .filter_map(|class| {
+                let (control, experimental) = self.class_counts(class);
+                one_sided_test(control, experimental, alpha).map(|result| (class, result))
+            })
+            .collect()
+    }
+
+    /// Returns the split sets of codes that are tested apart from the 5XX rate.
+    fn tested_sets(&self) -> Vec<StatusClass> {
+        self.code_sets
+            .classes()
+            .filter(|class| !class.is_server_error())
+            .collect()
+    }
+
+    /// Returns the significance level of each one-sided test: the alpha
+    /// cutoff, split between the 5XX rate and every tested set, as the
+    /// Bonferroni correction does.
+    fn family_alpha(&self) -> Alpha {
+        self.alpha_cutoff.split(1 + self.tested_sets().len())
+    }
+
+    /// Returns the smallest p-value of the one-sided tests, scaled up by
+    /// the number of tests, so it can be compared to the alpha cutoff.
+    fn family_p_value(&self, p_values: impl IntoIterator<Item = f64>) -> f64 {
+        let tests = 1 + self.tested_sets().len();
+        let p_value = p_values.into_iter().fold(1.0, f64::min);
+        (p_value * tests as f64).min(1.0)
+    }
+
+    /// Returns the (count, total) of the class in the control and experimental groups.
+    fn class_counts(&self, class: StatusClass) -> ((u64, u64), (u64, u64)) {
+        let counts =
+            |table: &ObservedTable<StatusClass>| (table.group_count(&class), table.total_count());
+        (counts(&self.control), counts(&self.experimental))
+    }
+
+    /// Returns the 5XX rate of each group and their difference, with
+    /// confidence intervals at the configured alpha cutoff, or None
+    /// while either group is empty.
+    pub fn error_rates(&self) -> Option<ErrorRates> {
+        let (control, experimental) = self.error_counts();
+        ErrorRates::new(control, experimental, self.alpha_cutoff)
+    }
+
+    /// Returns the (5XX, total) counts of the control and experimental groups.
+    fn error_counts(&self) -> ((u64, u64), (u64, u64)) {
+        let counts = |table: &ObservedTable<StatusClass>| {
+            let errors = StatusClass::groups()
+                .filter(|class| class.is_server_error())
+                .map(|class| table.group_count(&class))
+                .sum();
+            (errors, table.total_count())
         };
-        // Increment the count.
-        entry.and_modify(|count| *count += 1).or_insert(1);
+        (counts(&self.control), counts(&self.experimental))
+    }
+}
+
+impl<C> ChiSquareEngine<C>
+where
+    C: EnumerableCategory + Hash + Eq,
+{
+    /// Test for significance at the given alpha cutoff.
+    pub fn with_alpha_cutoff(self, alpha_cutoff: Alpha) -> Self {
+        Self {
+            alpha_cutoff,
+            result: OnceCell::new(),
+            ..self
+        }
     }
 
-    /// calculate the test statistic from the contingency tables.
-    pub fn calc_test_statistic(&self) -> f64 {
-        let mut error = 0.0;
-        let categories = [
-            StatusCategory::_1XX,
-            StatusCategory::_2XX,
-            StatusCategory::_3XX,
-            StatusCategory::_4XX,
-            StatusCategory::_5XX,
-        ];
-        // For each category, we calculate the squared error between the
-        // expected and the observed probabilies.
-        for category in categories {
-            let expected = self.expected_frequency(category);
-            let observed = self.observed_frequency(category);
-            error += (observed - expected).powi(2) / expected;
+    /// Choose how the p-value is computed. By default, the chi-square
+    /// distribution is used unless the expected counts are too small.
+    pub fn with_test_selection(self, selection: TestSelection) -> Self {
+        Self {
+            selection,
+            result: OnceCell::new(),
+            ..self
         }
-        error
     }
 
-    /// calculate the expected frequency for this category.
-    fn expected_frequency(&self, category: StatusCategory) -> f64 {
-        let observation_count = self.control[&category] as f64;
-        let total_count = self.control[&category] as f64;
-        observation_count / total_count
+    /// Refuse to decide until each group has at least this many observations.
+    /// An empty group is never enough, whatever the minimum.
+    pub fn with_min_group_size(self, min_group_size: usize) -> Self {
+        Self {
+            min_group_size,
+            ..self
+        }
+    }
+
+    /// Record an observation of the category in the given group.
+    pub fn add(&mut self, group: Group, category: C) {
+        self.add_count(group, category, 1);
+    }
+
+    /// Record several observations of the category in the group at once.
+    pub fn add_count(&mut self, group: Group, category: C, count: u64) {
+        self.result.take();
+        match group {
+            Group::Control => self.control.add(category, count),
+            Group::Experimental => self.experimental.add(category, count),
+        }
     }
 
-    /// calculate the observed frequency for this category.
-    fn observed_frequency(&self, category: StatusCategory) -> f64 {
-        let observation_count = self.experimental[&category] as f64;
-        let total_count = self.experimental[&category] as f64;
-        observation_count / total_count
+    /// Run a chi-square test of homogeneity on the 2×k contingency table
+    /// formed by the control and experimental groups. Each category
+    /// is a column. Categories that neither group has observed are
+    /// excluded, since they carry no information about the difference
+    /// between the groups.
+    ///
+    /// The chi-square distribution only approximates the statistic's
+    /// distribution, and the approximation fails when any cell's expected
+    /// count is below 5, e.g. for rare server errors during a low-traffic
+    /// canary. Then we fall back to Fisher's exact test for a 2×2 table, or
+    /// a Monte Carlo permutation test for larger tables, unless another
+    /// [TestSelection] was chosen. The result records which method was used.
+    ///
+    /// If either group is empty, or fewer than two categories have been
+    /// observed, there's nothing to compare: the result has zero degrees
+    /// of freedom and is never significant.
+    ///
+    /// The result is computed once, and reused until another
+    /// observation is recorded.
+    pub fn test(&self) -> ChiSquareResult {
+        *self.result.get_or_init(|| self.run_test())
+    }
+
+    /// Run the test that [test](Self::test) describes.
+    fn run_test(&self) -> ChiSquareResult {
+        let columns = self.columns();
+        let result = self.pearson_test(&columns);
+        let asymptotic = match self.selection {
+            TestSelection::Auto => min_expected_count(&columns) >= MIN_EXPECTED_COUNT,
+            TestSelection::ChiSquare => true,
+            TestSelection::Exact => false,
+        };
+        if result.degrees_of_freedom == 0 || asymptotic {
+            return result;
+        }
+        let (method, p_value) = if columns.len() == 2 {
+            (TestMethod::FisherExact, exact::fisher_exact(&columns))
+        } else {
+            let rounds = exact::DEFAULT_PERMUTATIONS;
+            let p_value = exact::permutation_test(&columns, result.statistic, rounds);
+            (TestMethod::Permutation, p_value)
+        };
+        ChiSquareResult {
+            method,
+            p_value,
+            significant: self.alpha_cutoff.rejects(p_value),
+            ..result
+        }
+    }
+
+    /// Run the chi-square test with the asymptotic chi-square distribution,
+    /// however small the expected counts are.
+    fn pearson_test(&self, columns: &[(u64, u64)]) -> ChiSquareResult {
+        if self.control.total_count() == 0
+            || self.experimental.total_count() == 0
+            || columns.len() < 2
+        {
+            return ChiSquareResult::inconclusive();
+        }
+        let statistic = pearson_statistic(columns);
+        // • A 2×k table has (2 - 1) × (k - 1) degrees of freedom.
+        let degrees_of_freedom = columns.len() as u64 - 1;
+        let distribution =
+            ChiSquared::new(degrees_of_freedom as f64).expect("Degrees of freedom must be >= 0");
+        let p_value = distribution.sf(statistic);
+        ChiSquareResult {
+            statistic,
+            degrees_of_freedom,
+            p_value,
+            significant: self.alpha_cutoff.rejects(p_value),
+            method: TestMethod::ChiSquare,
+        }
+    }
+
+    /// Collect the (control, experimental) counts for each category,
+    /// skipping any column that would be all zeroes.
+    fn columns(&self) -> Vec<(u64, u64)> {
+        C::groups()
+            .map(|category| {
+                (
+                    self.control.group_count(&category),
+                    self.experimental.group_count(&category),
+                )
+            })
+            .filter(|(control, experimental)| control + experimental > 0)
+            .collect()
+    }
+
+    /// returns true if the difference between the control and experimental
+    /// groups is statistically significant at the configured alpha cutoff.
+    pub fn is_significant(&self) -> bool {
+        self.test().significant
+    }
+
+    /// returns the total number of observations recorded across both groups.
+    pub fn total_count(&self) -> usize {
+        (self.control.total_count() + self.experimental.total_count()) as usize
+    }
+
+    /// returns true once both groups have enough observations to decide.
+    pub fn has_min_sample(&self) -> bool {
+        self.control_count().min(self.experimental_count()) >= self.min_group_size.max(1)
+    }
+
+    /// returns the number of observations recorded in the control group.
+    pub fn control_count(&self) -> usize {
+        self.control.total_count() as usize
+    }
+
+    /// returns the number of observations recorded in the experimental group.
+    pub fn experimental_count(&self) -> usize {
+        self.experimental.total_count() as usize
     }
 }
 
-/// This type maps the dependent variable to its count.
-pub type ContingencyTable = HashMap<StatusCategory, usize>;
+/// Run a one-sided two-proportion z-test on the (count, total) of each
+/// group, or return None while either group is empty. If neither share
+/// can vary, the groups don't differ.
+fn one_sided_test(
+    control: (u64, u64),
+    experimental: (u64, u64),
+    alpha: Alpha,
+) -> Option<ProportionResult> {
+    if control.1 == 0 || experimental.1 == 0 {
+        return None;
+    }
+    z_test(control, experimental, alpha).or_else(|| {
+        Some(ProportionResult {
+            control_rate: share(control),
+            experimental_rate: share(experimental),
+            statistic: 0.0,
+            p_value: 1.0,
+            direction: Direction::NoDifference,
+        })
+    })
+}
+
+/// Returns which way the canary's share of a column differs from the
+/// control group's, given the (count, total) of each group and a test of
+/// the column against the rest of the table.
+fn direction_of(
+    result: &ChiSquareResult,
+    (control, experimental): ((u64, u64), (u64, u64)),
+) -> Option<Direction> {
+    if result.degrees_of_freedom == 0 {
+        return None;
+    }
+    let (control, experimental) = (share(control), share(experimental));
+    Some(if !result.significant || experimental == control {
+        Direction::NoDifference
+    } else if experimental > control {
+        Direction::Worse
+    } else {
+        Direction::Better
+    })
+}
+
+/// Collapse the table into one column and the rest, given the (count,
+/// total) of each group, and collect the counts of the non-empty columns.
+fn collapse(
+    ((control, control_total), (count, total)): ((u64, u64), (u64, u64)),
+) -> Vec<(u64, u64)> {
+    [(control, count), (control_total - control, total - count)]
+        .into_iter()
+        .filter(|(control, experimental)| control + experimental > 0)
+        .collect()
+}
+
+/// Returns the share of a group's (count, total).
+fn share((count, total): (u64, u64)) -> f64 {
+    count as f64 / total as f64
+}
+
+/// Below this expected count in any cell, the chi-square approximation
+/// is unreliable, so an exact or permutation test is used instead.
+pub const MIN_EXPECTED_COUNT: f64 = 5.0;
+
+/// Returns the Pearson chi-square statistic of a 2×k table with no empty
+/// columns or rows. For each cell, the expected count under the null
+/// hypothesis is the row total times the column total over the grand
+/// total. Sum the squared error between the observed and expected counts,
+/// scaled by the expected count.
+fn pearson_statistic(columns: &[(u64, u64)]) -> f64 {
+    let (control_total, experimental_total) = row_totals(columns);
+    let grand_total = control_total + experimental_total;
+    columns
+        .iter()
+        .map(|(control, experimental)| {
+            let (control, experimental) = (*control as f64, *experimental as f64);
+            let column_total = control + experimental;
+            let expected_control = control_total * column_total / grand_total;
+            let expected_experimental = experimental_total * column_total / grand_total;
+            (control - expected_control).powi(2) / expected_control
+                + (experimental - expected_experimental).powi(2) / expected_experimental
+        })
+        .sum()
+}
+
+/// Returns the smallest expected count of any cell in the 2×k table.
+fn min_expected_count(columns: &[(u64, u64)]) -> f64 {
+    let (control_total, experimental_total) = row_totals(columns);
+    let smallest_row = control_total.min(experimental_total);
+    columns
+        .iter()
+        .map(|(control, experimental)| (control + experimental) as f64)
+        .fold(f64::INFINITY, f64::min)
+        * smallest_row
+        / (control_total + experimental_total)
+}
+
+/// Returns the total count of the control and experimental rows.
+fn row_totals(columns: &[(u64, u64)]) -> (f64, f64) {
+    columns
+        .iter()
+        .fold((0.0, 0.0), |(control, experimental), (c, e)| {
+            (control + *c as f64, experimental + *e as f64)
+        })
+}
+
+/// The [TestSelection] chooses how the [ChiSquareEngine] computes its p-value.
+#[derive(Deserialize, clap::ValueEnum, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum TestSelection {
+    /// Use the chi-square distribution, unless an expected
+    /// count is too small for it to be accurate.
+    #[default]
+    Auto,
+    /// Always use the chi-square distribution.
+    ChiSquare,
+    /// Always use Fisher's exact test or a permutation test.
+    Exact,
+}
+
+/// The [TestMethod] records how a [ChiSquareResult]'s p-value was computed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TestMethod {
+    /// The asymptotic chi-square distribution.
+    ChiSquare,
+    /// Fisher's exact test, for 2×2 tables with small expected counts.
+    FisherExact,
+    /// A Monte Carlo permutation test, for larger tables with
+    /// small expected counts.
+    Permutation,
+}
+
+impl TestMethod {
+    /// Returns the method's name, e.g. "fisher-exact".
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ChiSquare => "chi-square",
+            Self::FisherExact => "fisher-exact",
+            Self::Permutation => "permutation",
+        }
+    }
+}
+
+/// The [ChiSquareResult] summarizes a chi-square test of homogeneity
+/// between the control and experimental groups.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ChiSquareResult {
+    /// The chi-square test statistic.
+    pub statistic: f64,
+    /// The number of degrees of freedom of the contingency table.
+    pub degrees_of_freedom: u64,
+    /// The probability of observing a statistic at least this large
+    /// if the two groups had the same distribution of outcomes.
+    pub p_value: f64,
+    /// Whether the p-value falls below the alpha cutoff.
+    pub significant: bool,
+    /// How the p-value was computed.
+    pub method: TestMethod,
+}
+
+impl ChiSquareResult {
+    /// The result reported when there isn't enough data to run the test.
+    fn inconclusive() -> Self {
+        Self {
+            statistic: 0.0,
+            degrees_of_freedom: 0,
+            p_value: 1.0,
+            significant: false,
+            method: TestMethod::ChiSquare,
+        }
+    }
+}
 
 /// An [Observation] represents a measured outcome that
 /// belongs to either a control group or an experimental
 /// group (i.e. canary).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Observation {
     /// The experimental group or the control group.
     pub group: Group,
     /// The outcome of the observation, by status code.
     pub outcome: StatusCategory,
+    /// The exact HTTP status code, if it was recorded.
+    pub status: Option<u16>,
+    /// The gRPC status the call finished with, if it was a gRPC call.
+    pub grpc_status: Option<GrpcCode>,
+    /// How long the request took to serve, if it was recorded.
+    pub latency: Option<Duration>,
+}
+
+impl Observation {
+    /// Create an observation without a latency.
+    pub fn new(group: Group, outcome: StatusCategory) -> Self {
+        Self {
+            group,
+            outcome,
+            status: None,
+            grpc_status: None,
+            latency: None,
+        }
+    }
+
+    /// Create an observation of a gRPC call. Its outcome is the status
+    /// category the code maps to, given which codes count as failures.
+    pub fn grpc(group: Group, code: GrpcCode, failures: &GrpcFailures) -> Self {
+        Self {
+            grpc_status: Some(code),
+            ..Self::new(group, failures.outcome(code))
+        }
+    }
+
+    /// Attach the exact status code the outcome was classified from.
+    pub fn with_status(self, status: u16) -> Self {
+        Self {
+            status: Some(status),
+            ..self
+        }
+    }
+
+    /// Attach the time it took to serve the request.
+    pub fn with_latency(self, latency: Duration) -> Self {
+        Self {
+            latency: Some(latency),
+            ..self
+        }
+    }
+}
+
+/// An [AggregatedObservation] counts identical observations, e.g. the
+/// canary's 5XX responses over the last minute. Sources that already report
+/// counts per status, like metrics, emit these instead of one [Observation]
+/// per request, which engines can then record in a single step.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct AggregatedObservation {
+    /// The experimental group or the control group.
+    pub group: Group,
+    /// The outcome of the observations, by status code.
+    pub outcome: StatusCategory,
+    /// The exact HTTP status code, if the source reports it.
+    pub status: Option<u16>,
+    /// How many observations had this outcome.
+    pub count: u64,
+}
+
+impl AggregatedObservation {
+    /// Count observations of the outcome in the group.
+    pub fn new(group: Group, outcome: StatusCategory, count: u64) -> Self {
+        Self {
+            group,
+            outcome,
+            status: None,
+            count,
+        }
+    }
+
+    /// Attach the exact status code the outcome was classified from.
+    pub fn with_status(self, status: u16) -> Self {
+        Self {
+            status: Some(status),
+            ..self
+        }
+    }
+
+    /// Returns one of the observations this aggregate counts. Aggregates
+    /// don't carry latencies, so it doesn't have one either.
+    pub fn observation(&self) -> Observation {
+        Observation {
+            status: self.status,
+            ..Observation::new(self.group, self.outcome)
+        }
+    }
+}
+
+impl From<Observation> for AggregatedObservation {
+    /// Count a single observation. Its latency, if any, is dropped.
+    fn from(obs: Observation) -> Self {
+        Self {
+            group: obs.group,
+            outcome: obs.outcome,
+            status: obs.status,
+            count: 1,
+        }
+    }
 }
 
 /// The [Group] indicates from whence a given observation
@@ -129,7 +669,241 @@ pub enum StatusCategory {
     _5XX,
 }
 
+impl EnumerableCategory for StatusCategory {
+    fn groups() -> Box<dyn Iterator<Item = Self>> {
+        Box::new(
+            [
+                StatusCategory::_1XX,
+                StatusCategory::_2XX,
+                StatusCategory::_3XX,
+                StatusCategory::_4XX,
+                StatusCategory::_5XX,
+            ]
+            .into_iter(),
+        )
+    }
+}
+
+impl TryFrom<u16> for StatusCategory {
+    type Error = StatsError;
+
+    /// Classify an HTTP status code by its first digit.
+    fn try_from(status: u16) -> Result<Self, Self::Error> {
+        match status {
+            100..=199 => Ok(Self::_1XX),
+            200..=299 => Ok(Self::_2XX),
+            300..=399 => Ok(Self::_3XX),
+            400..=499 => Ok(Self::_4XX),
+            500..=599 => Ok(Self::_5XX),
+            _ => Err(StatsError::StatusCode(status)),
+        }
+    }
+}
+
+/// contains the validated significance level.
+mod alpha;
+/// contains the Beta-Binomial decision engine.
+mod bayes;
 /// contains the engine to calculate the chi square test statistic.
 mod chi;
-/// contains implementations of contingency tables.
+/// contains the exact status codes tested apart from their category.
+mod codes;
+/// contains the trait shared by every decision engine.
+mod decision;
+/// contains the errors raised by invalid test parameters.
+mod error;
+/// contains the exact tests used when expected counts are small.
+mod exact;
+/// contains the observations shared by the statistics tests.
+#[cfg(test)]
+pub(crate) mod fixtures;
+/// contains the gRPC status codes.
+mod grpc;
+/// contains the confidence intervals for error rates.
+mod interval;
+/// contains the non-parametric latency tests.
+mod latency;
+/// contains the policy combining verdicts across several metrics.
+mod policy;
+/// contains the sample size and power calculations.
+mod power;
+/// contains the one-sided two-proportion z-test.
+mod proportion;
+/// contains the always-valid sequential test.
+mod sequential;
+/// contains the quantile sketch used to summarize latencies.
+mod sketch;
+/// contains the table of observed counts per category.
 mod table;
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::fixtures::outcomes;
+    use super::{
+        AggregatedObservation, Alpha, ChiSquareEngine, DecisionEngine, EnumerableCategory, Group,
+        StatsError, StatusCategory, TestMethod,
+    };
+
+    /// Scenario: The control group serves 90 successes and 10 server errors,
+    /// while the canary serves 70 successes and 30 server errors. With pooled
+    /// expected counts of 80 and 20 per group, the statistic is 12.5 with one
+    /// degree of freedom, which is significant at the 5% level.
+    #[test]
+    fn detects_difference_between_groups() {
+        let mut engine = ChiSquareEngine::new();
+        engine.add_observations(&outcomes(Group::Control, StatusCategory::_2XX, 90));
+        engine.add_observations(&outcomes(Group::Control, StatusCategory::_5XX, 10));
+        engine.add_observations(&outcomes(Group::Experimental, StatusCategory::_2XX, 70));
+        engine.add_observations(&outcomes(Group::Experimental, StatusCategory::_5XX, 30));
+        let result = engine.test();
+        assert_eq!(result.degrees_of_freedom, 1);
+        assert_eq!((result.statistic * 100.0).round() / 100.0, 12.5);
+        assert!(0.0004 < result.p_value && result.p_value < 0.0005);
+        assert!(result.significant);
+    }
+
+    /// The result is reused between observations, but never outlives them.
+    #[test]
+    fn cached_result_follows_observations() {
+        let mut engine = ChiSquareEngine::new();
+        for group in [Group::Control, Group::Experimental] {
+            engine.add_observations(&outcomes(group, StatusCategory::_2XX, 90));
+            engine.add_observations(&outcomes(group, StatusCategory::_5XX, 10));
+        }
+        assert!(!engine.is_significant());
+        assert_eq!(engine.test(), engine.test());
+        engine.add_observations(&outcomes(Group::Experimental, StatusCategory::_5XX, 40));
+        assert!(engine.is_significant());
+        let engine = engine.with_alpha_cutoff(Alpha::new(1e-12).unwrap());
+        assert!(!engine.is_significant());
+    }
+
+    /// Groups with identical proportions produce a statistic of zero.
+    #[test]
+    fn identical_groups_are_not_significant() {
+        let mut engine = ChiSquareEngine::new();
+        for group in [Group::Control, Group::Experimental] {
+            engine.add_observations(&outcomes(group, StatusCategory::_2XX, 50));
+            engine.add_observations(&outcomes(group, StatusCategory::_4XX, 5));
+            engine.add_observations(&outcomes(group, StatusCategory::_5XX, 1));
+        }
+        let result = engine.test();
+        assert_eq!(result.degrees_of_freedom, 2);
+        assert_eq!(result.statistic, 0.0);
+        assert!(!result.significant);
+    }
+
+    /// Empty groups and single-category tables have nothing to compare,
+    /// so the engine must not panic or divide by zero.
+    #[test]
+    fn empty_cells_do_not_panic() {
+        let mut engine = ChiSquareEngine::new();
+        assert!(!engine.is_significant());
+        engine.add_observations(&outcomes(Group::Control, StatusCategory::_2XX, 10));
+        assert!(!engine.is_significant());
+        engine.add_observations(&outcomes(Group::Experimental, StatusCategory::_2XX, 10));
+        let result = engine.test();
+        assert_eq!(result.degrees_of_freedom, 0);
+        assert_eq!(result.p_value, 1.0);
+        assert!(!result.significant);
+    }
+
+    /// A few gRPC status codes, standing in for any categorical outcome.
+    #[derive(Hash, Debug, PartialEq, Eq, Clone, Copy)]
+    enum Grpc {
+        Ok,
+        Unavailable,
+        DeadlineExceeded,
+    }
+
+    impl EnumerableCategory for Grpc {
+        fn groups() -> Box<dyn Iterator<Item = Self>> {
+            Box::new([Grpc::Ok, Grpc::Unavailable, Grpc::DeadlineExceeded].into_iter())
+        }
+    }
+
+    /// The engine compares any categories, not just HTTP status codes.
+    #[test]
+    fn tests_arbitrary_categories() {
+        let mut engine = ChiSquareEngine::<Grpc>::default();
+        for (group, code, count) in [
+            (Group::Control, Grpc::Ok, 85),
+            (Group::Control, Grpc::Unavailable, 10),
+            (Group::Control, Grpc::DeadlineExceeded, 5),
+            (Group::Experimental, Grpc::Ok, 70),
+            (Group::Experimental, Grpc::Unavailable, 10),
+            (Group::Experimental, Grpc::DeadlineExceeded, 20),
+        ] {
+            for _ in 0..count {
+                engine.add(group, code);
+            }
+        }
+        let result = engine.test();
+        assert_eq!(result.degrees_of_freedom, 2);
+        assert_eq!(result.method, TestMethod::ChiSquare);
+        assert!(result.significant);
+        assert_eq!(engine.total_count(), 200);
+    }
+
+    /// Rare server errors leave expected counts below 5, so the engine
+    /// falls back to an exact test, and says so.
+    #[test]
+    fn small_expected_counts_fall_back() {
+        let mut engine = ChiSquareEngine::new();
+        engine.add_observations(&outcomes(Group::Control, StatusCategory::_2XX, 200));
+        engine.add_observations(&outcomes(Group::Experimental, StatusCategory::_2XX, 193));
+        engine.add_observations(&outcomes(Group::Experimental, StatusCategory::_5XX, 7));
+        let result = engine.test();
+        assert_eq!(result.method, TestMethod::FisherExact);
+        assert!(result.significant);
+        // • With a third category, the table is 2×3.
+        engine.add_observations(&outcomes(Group::Control, StatusCategory::_4XX, 2));
+        let result = engine.test();
+        assert_eq!(result.method, TestMethod::Permutation);
+        assert_eq!(result.degrees_of_freedom, 2);
+        assert!(result.significant);
+        // • Common outcomes are tested with the chi-square distribution.
+        engine.add_observations(&outcomes(Group::Control, StatusCategory::_5XX, 20));
+        engine.add_observations(&outcomes(Group::Control, StatusCategory::_4XX, 20));
+        engine.add_observations(&outcomes(Group::Experimental, StatusCategory::_4XX, 20));
+        engine.add_observations(&outcomes(Group::Experimental, StatusCategory::_5XX, 20));
+        assert_eq!(engine.test().method, TestMethod::ChiSquare);
+    }
+
+    #[test]
+    fn classifies_status_codes() {
+        assert_eq!(StatusCategory::try_from(100), Ok(StatusCategory::_1XX));
+        assert_eq!(StatusCategory::try_from(429), Ok(StatusCategory::_4XX));
+        assert_eq!(StatusCategory::try_from(599), Ok(StatusCategory::_5XX));
+        assert_eq!(
+            StatusCategory::try_from(99),
+            Err(StatsError::StatusCode(99))
+        );
+        assert_eq!(
+            StatusCategory::try_from(600),
+            Err(StatsError::StatusCode(600))
+        );
+    }
+
+    /// Recording an aggregate is the same as recording each
+    /// of the observations it counts.
+    #[test]
+    fn aggregates_match_observations() {
+        let mut aggregated = ChiSquareEngine::new();
+        let mut individual = ChiSquareEngine::new();
+        for (group, outcome, count) in [
+            (Group::Control, StatusCategory::_2XX, 950),
+            (Group::Control, StatusCategory::_5XX, 50),
+            (Group::Experimental, StatusCategory::_2XX, 900),
+            (Group::Experimental, StatusCategory::_5XX, 100),
+        ] {
+            aggregated.add_aggregate(AggregatedObservation::new(group, outcome, count));
+            individual.add_observations(&outcomes(group, outcome, count as usize));
+        }
+        assert_eq!(aggregated.total_count(), 2000);
+        assert_eq!(aggregated.test(), individual.test());
+        assert_eq!(aggregated.error_rates(), individual.error_rates());
+    }
+}
@@ -0,0 +1,477 @@
+use serde::Deserialize;
+
+use super::{
+    AggregatedObservation, Alpha, DecisionEngine, Diagnostics, ErrorRates, LatencyResult,
+    Observation, Verdict, DEFAULT_ALPHA_CUTOFF,
+};
+
+/// By default, optional metrics roll the canary back once
+/// metrics holding half of their total weight fail.
+pub const DEFAULT_WEIGHT_THRESHOLD: f64 = 0.5;
+
+/// A [Correction] controls the family-wise error rate when several
+/// metrics are tested at once. Without one, each additional metric
+/// is another chance to roll back a healthy canary by accident.
+#[derive(Deserialize, clap::ValueEnum, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum Correction {
+    /// Each engine's verdict stands on its own alpha cutoff.
+    #[default]
+    Unadjusted,
+    /// Reject a metric if its p-value is below alpha / m,
+    /// where m is the number of metrics with p-values.
+    Bonferroni,
+    /// Holm's step-down procedure: sort the p-values in ascending order,
+    /// and reject the k-th smallest while it's below alpha / (m - k + 1).
+    /// It controls the same error rate as Bonferroni, but rejects more.
+    Holm,
+}
+
+impl Correction {
+    /// Returns whether each p-value is rejected at the family-wise alpha cutoff.
+    pub fn reject(self, p_values: &[f64], alpha_cutoff: Alpha) -> Vec<bool> {
+        let m = p_values.len();
+        match self {
+            Self::Unadjusted => p_values.iter().map(|p| alpha_cutoff.rejects(*p)).collect(),
+            Self::Bonferroni => {
+                let alpha_cutoff = alpha_cutoff.split(m);
+                p_values.iter().map(|p| alpha_cutoff.rejects(*p)).collect()
+            }
+            Self::Holm => {
+                let mut order: Vec<usize> = (0..m).collect();
+                order.sort_by(|a, b| p_values[*a].total_cmp(&p_values[*b]));
+                let mut rejected = vec![false; m];
+                for (rank, index) in order.into_iter().enumerate() {
+                    if !alpha_cutoff.split(m - rank).rejects(p_values[index]) {
+                        break;
+                    }
+                    rejected[index] = true;
+                }
+                rejected
+            }
+        }
+    }
+}
+
+/// A [Metric] is a named [DecisionEngine] within a [VerdictPolicy].
+struct Metric {
+    name: &'static str,
+    engine: Box<dyn DecisionEngine>,
+    /// Whether this metric alone can roll the canary back or hold it.
+    required: bool,
+    /// How much an optional metric counts towards a rollback.
+    weight: f64,
+    /// The metric's verdict, after correction, from the most recent evaluation.
+    verdict: Option<Verdict>,
+}
+
+/// The [VerdictPolicy] gates a canary on several metrics at once, like the
+/// error rate, latency and saturation, each judged by its own engine.
+/// Every observation is fed to every engine, and their verdicts combine:
+///
+/// - The canary is rolled back if any required metric fails, or once the
+///   failing optional metrics hold enough of the optional metrics' weight.
+/// - It's held while any required metric needs more observations, and
+///   it doesn't have enough until every required metric does.
+/// - Otherwise, it's promoted if any metric passed. Metrics that can't be
+///   tested, e.g. because no latencies were reported, don't hold it back.
+///
+/// With a [Correction], a rollback backed by a p-value only stands if the
+/// p-value is also rejected at the policy's family-wise alpha cutoff.
+///
+/// The policy is only sequential if every engine is, since consulting a
+/// fixed-horizon engine early would void its guarantees.
+pub struct VerdictPolicy {
+    metrics: Vec<Metric>,
+    alpha_cutoff: Alpha,
+    correction: Correction,
+    weight_threshold: f64,
+    /// The number of observations recorded.
+    count: usize,
+    /// The index of the metric that determined the most recent verdict.
+    deciding: Option<usize>,
+}
+
+impl Default for VerdictPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VerdictPolicy {
+    /// Create a policy without any metrics.
+    pub fn new() -> Self {
+        Self {
+            metrics: Vec::new(),
+            alpha_cutoff: DEFAULT_ALPHA_CUTOFF,
+            correction: Correction::default(),
+            weight_threshold: DEFAULT_WEIGHT_THRESHOLD,
+            count: 0,
+            deciding: None,
+        }
+    }
+
+    /// Correct for testing several metrics at once, so the chance of
+    /// rolling back a healthy canary across all of them stays below
+    /// the given alpha cutoff.
+    pub fn with_correction(self, correction: Correction, alpha_cutoff: Alpha) -> Self {
+        Self {
+            correction,
+            alpha_cutoff,
+            ..self
+        }
+    }
+
+    /// Roll back once the failing optional metrics hold at least this
+    /// fraction of the optional metrics' total weight.
+    pub fn with_weight_threshold(self, weight_threshold: f64) -> Self {
+        Self {
+            weight_threshold,
+            ..self
+        }
+    }
+
+    /// Add a metric which rolls the canary back whenever it fails.
+    pub fn require(self, name: &'static str, engine: impl DecisionEngine + 'static) -> Self {
+        self.with_metric(name, Box::new(engine), true, 1.0)
+    }
+
+    /// Add a metric which only counts towards a rollback with the given weight.
+    pub fn optional(
+        self,
+        name: &'static str,
+        engine: impl DecisionEngine + 'static,
+        weight: f64,
+    ) -> Self {
+        self.with_metric(name, Box::new(engine), false, weight)
+    }
+
+    fn with_metric(
+        mut self,
+        name: &'static str,
+        engine: Box<dyn DecisionEngine>,
+        required: bool,
+        weight: f64,
+    ) -> Self {
+        self.metrics.push(Metric {
+            name,
+            engine,
+            required,
+            weight,
+            verdict: None,
+        });
+        self
+    }
+
+    /// Returns each metric's name and its verdict from the most recent
+    /// evaluation, after correction.
+    pub fn verdicts(&self) -> impl Iterator<Item = (&'static str, Option<Verdict>)> + '_ {
+        self.metrics
+            .iter()
+            .map(|metric| (metric.name, metric.verdict))
+    }
+
+    /// Returns the name of the metric that determined the most recent verdict.
+    pub fn deciding_metric(&self) -> Option<&'static str> {
+        self.deciding.map(|index| self.metrics[index].name)
+    }
+
+    /// Evaluate every metric, then overturn the rollbacks
+    /// whose p-values don't survive the correction.
+    fn evaluate_metrics(&mut self) {
+        let verdicts: Vec<_> = self
+            .metrics
+            .iter_mut()
+            .map(|metric| metric.engine.evaluate())
+            .collect();
+        // • The family is every metric with a p-value, not just the
+        //   failing ones, or the correction would be too lenient.
+        let tested: Vec<(usize, f64)> = self
+            .metrics
+            .iter()
+            .enumerate()
+            .filter_map(|(i, metric)| metric.engine.p_value().map(|p| (i, p)))
+            .collect();
+        let p_values: Vec<f64> = tested.iter().map(|(_, p)| *p).collect();
+        let rejected = self.correction.reject(&p_values, self.alpha_cutoff);
+        for (i, verdict) in verdicts.into_iter().enumerate() {
+            self.metrics[i].verdict = Some(verdict);
+        }
+        if self.correction == Correction::Unadjusted {
+            return;
+        }
+        for ((i, _), rejected) in tested.into_iter().zip(rejected) {
+            let metric = &mut self.metrics[i];
+            if metric.verdict == Some(Verdict::Rollback) && !rejected {
+                metric.verdict = Some(if metric.engine.is_sequential() {
+                    Verdict::Continue
+                } else {
+                    Verdict::Promote
+                });
+            }
+        }
+    }
+
+    /// Returns the index of the first metric matching the predicate.
+    fn find(&self, predicate: impl Fn(&Metric) -> bool) -> Option<usize> {
+        self.metrics.iter().position(predicate)
+    }
+}
+
+impl DecisionEngine for VerdictPolicy {
+    fn add_observation(&mut self, obs: Observation) {
+        self.count += 1;
+        for metric in &mut self.metrics {
+            metric.engine.add_observation(obs);
+        }
+    }
+
+    fn add_aggregate(&mut self, agg: AggregatedObservation) {
+        self.count += agg.count as usize;
+        for metric in &mut self.metrics {
+            metric.engine.add_aggregate(agg);
+        }
+    }
+
+    fn total_count(&self) -> usize {
+        self.count
+    }
+
+    fn evaluate(&mut self) -> Verdict {
+        self.evaluate_metrics();
+        let is = |verdict| move |metric: &Metric| metric.verdict == Some(verdict);
+        // • Weigh the failing optional metrics against all of them.
+        let optional_weight: f64 = self
+            .metrics
+            .iter()
+            .filter(|metric| !metric.required)
+            .map(|metric| metric.weight)
+            .sum();
+        let failing_weight: f64 = self
+            .metrics
+            .iter()
+            .filter(|metric| !metric.required && is(Verdict::Rollback)(metric))
+            .map(|metric| metric.weight)
+            .sum();
+        let too_heavy =
+            optional_weight > 0.0 && failing_weight / optional_weight >= self.weight_threshold;
+        let (verdict, deciding) = if let Some(i) =
+            self.find(|metric| metric.required && is(Verdict::Rollback)(metric))
+        {
+            (Verdict::Rollback, Some(i))
+        } else if too_heavy {
+            (Verdict::Rollback, self.find(is(Verdict::Rollback)))
+        } else if let Some(i) = self.find(|metric| metric.required && is(Verdict::Continue)(metric))
+        {
+            (Verdict::Continue, Some(i))
+        } else if let Some(i) = self.find(is(Verdict::Promote)) {
+            (Verdict::Promote, Some(i))
+        } else if let Some(i) = self.find(is(Verdict::Continue)) {
+            (Verdict::Continue, Some(i))
+        } else {
+            (Verdict::Inconclusive, None)
+        };
+        self.deciding = deciding;
+        verdict
+    }
+
+    fn is_sequential(&self) -> bool {
+        self.metrics
+            .iter()
+            .all(|metric| metric.engine.is_sequential())
+    }
+
+    fn has_min_sample(&self) -> bool {
+        self.metrics
+            .iter()
+            .filter(|metric| metric.required)
+            .all(|metric| metric.engine.has_min_sample())
+    }
+
+    /// Returns the error rates of the first metric that compares them.
+    fn error_rates(&self) -> Option<ErrorRates> {
+        self.metrics
+            .iter()
+            .find_map(|metric| metric.engine.error_rates())
+    }
+
+    /// Returns the latencies of the first metric that compares them.
+    fn latency(&self) -> Option<LatencyResult> {
+        self.metrics
+            .iter()
+            .find_map(|metric| metric.engine.latency())
+    }
+
+    /// Explains the verdict of the metric that determined it, or while no
+    /// metric has, the first required metric's.
+    fn diagnostics(&self) -> Diagnostics {
+        match self
+            .deciding
+            .or_else(|| self.find(|metric| metric.required))
+        {
+            Some(index) => self.metrics[index].engine.diagnostics(),
+            None => Diagnostics {
+                engine: "policy",
+                values: Vec::new(),
+                direction: None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{Correction, VerdictPolicy};
+    use crate::stats::fixtures::requests;
+    use crate::stats::{
+        ChiSquareEngine, DecisionEngine, Diagnostics, Group, LatencyEngine, LatencyTest,
+        Observation, Verdict, DEFAULT_ALPHA_CUTOFF,
+    };
+
+    /// This engine always reaches the same verdict with the same p-value.
+    struct FixedEngine(Verdict, Option<f64>);
+
+    impl DecisionEngine for FixedEngine {
+        fn add_observation(&mut self, _: Observation) {}
+
+        fn total_count(&self) -> usize {
+            0
+        }
+
+        fn evaluate(&mut self) -> Verdict {
+            self.0
+        }
+
+        fn p_value(&self) -> Option<f64> {
+            self.1
+        }
+
+        fn diagnostics(&self) -> Diagnostics {
+            Diagnostics {
+                engine: "fixed",
+                values: self.1.map_or_else(Vec::new, |p| vec![("p", p)]),
+                direction: None,
+            }
+        }
+    }
+
+    /// Holm's procedure rejects everything Bonferroni does, and more.
+    #[test]
+    fn holm_is_uniformly_more_powerful() {
+        let p_values = [0.04, 0.01, 0.3];
+        let bonferroni = Correction::Bonferroni.reject(&p_values, DEFAULT_ALPHA_CUTOFF);
+        assert_eq!(bonferroni, vec![false, true, false]);
+        let holm = Correction::Holm.reject(&[0.04, 0.01], DEFAULT_ALPHA_CUTOFF);
+        assert_eq!(holm, vec![true, true]);
+        // • Holm stops at the first p-value it can't reject.
+        let holm = Correction::Holm.reject(&p_values, DEFAULT_ALPHA_CUTOFF);
+        assert_eq!(holm, vec![false, true, false]);
+    }
+
+    /// A required metric rolls back alone, while optional
+    /// metrics must carry enough weight between them.
+    #[test]
+    fn required_and_weighted_metrics() {
+        let failing = || FixedEngine(Verdict::Rollback, Some(0.001));
+        let passing = || FixedEngine(Verdict::Promote, Some(0.5));
+        let mut policy = VerdictPolicy::new()
+            .require("errors", passing())
+            .optional("latency", failing(), 1.0)
+            .optional("saturation", passing(), 2.0);
+        assert_eq!(policy.evaluate(), Verdict::Promote);
+        let mut policy = VerdictPolicy::new()
+            .require("errors", passing())
+            .optional("latency", failing(), 2.0)
+            .optional("saturation", passing(), 1.0);
+        assert_eq!(policy.evaluate(), Verdict::Rollback);
+        assert_eq!(policy.deciding_metric(), Some("latency"));
+        let mut policy =
+            VerdictPolicy::new()
+                .require("errors", failing())
+                .optional("latency", passing(), 1.0);
+        assert_eq!(policy.evaluate(), Verdict::Rollback);
+        assert_eq!(policy.deciding_metric(), Some("errors"));
+    }
+
+    /// A required metric that needs more data holds the canary,
+    /// and untestable metrics don't.
+    #[test]
+    fn continue_and_inconclusive_metrics() {
+        let mut policy = VerdictPolicy::new()
+            .require("errors", FixedEngine(Verdict::Continue, None))
+            .require("latency", FixedEngine(Verdict::Promote, None));
+        assert_eq!(policy.evaluate(), Verdict::Continue);
+        let mut policy = VerdictPolicy::new()
+            .require("errors", FixedEngine(Verdict::Promote, None))
+            .require("latency", FixedEngine(Verdict::Inconclusive, None));
+        assert_eq!(policy.evaluate(), Verdict::Promote);
+        assert_eq!(VerdictPolicy::new().evaluate(), Verdict::Inconclusive);
+    }
+
+    /// A marginal failure on one of two metrics is overturned once the
+    /// alpha cutoff is shared between them.
+    #[test]
+    fn correction_overturns_marginal_rollbacks() {
+        let policy = || {
+            VerdictPolicy::new()
+                .require("errors", FixedEngine(Verdict::Rollback, Some(0.03)))
+                .require("latency", FixedEngine(Verdict::Promote, Some(0.5)))
+        };
+        assert_eq!(policy().evaluate(), Verdict::Rollback);
+        let mut bonferroni = policy().with_correction(Correction::Bonferroni, DEFAULT_ALPHA_CUTOFF);
+        assert_eq!(bonferroni.evaluate(), Verdict::Promote);
+        let verdicts: Vec<_> = bonferroni.verdicts().collect();
+        assert_eq!(
+            verdicts,
+            vec![
+                ("errors", Some(Verdict::Promote)),
+                ("latency", Some(Verdict::Promote))
+            ]
+        );
+        // • Holm rejects the smallest p-value at alpha / 2 too.
+        let mut holm = policy().with_correction(Correction::Holm, DEFAULT_ALPHA_CUTOFF);
+        assert_eq!(holm.evaluate(), Verdict::Promote);
+    }
+
+    /// Real engines see every observation, and the policy explains
+    /// its verdict with the deciding metric's diagnostics.
+    #[test]
+    fn slower_canary_fails_latency() {
+        let mut policy = VerdictPolicy::new()
+            .require("errors", ChiSquareEngine::new())
+            .require("latency", LatencyEngine::new(LatencyTest::MannWhitney))
+            .with_correction(Correction::Holm, DEFAULT_ALPHA_CUTOFF);
+        assert!(!policy.is_sequential());
+        policy.add_observations(&requests(Group::Control, 10, 100));
+        policy.add_observations(&requests(Group::Experimental, 10, 200));
+        assert_eq!(policy.total_count(), 200);
+        assert_eq!(policy.evaluate(), Verdict::Rollback);
+        assert_eq!(policy.deciding_metric(), Some("latency"));
+        assert_eq!(policy.diagnostics().engine, "latency");
+        assert!(policy.latency().is_some_and(|result| result.regressed()));
+    }
+
+    /// The policy only has enough observations once every required
+    /// metric does, however few an optional metric needs.
+    #[test]
+    fn waits_for_every_required_metric() {
+        let mut policy = VerdictPolicy::new()
+            .require("errors", ChiSquareEngine::new().with_min_group_size(150))
+            .require("latency", LatencyEngine::new(LatencyTest::MannWhitney))
+            .optional(
+                "errors",
+                ChiSquareEngine::new().with_min_group_size(1000),
+                1.0,
+            );
+        policy.add_observations(&requests(Group::Control, 10, 100));
+        policy.add_observations(&requests(Group::Experimental, 10, 100));
+        assert!(!policy.has_min_sample());
+        assert_eq!(policy.evaluate(), Verdict::Continue);
+        policy.add_observations(&requests(Group::Control, 10, 100));
+        policy.add_observations(&requests(Group::Experimental, 10, 100));
+        assert!(policy.has_min_sample());
+        assert_eq!(policy.evaluate(), Verdict::Promote);
+    }
+}
@@ -0,0 +1,124 @@
+use statrs::distribution::{ContinuousCDF, Normal};
+
+use super::{Alpha, StatsError};
+
+/// Returns the number of observations each group needs for a one-sided
+/// two-proportion z-test at the given alpha cutoff to detect that the
+/// canary's error rate exceeds the baseline by the minimum detectable effect,
+/// with the given power. This is the test the engines roll back on, so the
+/// alpha cutoff should be the one each test is actually run at, after any
+/// correction for testing several metrics at once. We use the usual normal
+/// approximation:
+///
+/// ```text
+/// n = (z(1 - α) · √(2p̄(1 - p̄)) + z(power) · √(p₁(1 - p₁) + p₂(1 - p₂)))² / (p₂ - p₁)²
+/// ```
+///
+/// where p₁ is the baseline, p₂ = p₁ + effect, and p̄ is their mean. The
+/// rates and the power must all be strictly between 0 and 1.
+pub fn sample_size(
+    baseline: f64,
+    min_detectable_effect: f64,
+    alpha: Alpha,
+    power: f64,
+) -> Result<usize, StatsError> {
+    if !(0.0 < power && power < 1.0) {
+        return Err(StatsError::Power(power));
+    }
+    let rates = Rates::new(baseline, min_detectable_effect, alpha)?;
+    let numerator = rates.critical * rates.pooled_deviation
+        + standard_normal().inverse_cdf(power) * rates.deviation;
+    Ok((numerator / min_detectable_effect).powi(2).ceil() as usize)
+}
+
+/// Returns the probability that a one-sided test at the given alpha cutoff
+/// detects the minimum detectable effect with this many observations in
+/// each group. This is the inverse of [sample_size].
+pub fn power(
+    baseline: f64,
+    min_detectable_effect: f64,
+    alpha: Alpha,
+    group_size: usize,
+) -> Result<f64, StatsError> {
+    let rates = Rates::new(baseline, min_detectable_effect, alpha)?;
+    let z = (min_detectable_effect * (group_size as f64).sqrt()
+        - rates.critical * rates.pooled_deviation)
+        / rates.deviation;
+    Ok(standard_normal().cdf(z))
+}
+
+/// The terms shared by the sample size and power calculations.
+struct Rates {
+    /// The critical value of the one-sided test: z(1 - α).
+    critical: f64,
+    /// The standard deviation of one observation under the null
+    /// hypothesis, when both groups share the pooled rate.
+    pooled_deviation: f64,
+    /// The standard deviation of the difference between one
+    /// observation of each group under the alternative hypothesis.
+    deviation: f64,
+}
+
+impl Rates {
+    fn new(baseline: f64, min_detectable_effect: f64, alpha: Alpha) -> Result<Self, StatsError> {
+        let canary = baseline + min_detectable_effect;
+        if !(0.0 < baseline && baseline < canary && canary < 1.0) {
+            return Err(StatsError::Rates {
+                baseline,
+                effect: min_detectable_effect,
+            });
+        }
+        let pooled = (baseline + canary) / 2.0;
+        Ok(Self {
+            critical: standard_normal().inverse_cdf(1.0 - alpha.get()),
+            pooled_deviation: (2.0 * pooled * (1.0 - pooled)).sqrt(),
+            deviation: (baseline * (1.0 - baseline) + canary * (1.0 - canary)).sqrt(),
+        })
+    }
+}
+
+fn standard_normal() -> Normal {
+    Normal::new(0.0, 1.0).expect("the standard normal distribution is valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{power, sample_size};
+    use crate::stats::{StatsError, DEFAULT_ALPHA_CUTOFF};
+
+    /// Detecting a rise from 10% to 15% with a one-sided test at the
+    /// usual 5% significance and 80% power takes 540 observations per group.
+    #[test]
+    fn textbook_sample_size() {
+        assert_eq!(sample_size(0.10, 0.05, DEFAULT_ALPHA_CUTOFF, 0.80), Ok(540));
+        let achieved = power(0.10, 0.05, DEFAULT_ALPHA_CUTOFF, 540).unwrap();
+        assert!((achieved - 0.80).abs() < 0.001, "power was {achieved}");
+    }
+
+    /// Rare errors and small effects need far more traffic.
+    #[test]
+    fn smaller_effects_need_more_observations() {
+        let coarse = sample_size(0.01, 0.01, DEFAULT_ALPHA_CUTOFF, 0.8).unwrap();
+        let fine = sample_size(0.01, 0.001, DEFAULT_ALPHA_CUTOFF, 0.8).unwrap();
+        assert!(coarse < 3_000, "needed {coarse}");
+        assert!(fine > 100_000, "needed {fine}");
+        assert!(power(0.01, 0.001, DEFAULT_ALPHA_CUTOFF, coarse).unwrap() < 0.2);
+    }
+
+    #[test]
+    fn invalid_parameters_are_errors() {
+        assert_eq!(
+            sample_size(0.6, 0.5, DEFAULT_ALPHA_CUTOFF, 0.8),
+            Err(StatsError::Rates {
+                baseline: 0.6,
+                effect: 0.5
+            })
+        );
+        assert_eq!(
+            sample_size(0.1, 0.1, DEFAULT_ALPHA_CUTOFF, 1.0),
+            Err(StatsError::Power(1.0))
+        );
+    }
+}
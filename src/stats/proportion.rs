@@ -0,0 +1,271 @@
+use statrs::distribution::{ContinuousCDF, Normal};
+
+use super::{
+    AggregatedObservation, Alpha, DecisionEngine, Diagnostics, ErrorRates, Group, Observation,
+    StatusCategory, Verdict, DEFAULT_ALPHA_CUTOFF, DEFAULT_MIN_GROUP_SIZE,
+};
+
+/// The [Direction] of a difference between the groups' error rates.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Direction {
+    /// The canary fails significantly more often than the control group.
+    Worse,
+    /// The canary fails significantly less often than the control group.
+    Better,
+    /// Neither group fails significantly more often than the other.
+    NoDifference,
+}
+
+impl Direction {
+    /// Returns a description of the direction, e.g. "canary worse".
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Worse => "canary worse",
+            Self::Better => "canary better",
+            Self::NoDifference => "no detectable difference",
+        }
+    }
+}
+
+/// The [ProportionEngine] runs a one-sided two-proportion z-test on the 5XX
+/// rates of the two groups. Unlike the chi-square test of homogeneity, which
+/// flags any difference in the mix of status codes, it only rejects when the
+/// canary fails *more* often than the control group, so a canary that fixed
+/// some errors is never rolled back for it.
+///
+/// Under the null hypothesis that both groups share the pooled rate p̄,
+///
+/// ```text
+/// z = (p̂ₑ - p̂꜀) / √(p̄(1 - p̄)(1/n꜀ + 1/nₑ))
+/// ```
+///
+/// is approximately standard normal, and the p-value is P(Z ≥ z).
+#[derive(Clone)]
+pub struct ProportionEngine {
+    /// The number of (errors, observations) in each group.
+    control: (u64, u64),
+    experimental: (u64, u64),
+    alpha_cutoff: Alpha,
+    /// The number of observations each group needs before we decide.
+    min_group_size: usize,
+}
+
+/// The [ProportionResult] summarizes a two-proportion z-test.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ProportionResult {
+    /// The control group's observed 5XX rate.
+    pub control_rate: f64,
+    /// The canary's observed 5XX rate.
+    pub experimental_rate: f64,
+    /// The z statistic, which is positive when the canary fails more often.
+    pub statistic: f64,
+    /// The probability of a statistic at least this large if the
+    /// groups had the same error rate: the one-sided p-value.
+    pub p_value: f64,
+    /// Which group, if either, fails significantly more often. The
+    /// canary counts as better if the opposite one-sided test rejects.
+    pub direction: Direction,
+}
+
+impl Default for ProportionEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProportionEngine {
+    pub fn new() -> Self {
+        Self {
+            control: (0, 0),
+            experimental: (0, 0),
+            alpha_cutoff: DEFAULT_ALPHA_CUTOFF,
+            min_group_size: DEFAULT_MIN_GROUP_SIZE,
+        }
+    }
+
+    /// Test for significance at the given alpha cutoff.
+    pub fn with_alpha_cutoff(self, alpha_cutoff: Alpha) -> Self {
+        Self {
+            alpha_cutoff,
+            ..self
+        }
+    }
+
+    /// Refuse to decide until each group has at least this many observations.
+    /// An empty group is never enough, whatever the minimum.
+    pub fn with_min_group_size(self, min_group_size: usize) -> Self {
+        Self {
+            min_group_size,
+            ..self
+        }
+    }
+
+    pub fn add_observation(&mut self, obs: Observation) {
+        self.add_aggregate(obs.into());
+    }
+
+    /// Record every observation an aggregate counts at once.
+    pub fn add_aggregate(&mut self, agg: AggregatedObservation) {
+        let counts = match agg.group {
+            Group::Control => &mut self.control,
+            Group::Experimental => &mut self.experimental,
+        };
+        if agg.outcome == StatusCategory::_5XX {
+            counts.0 += agg.count;
+        }
+        counts.1 += agg.count;
+    }
+
+    /// Compare the 5XX rates of the two groups. Returns None while a group
+    /// is empty, or while every observation so far had the same outcome,
+    /// since the rates then have no variance to test against.
+    pub fn test(&self) -> Option<ProportionResult> {
+        z_test(self.control, self.experimental, self.alpha_cutoff)
+    }
+
+    /// Returns the 5XX rate of each group and their difference, with
+    /// confidence intervals at the configured alpha cutoff, or None
+    /// while either group is empty.
+    pub fn error_rates(&self) -> Option<ErrorRates> {
+        ErrorRates::new(self.control, self.experimental, self.alpha_cutoff)
+    }
+
+    /// returns the total number of observations recorded across both groups.
+    pub fn total_count(&self) -> usize {
+        (self.control.1 + self.experimental.1) as usize
+    }
+
+    /// returns true once both groups have enough observations to decide.
+    pub fn has_min_sample(&self) -> bool {
+        self.control.1.min(self.experimental.1) >= self.min_group_size.max(1) as u64
+    }
+}
+
+/// Run a one-sided two-proportion z-test on the given (errors, observations)
+/// of each group, testing whether the canary's error rate is higher.
+pub fn z_test(
+    (control_errors, control_total): (u64, u64),
+    (errors, total): (u64, u64),
+    alpha: Alpha,
+) -> Option<ProportionResult> {
+    if control_total == 0 || total == 0 {
+        return None;
+    }
+    let control_rate = control_errors as f64 / control_total as f64;
+    let experimental_rate = errors as f64 / total as f64;
+    let pooled = (control_errors + errors) as f64 / (control_total + total) as f64;
+    let variance = pooled * (1.0 - pooled) * (1.0 / control_total as f64 + 1.0 / total as f64);
+    if variance <= 0.0 {
+        return None;
+    }
+    let statistic = (experimental_rate - control_rate) / variance.sqrt();
+    let normal = Normal::new(0.0, 1.0).expect("the standard normal distribution is valid");
+    let p_value = normal.sf(statistic);
+    // • Each direction is its own one-sided test at the full alpha;
+    //   at most one of them can reject.
+    let direction = if alpha.rejects(p_value) {
+        Direction::Worse
+    } else if alpha.rejects(normal.cdf(statistic)) {
+        Direction::Better
+    } else {
+        Direction::NoDifference
+    };
+    Some(ProportionResult {
+        control_rate,
+        experimental_rate,
+        statistic,
+        p_value,
+        direction,
+    })
+}
+
+impl DecisionEngine for ProportionEngine {
+    fn add_observation(&mut self, obs: Observation) {
+        ProportionEngine::add_observation(self, obs);
+    }
+
+    fn add_aggregate(&mut self, agg: AggregatedObservation) {
+        ProportionEngine::add_aggregate(self, agg);
+    }
+
+    fn total_count(&self) -> usize {
+        ProportionEngine::total_count(self)
+    }
+
+    fn evaluate(&mut self) -> Verdict {
+        match self.test() {
+            _ if !self.has_min_sample() => Verdict::Continue,
+            None => Verdict::Inconclusive,
+            Some(result) if result.direction == Direction::Worse => Verdict::Rollback,
+            Some(_) => Verdict::Promote,
+        }
+    }
+
+    fn has_min_sample(&self) -> bool {
+        ProportionEngine::has_min_sample(self)
+    }
+
+    fn p_value(&self) -> Option<f64> {
+        self.test().map(|result| result.p_value)
+    }
+
+    fn error_rates(&self) -> Option<ErrorRates> {
+        ProportionEngine::error_rates(self)
+    }
+
+    fn diagnostics(&self) -> Diagnostics {
+        let result = self.test();
+        Diagnostics {
+            engine: "z-test",
+            values: result.map_or_else(Vec::new, |result| {
+                vec![("p", result.p_value), ("z", result.statistic)]
+            }),
+            direction: result.map(|result| result.direction),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{z_test, Direction, ProportionEngine};
+    use crate::stats::fixtures::traffic;
+    use crate::stats::{DecisionEngine, Group, Verdict, DEFAULT_ALPHA_CUTOFF};
+
+    /// 40 errors in 1000 against 20 in 1000 gives z ≈ 2.62.
+    #[test]
+    fn textbook_z_test() {
+        let result = z_test((20, 1000), (40, 1000), DEFAULT_ALPHA_CUTOFF).unwrap();
+        assert!((result.statistic - 2.6216).abs() < 0.001, "{result:?}");
+        assert!((result.p_value - 0.0044).abs() < 0.0005, "{result:?}");
+        assert_eq!(result.direction, Direction::Worse);
+        assert_eq!(z_test((0, 1000), (0, 1000), DEFAULT_ALPHA_CUTOFF), None);
+        assert_eq!(z_test((1, 10), (0, 0), DEFAULT_ALPHA_CUTOFF), None);
+    }
+
+    /// A canary that fails less often is better, not a reason to roll back.
+    #[test]
+    fn only_worse_canaries_are_rolled_back() {
+        let mut engine = ProportionEngine::new();
+        engine.add_observations(&traffic(Group::Control, 960, 40));
+        engine.add_observations(&traffic(Group::Experimental, 980, 20));
+        assert_eq!(engine.test().unwrap().direction, Direction::Better);
+        assert_eq!(engine.evaluate(), Verdict::Promote);
+
+        let mut engine = ProportionEngine::new();
+        engine.add_observations(&traffic(Group::Control, 980, 20));
+        engine.add_observations(&traffic(Group::Experimental, 960, 40));
+        assert_eq!(engine.evaluate(), Verdict::Rollback);
+        assert_eq!(
+            engine.diagnostics().to_string(),
+            "z-test: p = 0.0044, z = 2.6216 (canary worse)"
+        );
+
+        let mut engine = ProportionEngine::new();
+        engine.add_observations(&traffic(Group::Control, 980, 20));
+        engine.add_observations(&traffic(Group::Experimental, 978, 22));
+        assert_eq!(engine.test().unwrap().direction, Direction::NoDifference);
+        assert_eq!(engine.evaluate(), Verdict::Promote);
+    }
+}
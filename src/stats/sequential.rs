@@ -0,0 +1,436 @@
+use std::collections::BTreeMap;
+
+use super::{
+    collapse, direction_of, AggregatedObservation, Alpha, ChiSquareEngine, ChiSquareResult,
+    Direction, ErrorRates, Observation, StatsError, StatusClass, StatusCodeSets,
+};
+
+/// The default variance of the mixing distribution over effect sizes.
+/// Effects are measured in standard deviations per observation, so this
+/// centers the test's power on differences of about a tenth of a standard
+/// deviation, e.g. an error rate moving from 1% to 2%.
+pub const DEFAULT_MIXING_VARIANCE: f64 = 0.01;
+
+/// By default, a stage passes early once we're confident that the canary's
+/// 5XX rate exceeds the control group's by less than half a percent.
+pub const DEFAULT_EQUIVALENCE_MARGIN: f64 = 0.005;
+
+/// The [SequentialEngine] runs a mixture sequential probability ratio test
+/// (mSPRT) on the 2×2 table of 5XX and other status codes, and another on
+/// each split set of codes that aren't server errors, like the one-sided
+/// tests of the [ChiSquareEngine], with the alpha cutoff split evenly
+/// between them. Unlike the [ChiSquareEngine]'s, its p-values are *always
+/// valid*: they may be checked after every batch, and the chance that one
+/// ever drops below alpha while the groups are the same stays below alpha,
+/// no matter how often we peek.
+///
+/// Following Johari et al., "Always Valid Inference" (2017), we treat the
+/// standardized differences between the groups as approximately normal.
+/// Their squared norm is the Pearson chi-square statistic `X²` with `d`
+/// degrees of freedom. Mixing the likelihood ratio over a normal prior with
+/// variance `τ²` on the effect gives
+///
+/// ```text
+/// Λₙ = (1 + r)^(-d/2) · exp(X² · r / (2(1 + r)))    where r = τ² · n꜀nₑ / (n꜀ + nₑ)
+/// ```
+///
+/// which is a martingale under the null hypothesis. The always-valid p-value
+/// is the running minimum of `1 / Λₙ`.
+///
+/// Each test keeps the direction the canary differed in when it first
+/// rejected, since the p-value never rises again: a canary found to be
+/// better isn't rolled back because its rate later drifts a hair above the
+/// control group's, without any new evidence that it's worse.
+///
+/// Rejecting that the groups are the same can only fail the canary. To pass
+/// a stage early, the engine also tests whether the canary is worse by the
+/// equivalence margin or more: the same mixture, with the statistic measuring
+/// how far the difference falls short of the margin. Once that's rejected for
+/// the 5XX rate and every tested set of codes, the canary is promoted.
+#[derive(Clone)]
+pub struct SequentialEngine {
+    /// Accumulates the contingency table.
+    engine: ChiSquareEngine,
+    mixing_variance: f64,
+    /// How much worse the canary may be and still pass early.
+    equivalence_margin: f64,
+    /// The smallest p-value of the 5XX rate seen so far.
+    p_value: f64,
+    /// The result of the most recent update.
+    last_result: Option<ChiSquareResult>,
+    /// The result of the most recent update of each tested set of codes,
+    /// whose p-value is the smallest seen so far.
+    set_results: Vec<(StatusClass, ChiSquareResult)>,
+    /// The smallest p-value seen so far against the canary being worse by
+    /// the margin or more, for the 5XX rate (None) and each tested set.
+    equivalence: BTreeMap<Option<StatusClass>, f64>,
+    /// The direction the canary differed in when the test of the 5XX
+    /// rate (None) or of each tested set first rejected.
+    rejected: BTreeMap<Option<StatusClass>, Direction>,
+}
+
+impl Default for SequentialEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SequentialEngine {
+    pub fn new() -> Self {
+        Self {
+            engine: ChiSquareEngine::new(),
+            mixing_variance: DEFAULT_MIXING_VARIANCE,
+            equivalence_margin: DEFAULT_EQUIVALENCE_MARGIN,
+            p_value: 1.0,
+            last_result: None,
+            set_results: Vec::new(),
+            equivalence: BTreeMap::new(),
+            rejected: BTreeMap::new(),
+        }
+    }
+
+    /// Bound the overall false-positive rate by the given alpha cutoff.
+    pub fn with_alpha_cutoff(self, alpha_cutoff: Alpha) -> Self {
+        Self {
+            engine: self.engine.with_alpha_cutoff(alpha_cutoff),
+            ..self
+        }
+    }
+
+    /// Don't pass a stage until each group has at least this many
+    /// observations. A failing canary may still be rejected sooner.
+    pub fn with_min_group_size(self, min_group_size: usize) -> Self {
+        Self {
+            engine: self.engine.with_min_group_size(min_group_size),
+            ..self
+        }
+    }
+
+    /// Test these sets of exact status codes as columns of their own.
+    pub fn with_code_sets(self, code_sets: StatusCodeSets) -> Self {
+        Self {
+            engine: self.engine.with_code_sets(code_sets),
+            ..self
+        }
+    }
+
+    /// Set the variance of the prior over effect sizes. Smaller values
+    /// make the test more sensitive to small differences, at the cost
+    /// of detecting large differences more slowly.
+    pub fn with_mixing_variance(self, mixing_variance: f64) -> Self {
+        Self {
+            mixing_variance,
+            ..self
+        }
+    }
+
+    /// Pass a stage early once the canary's 5XX rate, and its share of
+    /// every tested set of codes, is confidently less than this much higher
+    /// than the control group's. It must be at least 0 and less than 1.
+    pub fn with_equivalence_margin(self, equivalence_margin: f64) -> Result<Self, StatsError> {
+        if !(0.0..1.0).contains(&equivalence_margin) {
+            return Err(StatsError::Margin(equivalence_margin));
+        }
+        Ok(Self {
+            equivalence_margin,
+            ..self
+        })
+    }
+
+    pub fn add_observation(&mut self, obs: Observation) {
+        self.engine.add_observation(obs);
+    }
+
+    /// Record every observation an aggregate counts at once.
+    pub fn add_aggregate(&mut self, agg: AggregatedObservation) {
+        self.engine.add_aggregate(agg);
+    }
+
+    /// Fold the observations made so far into the always-valid p-value
+    /// and return the result. Call this after every batch: checking less
+    /// often is safe, but only makes the test slower to reject.
+    ///
+    /// Once the result is significant, it stays significant, and the
+    /// direction it was significant in is kept.
+    pub fn update(&mut self) -> ChiSquareResult {
+        let alpha = self.engine.family_alpha();
+        let result = self.fold(self.engine.error_counts(), self.p_value, alpha);
+        self.p_value = result.p_value;
+        self.last_result = Some(result);
+        self.set_results = self
+            .engine
+            .tested_sets()
+            .into_iter()
+            .map(|class| {
+                let p_value = self
+                    .set_results
+                    .iter()
+                    .find(|(tested, _)| *tested == class)
+                    .map_or(1.0, |(_, result)| result.p_value);
+                let counts = self.engine.class_counts(class);
+                (class, self.fold(counts, p_value, alpha))
+            })
+            .collect();
+        let results = std::iter::once((None, result, self.engine.error_counts())).chain(
+            self.set_results
+                .iter()
+                .map(|(class, result)| (Some(*class), *result, self.engine.class_counts(*class))),
+        );
+        for (class, result, counts) in results.collect::<Vec<_>>() {
+            if let Some(direction) = direction_of(&result, counts).filter(|_| result.significant) {
+                self.rejected.entry(class).or_insert(direction);
+            }
+        }
+        let columns = std::iter::once((None, self.engine.error_counts())).chain(
+            self.engine
+                .tested_sets()
+                .into_iter()
+                .map(|class| (Some(class), self.engine.class_counts(class))),
+        );
+        self.equivalence = columns
+            .map(|(class, counts)| {
+                let p_value = self.equivalence.get(&class).copied().unwrap_or(1.0);
+                (class, self.fold_equivalence(counts, p_value))
+            })
+            .collect();
+        result
+    }
+
+    /// Fold the evidence that the canary's share of one column exceeds the
+    /// control group's by less than the margin into the smallest p-value
+    /// seen so far. Only a canary within the margin counts as evidence.
+    fn fold_equivalence(
+        &self,
+        ((control, control_total), (count, total)): ((u64, u64), (u64, u64)),
+        p_value: f64,
+    ) -> f64 {
+        if control_total == 0 || total == 0 {
+            return p_value;
+        }
+        let (control_total, total) = (control_total as f64, total as f64);
+        let difference = count as f64 / total - control as f64 / control_total;
+        if difference >= self.equivalence_margin {
+            return p_value;
+        }
+        // • Smooth the rates for the variance, so a stage without
+        //   errors isn't mistaken for a perfectly precise estimate.
+        let smoothed = |count: u64, total: f64| (count as f64 + 1.0) / (total + 2.0);
+        let (control_rate, rate) = (smoothed(control, control_total), smoothed(count, total));
+        let variance =
+            control_rate * (1.0 - control_rate) / control_total + rate * (1.0 - rate) / total;
+        let statistic = (difference - self.equivalence_margin).powi(2) / variance;
+        let r = self.mixing_variance * control_total * total / (control_total + total);
+        let log_likelihood_ratio = -r.ln_1p() / 2.0 + statistic * r / (2.0 * (1.0 + r));
+        p_value.min((-log_likelihood_ratio).exp())
+    }
+
+    /// Fold the (count, total) of one column in each group, tested against
+    /// the rest of the table, into the smallest p-value seen so far.
+    fn fold(
+        &self,
+        counts: ((u64, u64), (u64, u64)),
+        p_value: f64,
+        alpha: Alpha,
+    ) -> ChiSquareResult {
+        // • The mixture is built on the asymptotic statistic, so
+        //   skip the exact fallbacks of the fixed-horizon test.
+        let fixed = self.engine.pearson_test(&collapse(counts));
+        let mut p_value = p_value;
+        if fixed.degrees_of_freedom > 0 {
+            let control = self.engine.control_count() as f64;
+            let experimental = self.engine.experimental_count() as f64;
+            let r = self.mixing_variance * control * experimental / (control + experimental);
+            let dof = fixed.degrees_of_freedom as f64;
+            let log_likelihood_ratio =
+                -dof / 2.0 * r.ln_1p() + fixed.statistic * r / (2.0 * (1.0 + r));
+            p_value = p_value.min((-log_likelihood_ratio).exp());
+        }
+        ChiSquareResult {
+            p_value,
+            significant: alpha.rejects(p_value),
+            ..fixed
+        }
+    }
+
+    /// Returns the result of the most recent update, if any.
+    pub fn last_result(&self) -> Option<ChiSquareResult> {
+        self.last_result
+    }
+
+    /// Returns the 5XX rate of each group and their difference,
+    /// with confidence intervals, or None while either group is empty.
+    /// The intervals are fixed-horizon: unlike the p-value, they aren't
+    /// valid at every peek.
+    pub fn error_rates(&self) -> Option<ErrorRates> {
+        self.engine.error_rates()
+    }
+
+    /// Returns which way the canary's 5XX rate differed when its test
+    /// rejected, or as of the most recent update if it hasn't, or None if
+    /// it couldn't be tested.
+    pub fn direction(&self) -> Option<Direction> {
+        let result = self.last_result?;
+        match self.rejected.get(&None) {
+            Some(direction) => Some(*direction),
+            None => direction_of(&result, self.engine.error_counts()),
+        }
+    }
+
+    /// Returns true if the test of the canary's 5XX rate, or of its share
+    /// of any tested set of codes, rejected while the canary was higher.
+    pub fn is_worse(&self) -> bool {
+        self.rejected
+            .values()
+            .any(|direction| *direction == Direction::Worse)
+    }
+
+    /// Returns true if, as of the most recent update, the canary is
+    /// confidently worse by less than the equivalence margin, in its 5XX
+    /// rate and its share of every tested set of codes.
+    pub fn is_equivalent(&self) -> bool {
+        let alpha = self.engine.family_alpha();
+        self.equivalence_p_value()
+            .is_some_and(|p_value| alpha.rejects(p_value))
+    }
+
+    /// Returns the largest always-valid p-value against the canary being
+    /// worse by the margin or more, or None before the first update.
+    pub fn equivalence_p_value(&self) -> Option<f64> {
+        self.equivalence.values().copied().reduce(f64::max)
+    }
+
+    /// Returns the smallest p-value of the most recent update, scaled
+    /// up by the number of tests, or None before the first update.
+    pub fn p_value(&self) -> Option<f64> {
+        self.last_result.map(|result| {
+            let sets = self.set_results.iter().map(|(_, result)| result.p_value);
+            self.engine
+                .family_p_value(std::iter::once(result.p_value).chain(sets))
+        })
+    }
+
+    /// returns the total number of observations recorded across both groups.
+    pub fn total_count(&self) -> usize {
+        self.engine.total_count()
+    }
+
+    /// returns true once both groups have enough observations to
+    /// pass. The test may reject before then, since it's always valid.
+    pub fn has_min_sample(&self) -> bool {
+        self.engine.has_min_sample()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::SequentialEngine;
+    use crate::stats::fixtures::{random_traffic, traffic};
+    use crate::stats::{Alpha, DecisionEngine, Direction, Group, Verdict};
+
+    /// Peeking after every batch of identical traffic
+    /// mustn't inflate the false-positive rate beyond alpha.
+    #[test]
+    fn peeking_honors_alpha() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let runs = 100;
+        let false_positives = (0..runs)
+            .filter(|_| {
+                let mut engine =
+                    SequentialEngine::new().with_alpha_cutoff(Alpha::new(0.05).unwrap());
+                (0..30).any(|_| {
+                    engine.add_observations(&random_traffic(&mut rng, (0.05, 0.05)));
+                    engine.update().significant
+                })
+            })
+            .count();
+        assert!(
+            false_positives <= runs * 5 / 100,
+            "{false_positives} of {runs} runs rejected"
+        );
+    }
+
+    /// A canary with a much higher error rate is rejected
+    /// well before a fixed horizon of thousands of observations.
+    #[test]
+    fn bad_canary_is_rejected_early() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut engine = SequentialEngine::new();
+        let batches = (1..=50)
+            .find(|_| {
+                engine.add_observations(&random_traffic(&mut rng, (0.01, 0.2)));
+                engine.update().significant
+            })
+            .expect("the canary should be rejected");
+        assert!(batches <= 5, "rejected after {batches} batches");
+    }
+
+    /// The always-valid p-value never increases, so
+    /// a rejection can't be undone by later data.
+    #[test]
+    fn p_value_is_monotone() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut engine = SequentialEngine::new();
+        let mut previous = 1.0;
+        for _ in 0..20 {
+            engine.add_observations(&random_traffic(&mut rng, (0.05, 0.07)));
+            let result = engine.update();
+            assert!(result.p_value <= previous);
+            previous = result.p_value;
+        }
+    }
+
+    /// A canary found to be better isn't rolled back when its rate later
+    /// drifts a hair above the control group's, since that's no evidence
+    /// that it's worse.
+    #[test]
+    fn better_canary_stays_better() {
+        let mut engine = SequentialEngine::new();
+        engine.add_observations(&traffic(Group::Control, 900, 100));
+        engine.add_observations(&traffic(Group::Experimental, 1000, 0));
+        assert!(engine.update().significant);
+        assert_eq!(engine.direction(), Some(Direction::Better));
+        engine.add_observations(&traffic(Group::Control, 1000, 0));
+        engine.add_observations(&traffic(Group::Experimental, 890, 110));
+        let (control, canary) = engine.engine.error_counts();
+        assert!(canary.0 * control.1 > control.0 * canary.1);
+        assert_ne!(engine.evaluate(), Verdict::Rollback);
+        assert_eq!(engine.direction(), Some(Direction::Better));
+        assert!(!engine.is_worse());
+    }
+
+    /// A canary as healthy as the control group passes once there's
+    /// enough traffic to bound its 5XX rate within the margin.
+    #[test]
+    fn healthy_canary_is_promoted_early() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut engine = SequentialEngine::new();
+        let batches = (1..=300)
+            .find(|_| {
+                engine.add_observations(&random_traffic(&mut rng, (0.01, 0.01)));
+                engine.update();
+                engine.is_equivalent()
+            })
+            .expect("the canary should be promoted");
+        assert!(!engine.is_worse());
+        assert!(batches >= 10, "promoted after {batches} batches");
+    }
+
+    /// A canary worse by well beyond the margin is never promoted,
+    /// even before it's rejected.
+    #[test]
+    fn worse_canary_is_never_promoted() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut engine = SequentialEngine::new()
+            .with_equivalence_margin(0.01)
+            .unwrap();
+        for _ in 0..100 {
+            engine.add_observations(&random_traffic(&mut rng, (0.01, 0.03)));
+            engine.update();
+            assert!(!engine.is_equivalent());
+        }
+        assert!(engine.is_worse());
+    }
+}
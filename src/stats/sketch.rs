@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+
+/// By default, every quantile estimate is within 1% of the true value.
+pub const DEFAULT_RELATIVE_ACCURACY: f64 = 0.01;
+
+/// Values at or below this are counted as zero, since their logarithms
+/// would land in an unbounded number of bins.
+const MIN_INDEXABLE_VALUE: f64 = 1e-9;
+
+/// A [DDSketch] summarizes a stream of non-negative values, like latencies,
+/// in bounded memory. Values are counted in logarithmically-sized bins, so
+/// any quantile estimate is within a fixed *relative* error of the true
+/// quantile, no matter how skewed the distribution is.
+///
+/// Sketches over the same relative accuracy can be merged exactly, e.g.
+/// to combine the sketches of several batches. See Masson et al.,
+/// "DDSketch: A Fast and Fully-Mergeable Quantile Sketch with
+/// Relative-Error Guarantees" (2019).
+#[derive(Debug, PartialEq, Clone)]
+pub struct DDSketch {
+    relative_accuracy: f64,
+    /// The base of the logarithmic bins: (1 + α) / (1 - α).
+    gamma: f64,
+    /// The number of values in each bin, by the bin's index.
+    bins: BTreeMap<i32, u64>,
+    /// The number of values too small to index.
+    zero_count: u64,
+    /// The total number of values added.
+    count: u64,
+}
+
+impl Default for DDSketch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DDSketch {
+    pub fn new() -> Self {
+        Self::with_relative_accuracy(DEFAULT_RELATIVE_ACCURACY)
+    }
+
+    /// Create a sketch whose quantile estimates are within the given
+    /// relative error. Lower values use more bins.
+    /// # Panics
+    /// This method panics unless 0 < relative_accuracy < 1.
+    pub fn with_relative_accuracy(relative_accuracy: f64) -> Self {
+        assert!(
+            0.0 < relative_accuracy && relative_accuracy < 1.0,
+            "The relative accuracy must be between 0 and 1."
+        );
+        Self {
+            relative_accuracy,
+            gamma: (1.0 + relative_accuracy) / (1.0 - relative_accuracy),
+            bins: BTreeMap::new(),
+            zero_count: 0,
+            count: 0,
+        }
+    }
+
+    /// Record a value. Negative values are counted as zero.
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+        if value <= MIN_INDEXABLE_VALUE {
+            self.zero_count += 1;
+        } else {
+            *self.bins.entry(self.index(value)).or_insert(0) += 1;
+        }
+    }
+
+    /// Add every value recorded by the other sketch to this one.
+    /// # Panics
+    /// This method panics if the sketches have different relative accuracies.
+    pub fn merge(&mut self, other: &DDSketch) {
+        assert_eq!(
+            self.relative_accuracy, other.relative_accuracy,
+            "Only sketches with the same relative accuracy can be merged."
+        );
+        for (index, count) in &other.bins {
+            *self.bins.entry(*index).or_insert(0) += count;
+        }
+        self.zero_count += other.zero_count;
+        self.count += other.count;
+    }
+
+    /// Returns the number of values recorded.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the number of bins in use, which bounds the sketch's memory.
+    pub fn bin_count(&self) -> usize {
+        self.bins.len() + usize::from(self.zero_count > 0)
+    }
+
+    /// Estimate the value at the given quantile, between 0 and 1.
+    /// Returns `None` if the sketch is empty.
+    pub fn quantile(&self, quantile: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (quantile.clamp(0.0, 1.0) * (self.count - 1) as f64).floor() as u64;
+        let mut seen = 0;
+        self.bins().find_map(|(value, count)| {
+            seen += count;
+            (seen > rank).then_some(value)
+        })
+    }
+
+    /// Returns the estimated median.
+    pub fn p50(&self) -> Option<f64> {
+        self.quantile(0.50)
+    }
+
+    /// Returns the estimated 90th percentile.
+    pub fn p90(&self) -> Option<f64> {
+        self.quantile(0.90)
+    }
+
+    /// Returns the estimated 99th percentile.
+    pub fn p99(&self) -> Option<f64> {
+        self.quantile(0.99)
+    }
+
+    /// Returns each non-empty bin's representative value and count, in
+    /// ascending order. Every value in a bin is within the sketch's
+    /// relative accuracy of its representative value.
+    pub fn bins(&self) -> impl Iterator<Item = (f64, u64)> + '_ {
+        let zero = (self.zero_count > 0).then_some((0.0, self.zero_count));
+        zero.into_iter().chain(
+            self.bins
+                .iter()
+                .map(|(index, count)| (self.value(*index), *count)),
+        )
+    }
+
+    /// Returns the index of the bin holding the value: ⌈log_γ(value)⌉.
+    fn index(&self, value: f64) -> i32 {
+        (value.ln() / self.gamma.ln()).ceil() as i32
+    }
+
+    /// Returns the representative value of the bin at the index. Bin i
+    /// holds values in (γ^(i-1), γ^i], and this value is within the
+    /// relative accuracy of both ends.
+    fn value(&self, index: i32) -> f64 {
+        2.0 * self.gamma.powi(index) / (self.gamma + 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DDSketch;
+
+    /// Returns the relative error of an estimate.
+    fn relative_error(estimate: f64, actual: f64) -> f64 {
+        (estimate - actual).abs() / actual
+    }
+
+    /// Every quantile estimate is within the relative accuracy
+    /// of the true quantile, even for a heavily skewed distribution.
+    #[test]
+    fn quantiles_have_bounded_relative_error() {
+        let mut sketch = DDSketch::with_relative_accuracy(0.01);
+        // Exponentially growing latencies from 1ms to about 22 seconds.
+        let mut values: Vec<f64> = (0..10_000).map(|i| (i as f64 / 1000.0).exp()).collect();
+        for value in &values {
+            sketch.add(*value);
+        }
+        values.sort_by(f64::total_cmp);
+        for quantile in [0.0, 0.25, 0.5, 0.9, 0.99, 1.0] {
+            let actual = values[(quantile * (values.len() - 1) as f64).floor() as usize];
+            let estimate = sketch.quantile(quantile).unwrap();
+            assert!(
+                relative_error(estimate, actual) <= 0.01,
+                "q{quantile}: estimated {estimate}, actually {actual}"
+            );
+        }
+        // Ten thousand distinct values fit in about a thousand bins.
+        assert!(sketch.bin_count() <= 1000);
+    }
+
+    /// Merging two sketches is the same as sketching both streams.
+    #[test]
+    fn merge_is_exact() {
+        let mut left = DDSketch::new();
+        let mut right = DDSketch::new();
+        let mut both = DDSketch::new();
+        for i in 0..1000 {
+            let value = f64::from(i % 97) + 0.5;
+            if i % 3 == 0 {
+                left.add(value);
+            } else {
+                right.add(value);
+            }
+            both.add(value);
+        }
+        left.merge(&right);
+        assert_eq!(left, both);
+    }
+
+    #[test]
+    fn empty_and_zero_values() {
+        let mut sketch = DDSketch::new();
+        assert_eq!(sketch.p50(), None);
+        sketch.add(0.0);
+        sketch.add(0.0);
+        sketch.add(10.0);
+        assert_eq!(sketch.p50(), Some(0.0));
+        assert!(relative_error(sketch.quantile(1.0).unwrap(), 10.0) <= 0.01);
+    }
+}
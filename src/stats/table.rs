@@ -1,6 +1,102 @@
-/// A [FixedContingencyTable] is used to model scenarios where the
-/// frequencies are fixed (i.e. known ahead of time), like fair dice.
-/// It is mostly used for testing.
-pub struct FixedContingencyTable {
-    frequency: f64,
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::chi::{ContingencyTable, EnumerableCategory};
+
+/// An [ObservedTable] counts how often each category was observed in one
+/// group. Categories that were never observed have a count of zero, so the
+/// table always spans every category of the [EnumerableCategory].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObservedTable<C>
+where
+    C: EnumerableCategory + Hash + Eq,
+{
+    counts: HashMap<C, u64>,
+    total: u64,
+}
+
+impl<C> Default for ObservedTable<C>
+where
+    C: EnumerableCategory + Hash + Eq,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> ObservedTable<C>
+where
+    C: EnumerableCategory + Hash + Eq,
+{
+    /// Construct an empty table.
+    pub fn new() -> Self {
+        Self {
+            counts: HashMap::new(),
+            total: 0,
+        }
+    }
+
+    /// Record one observation of the category.
+    pub fn increment(&mut self, category: C) {
+        self.add(category, 1);
+    }
+
+    /// Record several observations of the category at once.
+    pub fn add(&mut self, category: C, count: u64) {
+        *self.counts.entry(category).or_insert(0) += count;
+        self.total += count;
+    }
+
+    /// Returns the number of observations of this category.
+    pub fn group_count(&self, category: &C) -> u64 {
+        self.counts.get(category).copied().unwrap_or(0)
+    }
+
+    /// Returns the number of observations across every category.
+    pub fn total_count(&self) -> u64 {
+        self.total
+    }
+}
+
+impl<C> ContingencyTable<C> for ObservedTable<C>
+where
+    C: EnumerableCategory + Hash + Eq,
+{
+    fn group_count(&self, category: &C) -> u64 {
+        Self::group_count(self, category)
+    }
+
+    fn groups(&self) -> Box<dyn Iterator<Item = C>> {
+        C::groups()
+    }
+
+    fn total_count(&self) -> u64 {
+        self.total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::ObservedTable;
+    use crate::stats::{ContingencyTable, StatusCategory};
+
+    /// Unobserved categories count as zero, and the
+    /// table's total agrees with the trait's definition.
+    #[test]
+    fn counts_observations() {
+        let mut table = ObservedTable::new();
+        table.increment(StatusCategory::_2XX);
+        table.add(StatusCategory::_5XX, 3);
+        table.increment(StatusCategory::_5XX);
+        assert_eq!(table.group_count(&StatusCategory::_5XX), 4);
+        assert_eq!(table.group_count(&StatusCategory::_4XX), 0);
+        assert_eq!(table.groups().count(), 5);
+        let total = table
+            .groups()
+            .map(|category| table.group_count(&category))
+            .sum::<u64>();
+        assert_eq!(ContingencyTable::total_count(&table), total);
+    }
 }